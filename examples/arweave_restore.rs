@@ -0,0 +1,75 @@
+use diesel_migrations::{EmbeddedMigrations, embed_migrations, MigrationHarness};
+use kv_server::{
+    config::C,
+    crypto::secp256k1::Secp256k1KeyPair,
+    model,
+    model::arweave::{restore::Restorer, scan_tags},
+};
+
+pub const MIGRATIONS: EmbeddedMigrations = embed_migrations!("./migrations");
+
+const USAGE: &str = "Usage:
+  arweave_restore [--gateway URL] --head TX_ID [--head TX_ID ...]
+  arweave_restore [--gateway URL] --all
+  arweave_restore [--gateway URL] --avatar PUBKEY
+  arweave_restore [--gateway URL] --tag NAME=VALUE [--tag NAME=VALUE ...]
+
+Rebuild kv_chains and kv from documents archived on Arweave, either by
+following `previous_arweave_id` back from head transactions, or by
+scanning transactions with tags. `--all` and `--avatar` search by
+`App-Name` (and `Avatar`) tags of `arweave.tags` config. Gateway
+defaults to `arweave.url` in config.";
+
+/// Restore (or bootstrap) DB from Arweave.
+#[tokio::main]
+async fn main() {
+    env_logger::try_init().unwrap();
+
+    let tags_config = C.arweave.as_ref().map(|arweave| arweave.tags.clone()).unwrap_or_default();
+    let mut gateway = C.arweave.as_ref().map(|arweave| arweave.url.clone());
+    let mut heads: Vec<String> = vec![];
+    let mut tags: Vec<(String, String)> = vec![];
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--all" {
+            tags.extend(scan_tags(&tags_config, None));
+            continue;
+        }
+        let value = args.next().unwrap_or_else(|| panic!("{}", USAGE));
+        match arg.as_str() {
+            "--gateway" => gateway = Some(value),
+            "--head" => heads.push(value),
+            "--avatar" => {
+                let avatar = Secp256k1KeyPair::from_pubkey_hex(&value).expect("Invalid avatar");
+                tags.extend(scan_tags(&tags_config, Some(&avatar.public_key)));
+            }
+            "--tag" => {
+                let (name, tag_value) = value.split_once('=').unwrap_or_else(|| panic!("{}", USAGE));
+                tags.push((name.into(), tag_value.into()));
+            }
+            _ => panic!("{}", USAGE),
+        }
+    }
+    if heads.is_empty() == tags.is_empty() {
+        panic!("{}", USAGE);
+    }
+    let client_config = C.arweave.as_ref().map(|arweave| arweave.client.clone()).unwrap_or_default();
+    let restorer = Restorer::new(&gateway.expect("Arweave gateway is not configured"), client_config);
+
+    let mut conn = model::establish_connection();
+    conn.run_pending_migrations(MIGRATIONS).expect("Migration failed");
+    let mut reports = vec![];
+    for head in heads.iter() {
+        reports.push(restorer.restore_from_head(&mut conn, head).await.expect("Restore failed"));
+    }
+    if !tags.is_empty() {
+        reports.push(restorer.restore_by_tags(&mut conn, &tags).await.expect("Restore failed"));
+    }
+
+    for report in reports {
+        println!("restored: {}", report.restored);
+        for (transaction_id, reason) in report.skipped {
+            println!("skipped: {} ({})", transaction_id, reason);
+        }
+    }
+}
@@ -0,0 +1,15 @@
+use diesel_migrations::{EmbeddedMigrations, embed_migrations, MigrationHarness};
+use kv_server::{config::C, model, worker};
+
+pub const MIGRATIONS: EmbeddedMigrations = embed_migrations!("./migrations");
+
+/// Arweave upload worker, for deployments where HTTP server doesn't run
+/// it (e.g. Lambda, or `arweave.outbox.embedded = false`).
+#[tokio::main]
+async fn main() {
+    env_logger::try_init().unwrap();
+    model::establish_connection().run_pending_migrations(MIGRATIONS).expect("Migration failed");
+
+    let arweave = C.arweave.as_ref().expect("[arweave] is not configured");
+    worker::arweave::run(arweave.clone()).await;
+}
@@ -0,0 +1,6 @@
+-- This file should undo anything in `up.sql`
+
+DROP INDEX IF EXISTS idx_kv_payloads_expired_at;
+DROP INDEX IF EXISTS idx_kv_payloads_uuid;
+
+DROP TABLE IF EXISTS kv_payloads;
@@ -0,0 +1,13 @@
+-- Your SQL goes here
+
+CREATE TABLE kv_payloads (
+       id SERIAL PRIMARY KEY,
+       uuid UUID NOT NULL,
+       persona bytea NOT NULL,
+       created_at TIMESTAMP WITH TIME ZONE NOT NULL,
+       expired_at TIMESTAMP WITH TIME ZONE NOT NULL,
+       used_at TIMESTAMP WITH TIME ZONE
+);
+
+CREATE UNIQUE INDEX idx_kv_payloads_uuid ON kv_payloads (uuid);
+CREATE INDEX idx_kv_payloads_expired_at ON kv_payloads (expired_at);
@@ -0,0 +1,6 @@
+-- This file should undo anything in `up.sql`
+
+DROP INDEX IF EXISTS idx_kv_receipts_kv_chain_id;
+DROP INDEX IF EXISTS idx_kv_receipts_uuid;
+
+DROP TABLE IF EXISTS kv_receipts;
@@ -0,0 +1,14 @@
+-- Your SQL goes here
+
+CREATE TABLE kv_receipts (
+       id SERIAL PRIMARY KEY,
+       uuid UUID NOT NULL,
+       kv_chain_id INTEGER NOT NULL,
+       payload VARCHAR NOT NULL,
+       signature bytea NOT NULL,
+       signer bytea NOT NULL,
+       created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE UNIQUE INDEX idx_kv_receipts_uuid ON kv_receipts (uuid);
+CREATE INDEX idx_kv_receipts_kv_chain_id ON kv_receipts (kv_chain_id);
@@ -0,0 +1,12 @@
+-- This file should undo anything in `up.sql`
+
+DROP INDEX IF EXISTS idx_kv_read_nonces_expired_at;
+DROP INDEX IF EXISTS idx_kv_read_nonces_reader_nonce;
+DROP TABLE IF EXISTS kv_read_nonces;
+
+DROP INDEX IF EXISTS idx_kv_visibilities_persona;
+
+DROP TABLE IF EXISTS kv_visibilities;
+
+ALTER TABLE kv_chains
+DROP COLUMN action;
@@ -0,0 +1,25 @@
+-- Your SQL goes here
+
+ALTER TABLE kv_chains
+ADD action VARCHAR NOT NULL DEFAULT 'kv';
+
+CREATE TABLE kv_visibilities (
+       id SERIAL PRIMARY KEY,
+       persona bytea NOT NULL,
+       settings JSONB NOT NULL DEFAULT '{}'::jsonb,
+       created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP,
+       updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE UNIQUE INDEX idx_kv_visibilities_persona ON kv_visibilities (persona);
+
+-- Single-use nonces of signed read requests, kept until they expire.
+CREATE TABLE kv_read_nonces (
+       id SERIAL PRIMARY KEY,
+       reader bytea NOT NULL,
+       nonce VARCHAR NOT NULL,
+       expired_at TIMESTAMP WITH TIME ZONE NOT NULL
+);
+
+CREATE UNIQUE INDEX idx_kv_read_nonces_reader_nonce ON kv_read_nonces (reader, nonce);
+CREATE INDEX idx_kv_read_nonces_expired_at ON kv_read_nonces (expired_at);
@@ -0,0 +1,13 @@
+-- This file should undo anything in `up.sql`
+
+ALTER TABLE kv_chains DROP COLUMN IF EXISTS signer_signatures;
+
+DROP INDEX IF EXISTS idx_kv_proposal_signatures_signer;
+DROP TABLE IF EXISTS kv_proposal_signatures;
+
+DROP INDEX IF EXISTS idx_kv_proposals_persona;
+DROP INDEX IF EXISTS idx_kv_proposals_uuid;
+DROP TABLE IF EXISTS kv_proposals;
+
+DROP INDEX IF EXISTS idx_kv_multisigs_persona;
+DROP TABLE IF EXISTS kv_multisigs;
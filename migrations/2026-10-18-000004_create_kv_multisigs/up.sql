@@ -0,0 +1,42 @@
+-- Your SQL goes here
+
+CREATE TABLE kv_multisigs (
+       id SERIAL PRIMARY KEY,
+       persona bytea NOT NULL,
+       settings JSONB NOT NULL DEFAULT '{}'::jsonb,
+       created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP,
+       updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE UNIQUE INDEX idx_kv_multisigs_persona ON kv_multisigs (persona);
+
+CREATE TABLE kv_proposals (
+       id SERIAL PRIMARY KEY,
+       uuid UUID NOT NULL,
+       persona bytea NOT NULL,
+       platform VARCHAR NOT NULL,
+       identity VARCHAR NOT NULL,
+       patch JSONB NOT NULL DEFAULT '{}'::jsonb,
+       previous_id INTEGER,
+       signature_payload VARCHAR NOT NULL,
+       action VARCHAR NOT NULL DEFAULT 'kv',
+       created_at TIMESTAMP WITH TIME ZONE NOT NULL,
+       finalized_at TIMESTAMP WITH TIME ZONE,
+       kv_chain_id INTEGER
+);
+
+CREATE UNIQUE INDEX idx_kv_proposals_uuid ON kv_proposals (uuid);
+CREATE INDEX idx_kv_proposals_persona ON kv_proposals (persona);
+
+CREATE TABLE kv_proposal_signatures (
+       id SERIAL PRIMARY KEY,
+       proposal_id INTEGER NOT NULL,
+       signer bytea NOT NULL,
+       signature bytea NOT NULL,
+       created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE UNIQUE INDEX idx_kv_proposal_signatures_signer ON kv_proposal_signatures (proposal_id, signer);
+
+-- Signatures of m-of-n signers of a link: [{"signer": ..., "signature": ...}].
+ALTER TABLE kv_chains ADD COLUMN signer_signatures JSONB NOT NULL DEFAULT '[]';
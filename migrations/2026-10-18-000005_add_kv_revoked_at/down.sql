@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+
+ALTER TABLE kv DROP COLUMN IF EXISTS revoked_at;
+ALTER TABLE kv DROP COLUMN IF EXISTS proof_event_at;
@@ -0,0 +1,6 @@
+-- Your SQL goes here
+
+ALTER TABLE kv ADD revoked_at TIMESTAMP WITH TIME ZONE;
+
+-- `created_at` of last applied proof event (see `POST /v1/webhook/proof`).
+ALTER TABLE kv ADD proof_event_at TIMESTAMP WITH TIME ZONE;
@@ -0,0 +1,6 @@
+-- This file should undo anything in `up.sql`
+
+DROP INDEX IF EXISTS idx_kv_arweave_outbox_pending;
+DROP INDEX IF EXISTS idx_kv_arweave_outbox_kv_chain_id;
+
+DROP TABLE IF EXISTS kv_arweave_outbox;
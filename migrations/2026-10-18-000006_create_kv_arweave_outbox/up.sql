@@ -0,0 +1,16 @@
+-- Your SQL goes here
+
+CREATE TABLE kv_arweave_outbox (
+       id SERIAL PRIMARY KEY,
+       kv_chain_id INTEGER NOT NULL,
+       attempts INTEGER NOT NULL DEFAULT 0,
+       last_error VARCHAR,
+       next_attempt_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP,
+       created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP,
+       done_at TIMESTAMP WITH TIME ZONE,
+       -- See `model::arweave_outbox::OUTBOX_*`.
+       state VARCHAR NOT NULL DEFAULT 'pending'
+);
+
+CREATE UNIQUE INDEX idx_kv_arweave_outbox_kv_chain_id ON kv_arweave_outbox (kv_chain_id);
+CREATE INDEX idx_kv_arweave_outbox_pending ON kv_arweave_outbox (next_attempt_at) WHERE done_at IS NULL;
@@ -0,0 +1,13 @@
+-- This file should undo anything in `up.sql`
+
+DROP INDEX IF EXISTS idx_kv_chains_arweave_pending;
+
+ALTER TABLE kv
+  DROP COLUMN IF EXISTS arweave_status,
+  DROP COLUMN IF EXISTS arweave_block_height;
+
+ALTER TABLE kv_chains
+  DROP COLUMN IF EXISTS arweave_status,
+  DROP COLUMN IF EXISTS arweave_block_height,
+  DROP COLUMN IF EXISTS arweave_submitted_at,
+  DROP COLUMN IF EXISTS arweave_checked_at;
@@ -0,0 +1,21 @@
+-- Your SQL goes here
+
+ALTER TABLE kv_chains
+  ADD COLUMN arweave_status VARCHAR,
+  ADD COLUMN arweave_block_height BIGINT,
+  ADD COLUMN arweave_submitted_at TIMESTAMP WITH TIME ZONE,
+  ADD COLUMN arweave_checked_at TIMESTAMP WITH TIME ZONE;
+
+ALTER TABLE kv
+  ADD COLUMN arweave_status VARCHAR,
+  ADD COLUMN arweave_block_height BIGINT;
+
+-- Links uploaded before tracking: check them as well.
+UPDATE kv_chains
+  SET arweave_status = 'pending', arweave_submitted_at = updated_at
+  WHERE arweave_id IS NOT NULL AND arweave_id <> '';
+UPDATE kv
+  SET arweave_status = 'pending'
+  WHERE arweave_id IS NOT NULL AND arweave_id <> '';
+
+CREATE INDEX idx_kv_chains_arweave_pending ON kv_chains (id) WHERE arweave_status = 'pending';
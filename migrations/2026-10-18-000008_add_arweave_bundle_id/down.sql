@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+
+ALTER TABLE kv_chains DROP COLUMN IF EXISTS arweave_bundle_id;
@@ -0,0 +1,3 @@
+-- Your SQL goes here
+
+ALTER TABLE kv_chains ADD arweave_bundle_id VARCHAR;
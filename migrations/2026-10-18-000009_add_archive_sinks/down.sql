@@ -0,0 +1,10 @@
+-- This file should undo anything in `up.sql`
+
+DROP INDEX IF EXISTS idx_kv_arweave_outbox_kv_chain_id_sink;
+DELETE FROM kv_arweave_outbox WHERE sink <> 'arweave';
+ALTER TABLE kv_arweave_outbox DROP COLUMN IF EXISTS sink;
+CREATE UNIQUE INDEX IF NOT EXISTS idx_kv_arweave_outbox_kv_chain_id ON kv_arweave_outbox (kv_chain_id);
+
+ALTER TABLE kv_chains ADD COLUMN arweave_id VARCHAR;
+UPDATE kv_chains SET arweave_id = archive_ids->>'arweave';
+ALTER TABLE kv_chains DROP COLUMN IF EXISTS archive_ids;
@@ -0,0 +1,13 @@
+-- Your SQL goes here
+
+-- Content id of each link in every archive sink (sink name => id).
+-- Arweave one is the `arweave` entry, replacing `arweave_id`.
+ALTER TABLE kv_chains ADD COLUMN archive_ids JSONB NOT NULL DEFAULT '{}';
+UPDATE kv_chains SET archive_ids = jsonb_build_object('arweave', arweave_id)
+       WHERE arweave_id IS NOT NULL AND arweave_id <> '';
+ALTER TABLE kv_chains DROP COLUMN arweave_id;
+
+-- One outbox entry per link and sink.
+ALTER TABLE kv_arweave_outbox ADD COLUMN sink VARCHAR NOT NULL DEFAULT 'arweave';
+DROP INDEX IF EXISTS idx_kv_arweave_outbox_kv_chain_id;
+CREATE UNIQUE INDEX idx_kv_arweave_outbox_kv_chain_id_sink ON kv_arweave_outbox (kv_chain_id, sink);
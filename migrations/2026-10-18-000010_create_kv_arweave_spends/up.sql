@@ -0,0 +1,9 @@
+-- Your SQL goes here
+
+-- Fees paid for Arweave transactions, per day (UTC).
+CREATE TABLE kv_arweave_spends (
+       day DATE PRIMARY KEY,
+       winston BIGINT NOT NULL DEFAULT 0,
+       transactions INTEGER NOT NULL DEFAULT 0,
+       updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
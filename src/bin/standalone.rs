@@ -0,0 +1,37 @@
+use diesel_migrations::{EmbeddedMigrations, embed_migrations, MigrationHarness};
+use kv_server::controller::standalone;
+use kv_server::{model, model::receipt, worker};
+use kv_server::config::C;
+use std::net::SocketAddr;
+use tracing_subscriber::EnvFilter;
+
+pub const MIGRATIONS: EmbeddedMigrations = embed_migrations!("./migrations");
+
+#[tokio::main]
+async fn main() {
+    // Also collects `log` records.
+    tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")))
+        .init();
+    let config = C.clone(); // TODO
+    receipt::signer().expect("Invalid signer config");
+    model::establish_connection().run_pending_migrations(MIGRATIONS).expect("Migration failed");
+
+    if let Some(arweave) = config.arweave.as_ref().filter(|arweave| arweave.outbox.embedded) {
+        tokio::spawn(worker::arweave::run(arweave.clone()));
+    }
+
+    let addr: SocketAddr = format!("{}:{}", config.web.listen, config.web.port)
+        .parse()
+        .expect("Unable to parse web listen address");
+
+    let app = standalone::app(&config.web);
+    tracing::info!("Listening on {}", addr);
+    let server = axum::Server::bind(&addr)
+        .serve(app.into_make_service())
+        .with_graceful_shutdown(standalone::shutdown_signal());
+    if let Err(e) = server.await {
+        tracing::error!("Server error: {}", e);
+    }
+    tracing::info!("Shut down");
+}
@@ -3,6 +3,7 @@ mod env;
 use crate::error::Error;
 use config::Config;
 use serde::Deserialize;
+use std::collections::HashMap;
 
 use self::env::ENV;
 
@@ -28,6 +29,12 @@ pub struct KVConfig {
     pub web: ConfigWeb,
     pub proof_service: ConfigProofService,
     pub arweave: Option<ConfigArwave>,
+    #[serde(default)]
+    pub archive: ConfigArchive,
+    #[serde(default)]
+    pub payload: ConfigPayload,
+    pub signer: Option<ConfigSigner>,
+    pub webhook: Option<ConfigWebhook>,
 }
 
 #[derive(Clone, Deserialize, Default)]
@@ -43,17 +50,406 @@ pub struct ConfigDB {
 pub struct ConfigWeb {
     pub listen: String,
     pub port: u16,
+    /// Prefix of all routes (e.g. `/api`).  Defaults to none for the
+    /// standalone server, `/api` for Lambda (API Gateway stage).
+    #[serde(default)]
+    pub base_path: Option<String>,
+    #[serde(default)]
+    pub cors: ConfigCors,
+    /// Standalone server only: largest request body (bytes).  Defaults
+    /// to 1 MiB.
+    #[serde(default)]
+    pub body_limit: Option<usize>,
+    /// Standalone server only: seconds before a request is answered
+    /// `408`.  Defaults to 30.
+    #[serde(default)]
+    pub timeout: Option<u64>,
+}
+
+#[derive(Clone, Deserialize, Debug)]
+#[serde(default)]
+pub struct ConfigCors {
+    /// Origins allowed to call the API.  `*` allows any.
+    pub allow_origins: Vec<String>,
+    pub allow_methods: Vec<String>,
+    /// Request headers allowed in a cross-origin request.
+    pub allow_headers: Vec<String>,
+    /// Seconds a browser may cache a preflight response.
+    pub max_age: u64,
+}
+
+impl Default for ConfigCors {
+    fn default() -> Self {
+        Self {
+            allow_origins: vec!["*".into()],
+            allow_methods: vec!["GET".into(), "POST".into(), "OPTIONS".into()],
+            allow_headers: vec![
+                "Content-Type".into(),
+                "Authorization".into(),
+                "X-Amz-Date".into(),
+                "X-Api-Key".into(),
+                "X-Amz-Security-Token".into(),
+            ],
+            max_age: 86400,
+        }
+    }
 }
 
 #[derive(Clone, Deserialize, Default)]
 pub struct ConfigProofService {
     pub url: String,
+    #[serde(default)]
+    pub cache: ConfigProofCache,
+    #[serde(default)]
+    pub client: ConfigHttpClient,
+    /// Who decides if a persona can set KV of a platform-identity pair.
+    #[serde(default)]
+    pub authority: ConfigProofAuthority,
+    /// Allow-list used when `authority = "static"`.
+    #[serde(default)]
+    pub allow: Vec<ConfigStaticProof>,
+    /// Platform name => rules for this platform.  Platforms not listed
+    /// here use `authority` above (`nextid` defaults to `self`).
+    #[serde(default)]
+    pub platforms: HashMap<String, ConfigPlatform>,
+}
+
+#[derive(Clone, Deserialize, Default, Debug)]
+#[serde(default)]
+pub struct ConfigPlatform {
+    /// Overrides `proof_service.authority` for this platform.
+    pub authority: Option<ConfigProofAuthority>,
+    /// JSON-RPC endpoint used when `authority = "ethereum"`.
+    pub rpc_url: String,
+    /// If not empty, only these identities are accepted.
+    pub allow: Vec<String>,
+    /// Identities always rejected. `*` rejects the whole platform.
+    pub deny: Vec<String>,
+    /// Client to `rpc_url`.
+    pub client: ConfigHttpClient,
+}
+
+#[derive(Clone, Deserialize, Debug)]
+#[serde(default)]
+pub struct ConfigHttpClient {
+    /// Milliseconds to wait for a connection to upstream.
+    pub connect_timeout: u64,
+    /// Milliseconds to wait for a whole response (incl. body).
+    pub timeout: u64,
+    /// Extra attempts of a failed GET request. `0` disables retry.
+    pub retries: u32,
+    /// Base milliseconds to wait before a retry. Doubled on each
+    /// attempt, plus random jitter up to the same amount.
+    pub retry_backoff: u64,
+    /// Consecutive failures to open the circuit breaker. `0` disables it.
+    pub breaker_threshold: u32,
+    /// Milliseconds to fail fast after the breaker opens. Then a single
+    /// call probes upstream, closing the breaker if it succeeds.
+    pub breaker_cooldown: u64,
+}
+
+impl Default for ConfigHttpClient {
+    fn default() -> Self {
+        Self {
+            connect_timeout: 3000,
+            timeout: 10000,
+            retries: 2,
+            retry_backoff: 200,
+            breaker_threshold: 5,
+            breaker_cooldown: 30000,
+        }
+    }
+}
+
+#[derive(Clone, Deserialize, Debug, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ConfigProofAuthority {
+    /// Ask ProofService (`proof_service.url`).
+    #[default]
+    ProofService,
+    /// Use `proof_service.allow` only. For local development.
+    Static,
+    /// Identity must be the persona public key itself.
+    #[serde(rename = "self")]
+    SelfProof,
+    /// Identity must be the Ethereum address of persona, or a contract
+    /// whose `owner()` is that address.
+    Ethereum,
+}
+
+#[derive(Clone, Deserialize, Default, Debug)]
+pub struct ConfigStaticProof {
+    /// Compressed persona pubkey (`0x[0-9a-f]{66}`), or `*` for any.
+    pub persona: String,
+    /// Platform name, or `*` for any.
+    pub platform: String,
+    /// Identity, or `*` for any.
+    pub identity: String,
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+pub struct ConfigProofCache {
+    /// Max personas to keep. `0` disables the cache.
+    pub size: usize,
+    /// Seconds to keep a persona found on ProofService.
+    pub ttl: u64,
+    /// Seconds to keep a persona not found on ProofService.
+    pub negative_ttl: u64,
+}
+
+impl Default for ConfigProofCache {
+    fn default() -> Self {
+        Self {
+            size: 10000,
+            ttl: 300,
+            negative_ttl: 30,
+        }
+    }
 }
 
 #[derive(Clone, Deserialize, Default)]
 pub struct ConfigArwave {
     pub jwt: String,
     pub url: String,
+    #[serde(default)]
+    pub outbox: ConfigArweaveOutbox,
+    #[serde(default)]
+    pub tracker: ConfigArweaveTracker,
+    #[serde(default)]
+    pub tags: ConfigArweaveTags,
+    #[serde(default)]
+    pub bundle: ConfigArweaveBundle,
+    #[serde(default)]
+    pub wallet: ConfigArweaveWallet,
+    /// Client to gateway `url`.
+    #[serde(default)]
+    pub client: ConfigHttpClient,
+}
+
+#[derive(Clone, Deserialize, Debug)]
+#[serde(default)]
+pub struct ConfigArweaveOutbox {
+    /// Run upload worker inside standalone server.  Turn it off if
+    /// `arweave_worker` runs separately.
+    pub embedded: bool,
+    /// Seconds to sleep between two polls of the outbox.
+    pub interval: u64,
+    /// Max uploads per poll.
+    pub batch: i64,
+    /// Seconds a claimed upload is hidden from other workers.
+    pub lease: i64,
+    /// Seconds to wait before retrying a failed upload. Doubled on
+    /// each attempt, up to `max_backoff`.
+    pub backoff: i64,
+    pub max_backoff: i64,
+    /// Failed attempts after which an entry is given up (dead letter,
+    /// counted by `/healthz` and `/metrics`).
+    pub max_attempts: i32,
+}
+
+impl Default for ConfigArweaveOutbox {
+    fn default() -> Self {
+        Self {
+            embedded: true,
+            interval: 10,
+            batch: 10,
+            lease: 300,
+            backoff: 30,
+            max_backoff: 3600,
+            max_attempts: 20,
+        }
+    }
+}
+
+#[derive(Clone, Deserialize, Debug)]
+#[serde(default)]
+pub struct ConfigArweaveTracker {
+    /// Seconds between two status checks of a pending transaction.
+    pub interval: i64,
+    /// Max transactions checked per poll.
+    pub batch: i64,
+    /// Seconds after posting a transaction still unknown to gateway is
+    /// considered dropped, and its link is uploaded again.
+    pub drop_after: i64,
+}
+
+impl Default for ConfigArweaveTracker {
+    fn default() -> Self {
+        Self {
+            interval: 120,
+            batch: 50,
+            drop_after: 3600,
+        }
+    }
+}
+
+#[derive(Clone, Deserialize, Debug)]
+#[serde(default)]
+pub struct ConfigArweaveBundle {
+    /// Upload pending links as ANS-104 data items in one bundle
+    /// transaction, instead of one transaction per link.
+    pub enabled: bool,
+    /// Post a bundle once this many links are pending...
+    pub max_items: i64,
+    /// ...or the oldest pending one waited this many seconds.
+    pub max_wait: i64,
+    /// Max size (bytes) of data items in a bundle.
+    pub max_bytes: usize,
+}
+
+impl Default for ConfigArweaveBundle {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_items: 100,
+            max_wait: 300,
+            max_bytes: 10 * 1024 * 1024,
+        }
+    }
+}
+
+#[derive(Clone, Deserialize, Debug)]
+#[serde(default)]
+pub struct ConfigArweaveWallet {
+    /// Winston (10^-12 AR) to keep in the wallet.  Uploads whose fee
+    /// would take the balance below it are refused, and stay queued.
+    pub reserve: u64,
+    /// Seconds to cache the balance fetched from gateway, or its failure.
+    pub balance_ttl: u64,
+}
+
+impl Default for ConfigArweaveWallet {
+    fn default() -> Self {
+        Self {
+            reserve: 0,
+            balance_ttl: 60,
+        }
+    }
+}
+
+#[derive(Clone, Deserialize, Debug)]
+#[serde(default)]
+pub struct ConfigArweaveTags {
+    /// `App-Name` tag.  Restore tool searches documents by it.
+    pub app_name: String,
+    /// `App-Version` tag.
+    pub app_version: String,
+    /// Tags describing each link, among `avatar`, `platform`,
+    /// `identity`, `uuid` and `previous`.
+    pub link: Vec<String>,
+    /// Extra constant tags (name => value).
+    pub extra: HashMap<String, String>,
+}
+
+impl Default for ConfigArweaveTags {
+    fn default() -> Self {
+        Self {
+            app_name: "kv_server".into(),
+            app_version: env!("CARGO_PKG_VERSION").into(),
+            link: ["avatar", "platform", "identity", "uuid", "previous"]
+                .iter()
+                .map(|tag| tag.to_string())
+                .collect(),
+            extra: HashMap::new(),
+        }
+    }
+}
+
+/// Archive sinks other than Arweave.  Links are stored in each
+/// configured one, too.  See `model::archive`.
+#[derive(Clone, Deserialize, Default, Debug)]
+#[serde(default)]
+pub struct ConfigArchive {
+    pub ipfs: Option<ConfigArchiveIpfs>,
+    pub s3: Option<ConfigArchiveS3>,
+}
+
+#[derive(Clone, Deserialize, Debug)]
+#[serde(default)]
+pub struct ConfigArchiveIpfs {
+    /// Kubo-compatible HTTP RPC API.
+    pub api: String,
+    /// Pin added documents.
+    pub pin: bool,
+    pub client: ConfigHttpClient,
+}
+
+impl Default for ConfigArchiveIpfs {
+    fn default() -> Self {
+        Self {
+            api: "http://127.0.0.1:5001".into(),
+            pin: true,
+            client: ConfigHttpClient::default(),
+        }
+    }
+}
+
+#[derive(Clone, Deserialize, Debug)]
+pub struct ConfigArchiveS3 {
+    /// S3-compatible endpoint, e.g. `https://s3.us-east-1.amazonaws.com`.
+    /// Objects are addressed path-style: `{endpoint}/{bucket}/{key}`.
+    pub endpoint: String,
+    pub region: String,
+    pub bucket: String,
+    /// Prepended to object keys (`{prefix}{uuid}.json`).
+    #[serde(default)]
+    pub prefix: String,
+    pub access_key: String,
+    pub secret_key: String,
+    #[serde(default)]
+    pub client: ConfigHttpClient,
+}
+
+#[derive(Clone, Deserialize, Default)]
+pub struct ConfigSigner {
+    /// Secp256k1 secret key of this server (hexstring), used to sign
+    /// receipts of accepted patches.
+    pub secret_key: String,
+}
+
+#[derive(Clone, Deserialize, Debug)]
+#[serde(default)]
+pub struct ConfigWebhook {
+    /// Shared secret of `X-Signature: sha256=...` (HMAC-SHA256 of body).
+    /// Empty to disable.
+    pub hmac_secret: String,
+    /// Public key (hexstring) of `X-Signature: secp256k1=...`
+    /// (`eth_personalSign` of body).  Empty to disable.
+    pub public_key: String,
+    /// Max difference (in seconds) between `created_at` of an event
+    /// and server time.
+    pub window: i64,
+}
+
+impl Default for ConfigWebhook {
+    fn default() -> Self {
+        Self {
+            hmac_secret: "".into(),
+            public_key: "".into(),
+            window: 300,
+        }
+    }
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+pub struct ConfigPayload {
+    /// How long (in seconds) a sign payload issued by `POST
+    /// /v1/kv/payload` stays valid for uploading.
+    pub ttl: i64,
+    /// Max difference (in seconds) between `reader_created_at` of a
+    /// signed read request and server time.
+    pub read_window: i64,
+}
+
+impl Default for ConfigPayload {
+    fn default() -> Self {
+        Self {
+            ttl: 3600,
+            read_window: 300,
+        }
+    }
 }
 
 #[derive(Clone, Deserialize)]
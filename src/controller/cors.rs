@@ -0,0 +1,141 @@
+//! CORS headers of every response, and answers of preflight (`OPTIONS`)
+//! requests.  See `ConfigCors`.
+
+use http::{
+    header::{
+        ACCESS_CONTROL_ALLOW_HEADERS, ACCESS_CONTROL_ALLOW_METHODS, ACCESS_CONTROL_ALLOW_ORIGIN,
+        ACCESS_CONTROL_MAX_AGE, ORIGIN, VARY,
+    },
+    HeaderValue, StatusCode,
+};
+
+use crate::{
+    config::ConfigCors,
+    controller::{Request, Response},
+};
+
+/// Value of `Access-Control-Allow-Origin` for given `Origin`.  `None`
+/// if it is not allowed (or not a cross-origin request).
+fn allowed_origin(config: &ConfigCors, origin: Option<&HeaderValue>) -> Option<HeaderValue> {
+    if config.allow_origins.iter().any(|allowed| allowed == "*") {
+        return Some(HeaderValue::from_static("*"));
+    }
+    let origin = origin?;
+    let origin_str = origin.to_str().ok()?;
+    config
+        .allow_origins
+        .iter()
+        .any(|allowed| allowed.eq_ignore_ascii_case(origin_str))
+        .then(|| origin.clone())
+}
+
+/// `Origin` of given request.
+pub fn origin(req: &Request) -> Option<HeaderValue> {
+    req.headers().get(ORIGIN).cloned()
+}
+
+/// Add CORS headers to a response of a request from `origin`.
+pub fn apply(config: &ConfigCors, origin: Option<&HeaderValue>, resp: &mut Response) {
+    let headers = resp.headers_mut();
+    if !config.allow_origins.iter().any(|allowed| allowed == "*") {
+        // Response differs by origin: keep caches from mixing them.
+        headers.append(VARY, HeaderValue::from_static("Origin"));
+    }
+    if let Some(allowed) = allowed_origin(config, origin) {
+        headers.insert(ACCESS_CONTROL_ALLOW_ORIGIN, allowed);
+        if let Ok(methods) = config.allow_methods.join(", ").parse() {
+            headers.insert(ACCESS_CONTROL_ALLOW_METHODS, methods);
+        }
+        if let Ok(allow_headers) = config.allow_headers.join(", ").parse() {
+            headers.insert(ACCESS_CONTROL_ALLOW_HEADERS, allow_headers);
+        }
+    }
+}
+
+/// `204` answer of a preflight request.  CORS headers are added by
+/// `apply`; a disallowed origin gets none, so browser blocks it.
+pub fn preflight(config: &ConfigCors, origin: Option<&HeaderValue>) -> Response {
+    let mut resp = http::Response::builder()
+        .status(StatusCode::NO_CONTENT)
+        .body("".into())
+        .expect("failed to render response");
+    if allowed_origin(config, origin).is_some() {
+        resp.headers_mut()
+            .insert(ACCESS_CONTROL_MAX_AGE, HeaderValue::from(config.max_age));
+    }
+    apply(config, origin, &mut resp);
+    resp
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(origins: &[&str]) -> ConfigCors {
+        ConfigCors {
+            allow_origins: origins.iter().map(|origin| origin.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    fn empty() -> Response {
+        http::Response::builder().body("".into()).unwrap()
+    }
+
+    #[test]
+    fn test_any_origin() {
+        let mut resp = empty();
+        apply(&config(&["*"]), None, &mut resp);
+        assert_eq!("*", resp.headers()[ACCESS_CONTROL_ALLOW_ORIGIN]);
+        assert_eq!(
+            "GET, POST, OPTIONS",
+            resp.headers()[ACCESS_CONTROL_ALLOW_METHODS]
+        );
+        assert!(resp.headers().get(VARY).is_none());
+    }
+
+    #[test]
+    fn test_listed_origin() {
+        let config = config(&["https://app.example"]);
+        let allowed = HeaderValue::from_static("https://app.example");
+        let mut resp = empty();
+        apply(&config, Some(&allowed), &mut resp);
+        assert_eq!(
+            "https://app.example",
+            resp.headers()[ACCESS_CONTROL_ALLOW_ORIGIN]
+        );
+        assert_eq!("Origin", resp.headers()[VARY]);
+
+        let other = HeaderValue::from_static("https://evil.example");
+        let mut resp = empty();
+        apply(&config, Some(&other), &mut resp);
+        assert!(resp.headers().get(ACCESS_CONTROL_ALLOW_ORIGIN).is_none());
+        assert_eq!("Origin", resp.headers()[VARY]);
+    }
+
+    #[test]
+    fn test_preflight() {
+        let config = ConfigCors {
+            max_age: 600,
+            ..config(&["https://app.example"])
+        };
+        let resp = preflight(
+            &config,
+            Some(&HeaderValue::from_static("https://app.example")),
+        );
+        assert_eq!(StatusCode::NO_CONTENT, resp.status());
+        assert_eq!("600", resp.headers()[ACCESS_CONTROL_MAX_AGE]);
+        assert!(resp.headers()[ACCESS_CONTROL_ALLOW_HEADERS]
+            .to_str()
+            .unwrap()
+            .contains("Content-Type"));
+
+        let resp = preflight(
+            &config,
+            Some(&HeaderValue::from_static("https://evil.example")),
+        );
+        assert_eq!(StatusCode::NO_CONTENT, resp.status());
+        assert!(resp.headers().get(ACCESS_CONTROL_MAX_AGE).is_none());
+        assert!(resp.headers().get(ACCESS_CONTROL_ALLOW_ORIGIN).is_none());
+    }
+}
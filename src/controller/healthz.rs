@@ -1,6 +1,8 @@
 use crate::{
     controller::{json_response, Request, Response},
     error::Error,
+    metrics::ARWEAVE_OUTBOX_DEAD,
+    model::arweave::wallet::{WalletStatus, WALLET},
 };
 use http::StatusCode;
 use serde::Serialize;
@@ -10,9 +12,22 @@ struct HealthzResponse {
     pub hello: String,
     pub build_at: String,
     pub commit_version: String,
+    /// `None` if Arweave is not configured.
+    pub arweave_wallet: Option<WalletStatus>,
+    /// Last counted dead outbox entries.  `None` if Arweave is not
+    /// configured.
+    pub arweave_outbox_dead: Option<i64>,
 }
 
 pub async fn controller(_req: Request) -> Result<Response, Error> {
+    // Last known numbers only: health check must not depend on
+    // database or gateway being up.
+    let arweave_wallet = match WALLET.as_ref() {
+        Some(wallet) => Some(wallet.status().await),
+        None => None,
+    };
+    let arweave_outbox_dead = arweave_wallet.as_ref().map(|_| ARWEAVE_OUTBOX_DEAD.get());
+
     json_response(
         StatusCode::OK,
         &HealthzResponse {
@@ -23,6 +38,8 @@ pub async fn controller(_req: Request) -> Result<Response, Error> {
             commit_version: option_env!("KV_SERVER_CURRENT_COMMIT_ID")
                 .unwrap_or("UNKNOWN")
                 .to_string(),
+            arweave_wallet,
+            arweave_outbox_dead,
         },
     )
 }
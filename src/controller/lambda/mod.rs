@@ -1,56 +1,21 @@
-use crate::controller::{
-    error_response, healthz, payload, query, upload, Body as OurBody, Request as OurRequest,
-    Response as OurResponse, query_by_identity,
-};
-use crate::error::Error;
-use http::{Method, StatusCode};
+use crate::config::C;
+use crate::controller::{router, Body as OurBody, Request as OurRequest};
 use lambda_http::{
     Body as LambdaBody, Error as LambdaError, IntoResponse, Request as LambdaRequest,
     Response as LambdaResponse,
 };
-use log::info;
-use std::future::Future;
 
-/// Translate between `lambda_http` `Body` and our `Body`.
-async fn parse<F>(req: LambdaRequest, controller: fn(OurRequest) -> F) -> LambdaResponse<LambdaBody>
-where
-    F: Future<Output = Result<OurResponse, Error>>,
-{
+/// Base path if `web.base_path` is not set: API Gateway stage.
+pub const DEFAULT_BASE_PATH: &str = "/api";
+
+/// Main entrypoint for `lambda_http`.  Translates between
+/// `lambda_http` `Body` and our `Body`.
+pub async fn entrypoint(req: LambdaRequest) -> Result<impl IntoResponse, LambdaError> {
     let (parts, old_body) = req.into_parts();
     let body: OurBody = crate::controller::LambdaBody(old_body).into();
     let new_req: OurRequest = http::Request::from_parts(parts, body);
 
-    match controller(new_req).await {
-        Ok(resp) => {
-            let (parts, our_resp) = resp.into_parts();
-            let resp = lambda_http::Body::Text(our_resp);
-            LambdaResponse::from_parts(parts, resp)
-        }
-        Err(err) => {
-            let (parts, our_resp) = error_response(err).into_parts();
-            let resp = lambda_http::Body::Text(our_resp);
-            LambdaResponse::from_parts(parts, resp)
-        }
-    }
-}
-
-/// Main entrypoint for `lambda_http`.
-pub async fn entrypoint(req: LambdaRequest) -> Result<impl IntoResponse, LambdaError> {
-    info!(
-        "{} {}",
-        req.method().to_string(),
-        req.uri().path().to_string()
-    );
-
-    Ok(match (req.method(), req.uri().path()) {
-        (&Method::GET, "/api/healthz") => parse(req, healthz::controller).await,
-        (&Method::GET, "/api/v1/kv/by_identity") => parse(req, query_by_identity::controller).await,
-        (&Method::GET, "/api/v1/kv") => parse(req, query::controller).await,
-        (&Method::POST, "/api/v1/kv/payload") => parse(req, payload::controller).await,
-        (&Method::POST, "/api/v1/kv") => parse(req, upload::controller).await,
-        _ => LambdaResponse::builder()
-            .status(StatusCode::NOT_FOUND)
-            .body("Not Found".into())
-            .expect("Failed to render response"),
-    })
+    let base_path = C.web.base_path.as_deref().unwrap_or(DEFAULT_BASE_PATH);
+    let (parts, our_resp) = router::route(base_path, new_req).await.into_parts();
+    Ok(LambdaResponse::from_parts(parts, LambdaBody::Text(our_resp)))
 }
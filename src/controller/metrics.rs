@@ -0,0 +1,23 @@
+use crate::{
+    controller::{Request, Response},
+    error::Error,
+    metrics,
+    model::{arweave::wallet::WALLET, arweave_outbox::ArweaveOutbox, establish_connection},
+};
+use http::StatusCode;
+
+pub async fn controller(_req: Request) -> Result<Response, Error> {
+    // Wallet numbers may be updated by a separate `arweave_worker`.
+    if let Some(wallet) = WALLET.as_ref() {
+        let mut conn = establish_connection();
+        wallet.refresh_spend(&mut conn)?;
+        wallet.status().await;
+        metrics::ARWEAVE_OUTBOX_DEAD.set(ArweaveOutbox::count_dead(&mut conn)?);
+    }
+
+    http::Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "text/plain; version=0.0.4")
+        .body(metrics::render())
+        .map_err(|e| e.into())
+}
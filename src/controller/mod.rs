@@ -1,8 +1,16 @@
+pub mod cors;
 pub mod healthz;
+pub mod metrics;
 pub mod payload;
+pub mod proof;
+pub mod proposal;
 pub mod query;
 pub mod query_by_identity;
+pub mod receipt;
+pub mod router;
+pub mod standalone;
 pub mod upload;
+pub mod webhook;
 
 use http::StatusCode;
 use serde::{Deserialize, Serialize};
@@ -58,14 +66,6 @@ where
 
     http::Response::builder()
         .status(status)
-        // CORS
-        // TODO: impl this with tower middleware
-        .header("Access-Control-Allow-Origin", "*")
-        .header(
-            "Access-Control-Allow-Headers",
-            "Content-Type,Authorization,X-Amz-Date,X-Api-Key,X-Amz-Security-Token",
-        )
-        .header("Access-Control-Allow-Methods", "POST, GET, OPTIONS")
         .body(body)
         .map_err(|e| e.into())
 }
@@ -81,25 +81,40 @@ pub fn query_parse(req: Request) -> HashMap<String, String> {
         .unwrap_or_else(HashMap::new)
 }
 
-#[derive(Debug, Serialize)]
-struct ErrorResponse {
+/// Sent back in every response.  Taken from request if given,
+/// generated otherwise.
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// `X-Request-Id` of given request, or a new one.
+pub fn request_id(req: &Request) -> String {
+    req.headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|id| id.to_str().ok())
+        .map(|id| id.to_string())
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string())
+}
+
+/// See "Errors" in `docs/api.apib`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ErrorResponse {
+    pub code: String,
     pub message: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub details: Option<serde_json::Value>,
+    pub request_id: Option<String>,
 }
 
-pub fn error_response(err: Error) -> Response {
+pub fn error_response(err: Error, request_id: Option<&str>) -> Response {
     let resp = ErrorResponse {
+        code: err.code().as_str().into(),
         message: err.to_string(),
+        details: err.details(),
+        request_id: request_id.map(|id| id.to_string()),
     };
     let body: String = serde_json::to_string(&resp).unwrap();
 
     http::Response::builder()
         .status(err.http_status())
-        .header("Access-Control-Allow-Origin", "*")
-        .header(
-            "Access-Control-Allow-Headers",
-            "Content-Type,Authorization,X-Amz-Date,X-Api-Key,X-Amz-Security-Token",
-        )
-        .header("Access-Control-Allow-Methods", "POST, GET, OPTIONS")
         .body(body)
         .expect("failed to render response")
 }
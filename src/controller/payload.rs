@@ -2,8 +2,8 @@ use crate::{
     controller::{json_parse_body, json_response, Request, Response},
     crypto::secp256k1::Secp256k1KeyPair,
     error::Error,
-    model::{establish_connection, kv_chains::NewKVChain},
-    proof_client::can_set_kv,
+    model::{establish_connection, kv_chains::{self, NewKVChain}, kv_payload},
+    proof_client::authority::{ProofAuthority, AUTHORITY},
 };
 use http::StatusCode;
 use serde::{Deserialize, Serialize};
@@ -15,6 +15,9 @@ struct PayloadRequest {
     pub platform: String,
     pub identity: String,
     pub patch: serde_json::Value,
+    /// `"kv"` (default) or `"visibility"`.
+    #[serde(default)]
+    pub action: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -25,6 +28,14 @@ struct PayloadResponse {
 }
 
 pub async fn controller(req: Request) -> Result<Response, Error> {
+    controller_with(req, AUTHORITY.as_ref()).await
+}
+
+/// `controller` with given proof authority.
+pub async fn controller_with(
+    req: Request,
+    authority: &dyn ProofAuthority,
+) -> Result<Response, Error> {
     let params: PayloadRequest = json_parse_body(&req)?;
 
     let keypair = Secp256k1KeyPair::from_pubkey_hex(
@@ -33,14 +44,24 @@ pub async fn controller(req: Request) -> Result<Response, Error> {
             .or(params.persona)
             .ok_or_else(|| Error::ParamError("avatar not found".into()))?,
     )?;
-    can_set_kv(&keypair.public_key, &params.platform, &params.identity).await?;
+    authority
+        .can_set_kv(&keypair.public_key, &params.platform, &params.identity)
+        .await?;
     let mut conn = establish_connection();
     let mut new_kvchain = NewKVChain::for_persona(&mut conn, &keypair.public_key)?;
 
     new_kvchain.platform = params.platform;
     new_kvchain.identity = params.identity;
     new_kvchain.patch = params.patch;
+    new_kvchain.action = kv_chains::parse_action(params.action)?;
     let sign_payload = new_kvchain.generate_signature_payload()?;
+    kv_payload::purge_expired(&mut conn)?;
+    kv_payload::KVPayload::issue(
+        &mut conn,
+        &keypair.public_key,
+        new_kvchain.uuid,
+        new_kvchain.created_at,
+    )?;
 
     Ok(json_response(
         StatusCode::OK,
@@ -63,6 +84,7 @@ mod tests {
     use crate::{
         crypto::util::{compress_public_key, hex_public_key},
         model::kv_chains::KVChain,
+        proof_client::authority::MockAuthority,
         schema::kv_chains::dsl::*,
         util::{naive_now, vec_to_base64},
     };
@@ -85,7 +107,7 @@ mod tests {
                 signature: vec![1],
                 signature_payload: "".into(),
                 created_at: naive_now(),
-                arweave_id: None,
+                action: "kv".into(),
             })
             .get_result(conn)
             .map_err(|e| e.into())
@@ -104,13 +126,14 @@ mod tests {
             platform: "facebook".into(),
             identity: Faker.fake(),
             patch: json!({"test":"abc"}),
+            action: None,
         };
         let req: Request = ::http::Request::builder()
             .method(Method::POST)
             .uri(format!("http://localhost?test"))
             .body(serde_json::to_string(&req_body).unwrap())
             .unwrap();
-        let resp = controller(req).await.unwrap();
+        let resp = controller_with(req, &MockAuthority::allow_all()).await.unwrap();
         let body: PayloadResponse = serde_json::from_str(resp.body()).unwrap();
         assert!(body.uuid.len() > 0);
         let payload = body.sign_payload;
@@ -119,6 +142,16 @@ mod tests {
         assert!(payload.contains("facebook"));
         assert!(payload.contains(&req_body.identity));
         assert!(payload.contains(r#""previous":null"#));
+
+        // Issued payload is recorded for replay protection.
+        let mut conn = establish_connection();
+        let issued = kv_payload::KVPayload::consume(
+            &mut conn,
+            &public_key,
+            ::uuid::Uuid::parse_str(&body.uuid).unwrap(),
+            body.created_at,
+        );
+        assert!(issued.is_ok());
     }
 
     #[tokio::test]
@@ -136,15 +169,40 @@ mod tests {
             platform: "facebook".into(),
             identity: Faker.fake(),
             patch: json!({"test":"abc"}),
+            action: None,
         };
         let req: Request = ::http::Request::builder()
             .method(Method::POST)
             .uri(format!("http://localhost?test"))
             .body(serde_json::to_string(&req_body).unwrap())
             .unwrap();
-        let resp = controller(req).await.unwrap();
+        let resp = controller_with(req, &MockAuthority::allow_all()).await.unwrap();
         let body: PayloadResponse = serde_json::from_str(resp.body()).unwrap();
         let payload = body.sign_payload;
         assert!(payload.contains(&vec_to_base64(&old_kv_chain.signature)));
     }
+
+    #[tokio::test]
+    async fn test_persona_not_found() {
+        let Secp256k1KeyPair {
+            public_key,
+            secret_key: _,
+        } = Secp256k1KeyPair::generate();
+
+        let req_body = PayloadRequest {
+            persona: None,
+            avatar: Some(compress_public_key(&public_key)),
+            platform: "facebook".into(),
+            identity: Faker.fake(),
+            patch: json!({"test":"abc"}),
+            action: None,
+        };
+        let req: Request = ::http::Request::builder()
+            .method(Method::POST)
+            .uri(format!("http://localhost?test"))
+            .body(serde_json::to_string(&req_body).unwrap())
+            .unwrap();
+        let err = controller_with(req, &MockAuthority::default()).await.unwrap_err();
+        assert!(err.to_string().contains("Persona not found"));
+    }
 }
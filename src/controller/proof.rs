@@ -0,0 +1,347 @@
+use crate::{
+    controller::{json_response, query_parse, Request, Response},
+    crypto::{secp256k1::Secp256k1KeyPair, util::hex_public_key},
+    error::Error,
+    model::{
+        arweave::KVChainArweaveDocument,
+        establish_connection, kv,
+        kv_chains::{find_all_by_action, find_all_by_identity, KVChain, ACTION_KV, ACTION_MULTISIG},
+        visibility::{self, Reader},
+    },
+};
+use diesel::PgConnection;
+use http::StatusCode;
+use libsecp256k1::PublicKey;
+use serde::{Deserialize, Serialize};
+
+/// `version` of `ProofResponse`.
+pub const PROOF_VERSION: &str = "1";
+
+/// Everything needed to check a KV value offline.  See `GET
+/// /v1/kv/proof` in `docs/api.apib`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProofResponse {
+    pub version: String,
+    /// Uncompressed hexstring started with `0x`.
+    pub avatar: String,
+    pub platform: String,
+    pub identity: String,
+    /// Current content, i.e. all `links` patches merged in order.
+    pub content: serde_json::Value,
+    pub revoked: bool,
+    /// Links of this platform-identity pair (of any action), oldest first.
+    pub links: Vec<ProofLink>,
+    /// `ACTION_MULTISIG` links of this avatar (of any platform-identity
+    /// pair), oldest first: signer settings of m-of-n avatars.
+    pub multisig_links: Vec<ProofLink>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProofLink {
+    /// Links of an avatar are applied in ascending `id`.
+    pub id: i32,
+    /// See `ACTION_*` of `model::kv_chains`.
+    pub action: String,
+    /// Exactly the document archived on Arweave.
+    pub document: KVChainArweaveDocument,
+    /// Transaction (or bundled data item) of `document`.  `None` if not
+    /// uploaded yet.
+    pub arweave_id: Option<String>,
+    /// Bundle transaction carrying `arweave_id`, if bundled.
+    pub arweave_bundle_id: Option<String>,
+    pub arweave_status: Option<String>,
+    pub arweave_block_height: Option<i64>,
+}
+
+pub async fn controller(req: Request) -> Result<Response, Error> {
+    let params = query_parse(req);
+    let avatar_hex = params
+        .get("avatar")
+        .ok_or(Error::ParamMissing("avatar".into()))?;
+    let platform = params
+        .get("platform")
+        .ok_or(Error::ParamMissing("platform".into()))?;
+    let identity = params
+        .get("identity")
+        .ok_or(Error::ParamMissing("identity".into()))?;
+    let Secp256k1KeyPair {
+        public_key,
+        secret_key: _,
+    } = Secp256k1KeyPair::from_pubkey_hex(avatar_hex)?;
+    let mut conn = establish_connection();
+    let reader = visibility::reader_from_params(&mut conn, &params)?;
+    let response = proof_response(&mut conn, &public_key, platform, identity, reader.as_ref())?;
+
+    json_response(StatusCode::OK, &response)
+}
+
+/// Patches carry private namespaces in clear, so the whole proof is
+/// refused unless `reader` can read all of them, including ones removed
+/// from content since.
+pub fn proof_response(
+    conn: &mut PgConnection,
+    avatar: &PublicKey,
+    platform: &str,
+    identity: &str,
+    reader: Option<&Reader>,
+) -> Result<ProofResponse, Error> {
+    let persona_vec = avatar.serialize().to_vec();
+    let record = kv::find_all_by_identity(conn, platform, identity)?
+        .into_iter()
+        .find(|record| record.persona == persona_vec)
+        .ok_or_else(|| {
+            Error::General(
+                format!("KV not found: {} {}", platform, identity),
+                StatusCode::NOT_FOUND,
+            )
+        })?;
+
+    let mut links = find_all_by_identity(conn, platform, identity)?;
+    links.retain(|link| link.persona == persona_vec);
+    links.sort_by_key(|link| link.id);
+    let mut readable = visibility::redact_content(conn, &persona_vec, &record.content, reader)?
+        == record.content;
+    for link in links.iter().filter(|link| link.action == ACTION_KV) {
+        readable = readable
+            && visibility::redact_content(conn, &persona_vec, &link.patch, reader)? == link.patch;
+    }
+    if !readable {
+        return Err(Error::General(
+            "Proof includes private namespaces: sign the request as an allowed reader".into(),
+            StatusCode::FORBIDDEN,
+        ));
+    }
+    let multisig_links = find_all_by_action(conn, &persona_vec, ACTION_MULTISIG)?;
+
+    Ok(ProofResponse {
+        version: PROOF_VERSION.into(),
+        avatar: format!("0x{}", hex_public_key(avatar)),
+        platform: platform.into(),
+        identity: identity.into(),
+        content: record.content,
+        revoked: record.revoked_at.is_some(),
+        links: proof_links(conn, links)?,
+        multisig_links: proof_links(conn, multisig_links)?,
+    })
+}
+
+fn proof_links(conn: &mut PgConnection, links: Vec<KVChain>) -> Result<Vec<ProofLink>, Error> {
+    links
+        .into_iter()
+        .map(|link| {
+            let previous_arweave_id = link.find_previous_arweave(conn)?;
+            let arweave_id = link.arweave_id();
+            Ok(ProofLink {
+                id: link.id,
+                document: KVChainArweaveDocument::from_link(&link, previous_arweave_id),
+                action: link.action,
+                arweave_id,
+                arweave_bundle_id: link.arweave_bundle_id,
+                arweave_status: link.arweave_status,
+                arweave_block_height: link.arweave_block_height,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use diesel::{insert_into, RunQueryDsl};
+    use http::Method;
+    use serde_json::json;
+
+    use super::*;
+    use crate::{
+        model::kv_chains::{NewKVChain, SignPayload, ACTION_VISIBILITY},
+        schema::kv_chains,
+        util::{naive_now, vec_to_base64},
+    };
+
+    /// An accepted KV link, signed and applied as `controller::upload` does.
+    fn accept(
+        conn: &mut PgConnection,
+        avatar: &Secp256k1KeyPair,
+        identity: &str,
+        patch: serde_json::Value,
+        previous: Option<&KVChain>,
+    ) -> KVChain {
+        accept_action(conn, avatar, identity, ACTION_KV, patch, previous)
+    }
+
+    fn accept_action(
+        conn: &mut PgConnection,
+        avatar: &Secp256k1KeyPair,
+        identity: &str,
+        action: &str,
+        patch: serde_json::Value,
+        previous: Option<&KVChain>,
+    ) -> KVChain {
+        let created_at = naive_now();
+        let payload = SignPayload {
+            version: "1".into(),
+            uuid: ::uuid::Uuid::new_v4(),
+            avatar: hex_public_key(&avatar.public_key),
+            platform: "twitter".into(),
+            identity: identity.into(),
+            patch: patch.clone(),
+            created_at: created_at.timestamp(),
+            previous: previous.map(|link| vec_to_base64(&link.signature)),
+            action: (action != ACTION_KV).then(|| action.to_string()),
+        };
+        let signature_payload = serde_json::to_string(&payload).unwrap();
+        let link: KVChain = insert_into(kv_chains::table)
+            .values(&NewKVChain {
+                uuid: payload.uuid,
+                persona: avatar.public_key.serialize().to_vec(),
+                platform: "twitter".into(),
+                identity: identity.into(),
+                patch,
+                previous_id: previous.map(|link| link.id),
+                signature: avatar.personal_sign(&signature_payload).unwrap(),
+                signature_payload,
+                created_at,
+                action: action.into(),
+            })
+            .get_result(conn)
+            .unwrap();
+        link.perform_patch(conn).unwrap();
+        link
+    }
+
+    fn request(avatar: &Secp256k1KeyPair, identity: &str) -> Request {
+        ::http::Request::builder()
+            .method(Method::GET)
+            .uri(format!(
+                "http://localhost/v1/kv/proof?avatar=0x{}&platform=twitter&identity={}",
+                hex_public_key(&avatar.public_key),
+                identity
+            ))
+            .body("".into())
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_proof() {
+        let mut conn = establish_connection();
+        let avatar = Secp256k1KeyPair::generate();
+        let identity = ::uuid::Uuid::new_v4().to_string();
+        let first = accept(&mut conn, &avatar, &identity, json!({"a": 1, "b": 2}), None);
+        first.insert_arweave_id(&mut conn, Some("tx-1".into())).unwrap();
+        let second = accept(&mut conn, &avatar, &identity, json!({"a": null}), Some(&first));
+
+        let resp = controller(request(&avatar, &identity)).await.unwrap();
+        let body: ProofResponse = serde_json::from_str(resp.body()).unwrap();
+        assert_eq!(PROOF_VERSION, body.version);
+        assert_eq!(json!({"b": 2}), body.content);
+        assert_eq!(2, body.links.len());
+        assert_eq!(first.uuid, body.links[0].document.uuid);
+        assert_eq!(Some("tx-1".to_string()), body.links[0].arweave_id);
+        assert_eq!(None, body.links[1].arweave_id);
+        assert_eq!(Some("tx-1".to_string()), body.links[1].document.previous_arweave_id);
+        assert_eq!(second.signature, body.links[1].document.signature);
+
+        // Verifiable offline: signatures recover the avatar, patches
+        // merge into content.
+        let mut content = json!({});
+        for link in body.links.iter() {
+            let recovered = Secp256k1KeyPair::recover_from_personal_signature(
+                &link.document.signature,
+                &link.document.signature_payload,
+            )
+            .unwrap();
+            assert_eq!(body.avatar, format!("0x{}", hex_public_key(&recovered)));
+            if link.action == ACTION_KV {
+                json_patch::merge(&mut content, &link.document.patch);
+            }
+        }
+        assert_eq!(body.content, content);
+        assert!(body.multisig_links.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_settings_links() {
+        let mut conn = establish_connection();
+        let avatar = Secp256k1KeyPair::generate();
+        let signer = Secp256k1KeyPair::generate();
+        let identity = ::uuid::Uuid::new_v4().to_string();
+        let first = accept(&mut conn, &avatar, &identity, json!({"a": 1}), None);
+        let visibility_link = accept_action(
+            &mut conn,
+            &avatar,
+            &identity,
+            ACTION_VISIBILITY,
+            json!({"a": {"visibility": "public"}}),
+            Some(&first),
+        );
+        let multisig_link = accept_action(
+            &mut conn,
+            &avatar,
+            "elsewhere",
+            ACTION_MULTISIG,
+            json!({"threshold": 1, "signers": [hex_public_key(&signer.public_key)]}),
+            Some(&visibility_link),
+        );
+
+        let body = proof_response(&mut conn, &avatar.public_key, "twitter", &identity, None).unwrap();
+        assert_eq!(
+            vec![ACTION_KV, ACTION_VISIBILITY],
+            body.links.iter().map(|link| link.action.as_str()).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            Some(vec_to_base64(&first.signature)),
+            serde_json::from_str::<SignPayload>(&body.links[1].document.signature_payload)
+                .unwrap()
+                .previous
+        );
+        assert_eq!(1, body.multisig_links.len());
+        assert_eq!(multisig_link.id, body.multisig_links[0].id);
+        assert_eq!(json!({"a": 1}), body.content);
+    }
+
+    #[tokio::test]
+    async fn test_not_found() {
+        let avatar = Secp256k1KeyPair::generate();
+        let err = controller(request(&avatar, "nobody")).await.unwrap_err();
+        assert_eq!(StatusCode::NOT_FOUND, err.http_status());
+    }
+
+    #[tokio::test]
+    async fn test_private_namespace() {
+        let mut conn = establish_connection();
+        let avatar = Secp256k1KeyPair::generate();
+        let identity = ::uuid::Uuid::new_v4().to_string();
+        accept(&mut conn, &avatar, &identity, json!({"private": 1}), None);
+        visibility::find_or_create(&mut conn, &avatar.public_key)
+            .unwrap()
+            .patch(&mut conn, &json!({"private": {"visibility": "private", "allow": []}}))
+            .unwrap();
+
+        let err = controller(request(&avatar, &identity)).await.unwrap_err();
+        assert_eq!(StatusCode::FORBIDDEN, err.http_status());
+    }
+
+    #[tokio::test]
+    async fn test_deleted_private_namespace() {
+        let mut conn = establish_connection();
+        let avatar = Secp256k1KeyPair::generate();
+        let identity = ::uuid::Uuid::new_v4().to_string();
+        let first = accept(&mut conn, &avatar, &identity, json!({"private": 1, "public": 2}), None);
+        accept(&mut conn, &avatar, &identity, json!({"private": null}), Some(&first));
+        visibility::find_or_create(&mut conn, &avatar.public_key)
+            .unwrap()
+            .patch(&mut conn, &json!({"private": {"visibility": "private", "allow": []}}))
+            .unwrap();
+
+        // Not in content any more, but still in the first patch.
+        let err = proof_response(&mut conn, &avatar.public_key, "twitter", &identity, None).unwrap_err();
+        assert_eq!(StatusCode::FORBIDDEN, err.http_status());
+        assert!(proof_response(
+            &mut conn,
+            &avatar.public_key,
+            "twitter",
+            &identity,
+            Some(&Reader::owner(&avatar.public_key)),
+        )
+        .is_ok());
+    }
+}
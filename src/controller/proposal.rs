@@ -0,0 +1,228 @@
+use crate::{
+    controller::{json_parse_body, json_response, query_parse, upload, Request, Response},
+    error::Error,
+    model::{
+        arweave_outbox::ArweaveOutbox,
+        establish_connection,
+        multisig::{self, Multisig, Proposal},
+        receipt::Receipt,
+    },
+    util::base64_to_vec,
+};
+use diesel::{Connection, PgConnection};
+use http::StatusCode;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct SignatureRequest {
+    pub uuid: String,
+    /// Base64 of signature over `sign_payload` of this proposal.
+    pub signature: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProposalResponse {
+    pub uuid: String,
+    /// Avatar public key (uncompressed hexstring started with `0x`).
+    pub avatar: String,
+    pub sign_payload: String,
+    pub threshold: usize,
+    /// Signers who have signed (compressed hexstring started with `0x`).
+    pub signed: Vec<String>,
+    pub finalized: bool,
+}
+
+fn proposal_response(
+    conn: &mut PgConnection,
+    proposal: &Proposal,
+    multisig: &Multisig,
+) -> Result<ProposalResponse, Error> {
+    Ok(ProposalResponse {
+        uuid: proposal.uuid.to_string(),
+        avatar: format!("0x{}", hex::encode(&proposal.persona)),
+        sign_payload: proposal.signature_payload.clone(),
+        threshold: multisig.setting()?.threshold,
+        signed: proposal
+            .signatures(conn)?
+            .into_iter()
+            .map(|sig| format!("0x{}", hex::encode(sig.signer)))
+            .collect(),
+        finalized: proposal.finalized_at.is_some(),
+    })
+}
+
+fn find_proposal(conn: &mut PgConnection, uuid_str: &str) -> Result<(Proposal, Multisig), Error> {
+    let uuid = ::uuid::Uuid::parse_str(uuid_str)?;
+    let proposal = Proposal::find_by_uuid(conn, uuid)?.ok_or_else(|| {
+        Error::General(
+            format!("Proposal not found: {}", uuid),
+            StatusCode::NOT_FOUND,
+        )
+    })?;
+    let multisig = multisig::find_by_persona(conn, &proposal.persona)?.ok_or_else(|| {
+        Error::General(
+            format!("Avatar is no longer multisig: {}", uuid),
+            StatusCode::CONFLICT,
+        )
+    })?;
+    Ok((proposal, multisig))
+}
+
+/// Record a signature into proposal. Once enough signatures are
+/// collected, the link is applied and response is the same as `POST
+/// /v1/kv` (`201`), otherwise current status of proposal (`202`).
+pub(crate) async fn sign_proposal(
+    mut conn: PgConnection,
+    proposal: Proposal,
+    multisig: Multisig,
+    sig: Vec<u8>,
+) -> Result<Response, Error> {
+    let finalized = conn.transaction::<_, Error, _>(|conn| {
+        proposal.add_signature(conn, &multisig, &sig)?;
+        match proposal.try_finalize(conn, &multisig)? {
+            Some(kv_link) => {
+                let receipt = Receipt::issue(conn, &kv_link)?;
+                ArweaveOutbox::enqueue(conn, &kv_link)?;
+                Ok(Some((kv_link, receipt)))
+            }
+            None => Ok(None),
+        }
+    })?;
+
+    match finalized {
+        Some((kv_link, receipt)) => upload::respond_created(&mut conn, kv_link, receipt),
+        None => json_response(
+            StatusCode::ACCEPTED,
+            &proposal_response(&mut conn, &proposal, &multisig)?,
+        ),
+    }
+}
+
+/// `GET /v1/kv/proposal`
+pub async fn controller(req: Request) -> Result<Response, Error> {
+    let params = query_parse(req);
+    let uuid = params
+        .get("uuid")
+        .ok_or(Error::ParamMissing("uuid".into()))?;
+
+    let mut conn = establish_connection();
+    let (proposal, multisig) = find_proposal(&mut conn, uuid)?;
+
+    json_response(
+        StatusCode::OK,
+        &proposal_response(&mut conn, &proposal, &multisig)?,
+    )
+}
+
+/// `POST /v1/kv/proposal/signature`
+pub async fn sign_controller(req: Request) -> Result<Response, Error> {
+    let params: SignatureRequest = json_parse_body(&req)?;
+    let sig = base64_to_vec(&params.signature)?;
+
+    let mut conn = establish_connection();
+    let (proposal, multisig) = find_proposal(&mut conn, &params.uuid)?;
+
+    sign_proposal(conn, proposal, multisig, sig).await
+}
+
+#[cfg(test)]
+mod tests {
+    use diesel::{QueryDsl, RunQueryDsl};
+    use fake::{Fake, Faker};
+    use http::Method;
+    use serde_json::json;
+
+    use super::*;
+    use crate::{
+        controller::query::query_response,
+        crypto::{secp256k1::Secp256k1KeyPair, util::compress_public_key},
+        model::kv_chains::{KVChain, NewKVChain, ACTION_KV},
+        schema::kv_chains,
+        util::{naive_now, vec_to_base64},
+    };
+
+    fn sign_req(uuid: &::uuid::Uuid, sig: &Vec<u8>) -> Request {
+        ::http::Request::builder()
+            .method(Method::POST)
+            .uri("http://localhost/test")
+            .body(
+                serde_json::to_string(&SignatureRequest {
+                    uuid: uuid.to_string(),
+                    signature: vec_to_base64(sig),
+                })
+                .unwrap(),
+            )
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_two_of_three() {
+        let mut conn = establish_connection();
+        let dao = Secp256k1KeyPair::generate();
+        let signers: Vec<Secp256k1KeyPair> = (0..3).map(|_| Secp256k1KeyPair::generate()).collect();
+        multisig::find_or_create(&mut conn, &dao.public_key)
+            .unwrap()
+            .patch(
+                &mut conn,
+                &json!({
+                    "threshold": 2,
+                    "signers": signers.iter().map(|s| compress_public_key(&s.public_key)).collect::<Vec<String>>(),
+                }),
+            )
+            .unwrap();
+        let multisig = multisig::find_by_persona(&mut conn, &dao.public_key.serialize().to_vec())
+            .unwrap()
+            .unwrap();
+
+        let mut new_kv = NewKVChain::for_persona(&mut conn, &dao.public_key).unwrap();
+        new_kv.platform = Faker.fake();
+        new_kv.identity = Faker.fake();
+        new_kv.patch = json!({"name": "DAO"});
+        new_kv.action = ACTION_KV.into();
+        new_kv.created_at = naive_now();
+        new_kv.signature_payload =
+            serde_json::to_string(&new_kv.generate_signature_payload().unwrap()).unwrap();
+        let proposal = Proposal::create(&mut conn, &new_kv).unwrap();
+
+        // Not a signer
+        let outsider_sig = Secp256k1KeyPair::generate()
+            .personal_sign(&new_kv.signature_payload)
+            .unwrap();
+        assert!(sign_controller(sign_req(&proposal.uuid, &outsider_sig)).await.is_err());
+
+        // 1 of 2, twice
+        let sig_0 = signers[0].personal_sign(&new_kv.signature_payload).unwrap();
+        for _ in 0..2 {
+            let resp = sign_controller(sign_req(&proposal.uuid, &sig_0)).await.unwrap();
+            assert_eq!(resp.status(), StatusCode::ACCEPTED);
+            let body: ProposalResponse = serde_json::from_str(resp.body()).unwrap();
+            assert_eq!(1, body.signed.len());
+            assert!(!body.finalized);
+        }
+        assert_eq!(0, query_response(&mut conn, &dao.public_key, None).unwrap().proofs.len());
+
+        // 2 of 2
+        let sig_2 = signers[2].personal_sign(&new_kv.signature_payload).unwrap();
+        let resp = sign_controller(sign_req(&proposal.uuid, &sig_2)).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::CREATED);
+        let applied = query_response(&mut conn, &dao.public_key, None).unwrap();
+        assert_eq!(json!({"name": "DAO"}), applied.proofs.first().unwrap().content);
+
+        let (finalized, _) = find_proposal(&mut conn, &proposal.uuid.to_string()).unwrap();
+        assert!(finalized.finalized_at.is_some());
+        assert!(finalized.kv_chain_id.is_some());
+        let link: KVChain = kv_chains::table
+            .find(finalized.kv_chain_id.unwrap())
+            .first(&mut conn)
+            .unwrap();
+        let signer_signatures = link.signer_signatures().unwrap();
+        assert_eq!(2, signer_signatures.len());
+        assert_eq!(signer_signatures[0].signature, link.signature);
+        multisig
+            .setting()
+            .unwrap()
+            .verify_signatures(&signer_signatures, &link.signature_payload)
+            .unwrap();
+        assert!(finalized.add_signature(&mut conn, &multisig, &sig_0).is_err());
+    }
+}
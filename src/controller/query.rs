@@ -1,8 +1,11 @@
 use crate::{
     controller::{query_parse, Request, Response},
-    crypto::{secp256k1::Secp256k1KeyPair, util::hex_public_key},
+    crypto::{envelope, secp256k1::Secp256k1KeyPair, util::hex_public_key},
     error::Error,
-    model::{establish_connection, kv},
+    model::{
+        establish_connection, kv,
+        visibility::{self, Reader},
+    },
 };
 use diesel::PgConnection;
 use http::StatusCode;
@@ -24,6 +27,15 @@ pub struct QueryResponseSingleProof {
     pub identity: String,
     pub content: serde_json::Value,
     pub arweave_id: Option<String>,
+    /// `pending`, `confirmed` or `dropped`.  `None` if not uploaded.
+    #[serde(default)]
+    pub arweave_status: Option<String>,
+    /// Block which `arweave_id` is mined in, once confirmed.
+    #[serde(default)]
+    pub arweave_block_height: Option<i64>,
+    /// Proof of this platform-identity pair is revoked.
+    #[serde(default)]
+    pub revoked: bool,
 }
 
 pub async fn controller(req: Request) -> Result<Response, Error> {
@@ -37,15 +49,39 @@ pub async fn controller(req: Request) -> Result<Response, Error> {
         secret_key: _,
     } = Secp256k1KeyPair::from_pubkey_hex(avatar_hex)?;
 
+    let recipient = params
+        .get("recipient")
+        .map(|recipient_hex| Secp256k1KeyPair::from_pubkey_hex(recipient_hex))
+        .transpose()?;
     let mut conn = establish_connection();
-    let response = query_response(&mut conn, &public_key)?;
+    let reader = visibility::reader_from_params(&mut conn, &params)?;
+    let mut response = query_response(&mut conn, &public_key, reader.as_ref())?;
+    if let Some(Secp256k1KeyPair { public_key: recipient_key, secret_key: _ }) = recipient {
+        response.retain_envelopes_for(&recipient_key);
+    }
 
     json_response(StatusCode::OK, &response)
 }
 
+impl QueryResponse {
+    /// Keep only encrypted envelopes addressed to given recipient.
+    /// Proofs which have none of them are removed.
+    pub fn retain_envelopes_for(&mut self, recipient: &PublicKey) {
+        self.proofs = std::mem::take(&mut self.proofs)
+            .into_iter()
+            .filter_map(|proof| {
+                envelope::filter_for_recipient(&proof.content, recipient)
+                    .map(|content| QueryResponseSingleProof { content, ..proof })
+            })
+            .collect();
+    }
+}
+
+/// Private namespaces are redacted unless `reader` is allowed to read them.
 pub fn query_response(
     conn: &mut PgConnection,
     persona_public_key: &PublicKey,
+    reader: Option<&Reader>,
 ) -> Result<QueryResponse, Error> {
     let results = kv::find_all_by_persona(conn, persona_public_key)?;
 
@@ -57,10 +93,13 @@ pub fn query_response(
     };
     for proof in results.into_iter() {
         let proof_single = QueryResponseSingleProof {
+            content: visibility::redact_content(conn, &proof.persona, &proof.content, reader)?,
             platform: proof.platform,
             identity: proof.identity,
-            content: proof.content,
             arweave_id: proof.arweave_id,
+            arweave_status: proof.arweave_status,
+            arweave_block_height: proof.arweave_block_height,
+            revoked: proof.revoked_at.is_some(),
         };
         response.proofs.push(proof_single);
     }
@@ -118,4 +157,92 @@ mod tests {
         assert_eq!("twitter", body.proofs.first().unwrap().platform);
         assert_eq!(json!({}), body.proofs.first().unwrap().content);
     }
+
+    #[tokio::test]
+    async fn test_controller_with_reader() {
+        let mut conn = establish_connection();
+        let avatar = Secp256k1KeyPair::generate();
+        let friend = Secp256k1KeyPair::generate();
+        let (created, _) =
+            kv::find_or_create(&mut conn, "twitter", &fake::Faker.fake::<String>(), &avatar.public_key).unwrap();
+        created.patch(&mut conn, &json!({"public": 1, "private": 2})).unwrap();
+        visibility::find_or_create(&mut conn, &avatar.public_key)
+            .unwrap()
+            .patch(&mut conn, &json!({"private": {
+                "visibility": "private",
+                "allow": [hex_public_key(&friend.public_key)],
+            }}))
+            .unwrap();
+
+        let uri = format!("http://localhost/test?avatar=0x{}", hex_public_key(&avatar.public_key));
+        let req: Request = ::http::Request::builder()
+            .method(Method::GET)
+            .uri(&uri)
+            .body("".into())
+            .unwrap();
+        let body: QueryResponse = serde_json::from_str(controller(req).await.unwrap().body()).unwrap();
+        assert_eq!(json!({"public": 1}), body.proofs.first().unwrap().content);
+
+        let now = crate::util::timestamp();
+        let read_payload =
+            visibility::ReadRequest::payload(&friend.public_key, &avatar.public_key, "n1", now);
+        let read_signature = friend
+            .base64_personal_sign(&serde_json::to_string(&read_payload).unwrap())
+            .unwrap();
+        let req: Request = ::http::Request::builder()
+            .method(Method::GET)
+            .uri(format!(
+                "{}&reader={}&reader_nonce={}&reader_signature={}&reader_created_at={}",
+                uri,
+                read_payload.reader,
+                read_payload.nonce,
+                url::form_urlencoded::byte_serialize(read_signature.as_bytes()).collect::<String>(),
+                now,
+            ))
+            .body("".into())
+            .unwrap();
+        let body: QueryResponse = serde_json::from_str(controller(req).await.unwrap().body()).unwrap();
+        assert_eq!(json!({"public": 1, "private": 2}), body.proofs.first().unwrap().content);
+    }
+
+    #[tokio::test]
+    async fn test_controller_with_recipient() {
+        let mut conn = establish_connection();
+        let avatar = Secp256k1KeyPair::generate();
+        let recipient = Secp256k1KeyPair::generate();
+        let envelope = json!({
+            "type": "kv_encrypted",
+            "version": "1",
+            "algorithm": envelope::ENVELOPE_ALGORITHM,
+            "nonce": "AAAAAAAAAAAAAAAA",
+            "ciphertext": "c2VjcmV0",
+            "recipients": [{
+                "public_key": format!("0x{}", hex_public_key(&recipient.public_key)),
+                "encrypted_key": crate::util::vec_to_base64(&vec![1; 113]),
+            }],
+        });
+        let (addressed, _) =
+            kv::find_or_create(&mut conn, "twitter", &fake::Faker.fake::<String>(), &avatar.public_key).unwrap();
+        addressed
+            .patch(&mut conn, &json!({"public": "value", "private": envelope.clone()}))
+            .unwrap();
+        let (not_addressed, _) =
+            kv::find_or_create(&mut conn, "github", &fake::Faker.fake::<String>(), &avatar.public_key).unwrap();
+        not_addressed.patch(&mut conn, &json!({"public": "value"})).unwrap();
+
+        let req: Request = ::http::Request::builder()
+            .method(Method::GET)
+            .uri(format!(
+                "http://localhost/test?avatar=0x{}&recipient=0x{}",
+                hex_public_key(&avatar.public_key),
+                hex_public_key(&recipient.public_key),
+            ))
+            .body("".into())
+            .unwrap();
+        let resp = controller(req).await.unwrap();
+        let body: QueryResponse = serde_json::from_str(resp.body()).unwrap();
+        assert_eq!(1, body.proofs.len());
+        assert_eq!("twitter", body.proofs.first().unwrap().platform);
+        assert_eq!(json!({ "private": envelope }), body.proofs.first().unwrap().content);
+    }
 }
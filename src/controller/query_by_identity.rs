@@ -1,7 +1,15 @@
 use crate::{
+    config::C,
     controller::{query_parse, Request, Response},
+    crypto::{secp256k1::Secp256k1KeyPair, util::hex_public_key},
     error::Error,
-    model::{establish_connection, kv::find_all_by_identity},
+    model::{
+        establish_connection,
+        kv::{find_all_by_identity, find_all_by_persona},
+        visibility::{self, Reader},
+    },
+    http_client::HttpClient,
+    proof_client::{client::PROOF_CLIENT, query_identity_with, Proof},
 };
 use diesel::PgConnection;
 use http::StatusCode;
@@ -18,6 +26,43 @@ struct QueryResponse {
 struct QueryResponseSingleAvatar {
     pub avatar: String,
     pub content: serde_json::Value,
+    /// `pending`, `confirmed` or `dropped`.  `None` if not uploaded.
+    #[serde(default)]
+    pub arweave_status: Option<String>,
+    /// Block which the Arweave transaction is mined in, once confirmed.
+    #[serde(default)]
+    pub arweave_block_height: Option<i64>,
+}
+
+/// `?expand=true`: KVs of every persona linked to given identity.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ExpandedResponse {
+    pub personas: Vec<ExpandedPersona>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ExpandedPersona {
+    pub avatar: String,
+    /// Proof linking this persona to queried platform-identity.
+    pub linked_by: Proof,
+    pub values: Vec<ExpandedValue>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ExpandedValue {
+    pub platform: String,
+    pub identity: String,
+    pub content: serde_json::Value,
+    /// `pending`, `confirmed` or `dropped`.  `None` if not uploaded.
+    #[serde(default)]
+    pub arweave_status: Option<String>,
+    /// Block which the Arweave transaction is mined in, once confirmed.
+    #[serde(default)]
+    pub arweave_block_height: Option<i64>,
+    pub revoked: bool,
+    /// Proof of this platform-identity under the persona. `None` if
+    /// ProofService doesn't know it (e.g. `nextid`).
+    pub proof: Option<Proof>,
 }
 
 pub async fn controller(req: Request) -> Result<Response, Error> {
@@ -30,24 +75,98 @@ pub async fn controller(req: Request) -> Result<Response, Error> {
         .ok_or(Error::ParamMissing("identity".into()))?;
 
     let mut conn = establish_connection();
-    let response = query_response(&mut conn, &platform, &identity)?;
+    let reader = visibility::reader_from_params(&mut conn, &params)?;
+    if params.get("expand").map(|v| v == "true").unwrap_or(false) {
+        let response = expanded_response(
+            &mut conn,
+            &PROOF_CLIENT,
+            &C.proof_service.url,
+            platform,
+            identity,
+            reader.as_ref(),
+        )
+        .await?;
+        return json_response(StatusCode::OK, &response);
+    }
+    let response = query_response(&mut conn, &platform, &identity, reader.as_ref())?;
 
     json_response(StatusCode::OK, &response)
 }
 
+/// Resolve personas of given identity on ProofService, then collect
+/// all KVs of them.  Personas with invalid proof are skipped.
+async fn expanded_response(
+    conn: &mut PgConnection,
+    client: &HttpClient,
+    proof_service_url: &str,
+    platform: &str,
+    identity: &str,
+    reader: Option<&Reader>,
+) -> Result<ExpandedResponse, Error> {
+    let found = query_identity_with(client, proof_service_url, platform, identity).await?;
+
+    let mut personas: Vec<ExpandedPersona> = vec![];
+    for proof_persona in found.ids.into_iter() {
+        let linked_by = match proof_persona
+            .proofs
+            .iter()
+            .find(|proof| proof.platform == platform && proof.identity == identity && proof.is_valid)
+        {
+            Some(proof) => proof.clone(),
+            None => continue,
+        };
+        let Secp256k1KeyPair {
+            public_key,
+            secret_key: _,
+        } = Secp256k1KeyPair::from_pubkey_hex(&proof_persona.persona)?;
+
+        let values = find_all_by_persona(conn, &public_key)?
+            .into_iter()
+            .map(|kv| {
+                Ok(ExpandedValue {
+                    content: visibility::redact_content(conn, &kv.persona, &kv.content, reader)?,
+                    proof: proof_persona
+                        .proofs
+                        .iter()
+                        .find(|proof| proof.platform == kv.platform && proof.identity == kv.identity)
+                        .cloned(),
+                    revoked: kv.revoked_at.is_some(),
+                    arweave_status: kv.arweave_status,
+                    arweave_block_height: kv.arweave_block_height,
+                    platform: kv.platform,
+                    identity: kv.identity,
+                })
+            })
+            .collect::<Result<_, Error>>()?;
+
+        personas.push(ExpandedPersona {
+            avatar: format!("0x{}", hex_public_key(&public_key)),
+            linked_by,
+            values,
+        });
+    }
+
+    Ok(ExpandedResponse { personas })
+}
+
 fn query_response(
     conn: &mut PgConnection,
     platform: &str,
     identity: &str,
+    reader: Option<&Reader>,
 ) -> Result<QueryResponse, Error> {
     let found = find_all_by_identity(conn, platform, identity)?;
     let values: Vec<QueryResponseSingleAvatar> = found
         .into_iter()
-        .map(|kv| QueryResponseSingleAvatar {
-            avatar: kv.avatar(),
-            content: kv.content,
+        .map(|kv| {
+            Ok(QueryResponseSingleAvatar {
+                avatar: kv.avatar(),
+                content: visibility::redact_content(conn, &kv.persona, &kv.content, reader)?,
+                arweave_status: kv.arweave_status,
+                arweave_block_height: kv.arweave_block_height,
+            })
         })
-        .collect();
+        .collect::<Result<_, Error>>()?;
 
     Ok(QueryResponse { values })
 }
@@ -92,6 +211,7 @@ mod tests {
         } = Secp256k1KeyPair::generate();
         let (created1, _) = find_or_create(&mut conn, &platform, &identity, &public_key_1).unwrap();
         let (created2, _) = find_or_create(&mut conn, &platform, &identity, &public_key_2).unwrap();
+        created1.update_arweave(&mut conn, Some("tx-found".into())).unwrap();
         let req: Request = ::http::Request::builder()
             .method(Method::GET)
             .uri(format!("http://localhost/test?platform={}&identity={}", platform, identity))
@@ -100,10 +220,69 @@ mod tests {
         let resp = controller(req).await.unwrap();
         let body: QueryResponse= serde_json::from_str(resp.body()).unwrap();
         assert_eq!(2, body.values.len());
+        let uploaded = body.values.iter().find(|kv| kv.avatar == created1.avatar()).unwrap();
+        assert_eq!(Some("pending".to_string()), uploaded.arweave_status);
+        assert_eq!(None, uploaded.arweave_block_height);
         let avatars: Vec<String> = body.values.into_iter().map(|kv| kv.avatar).collect();
         assert!(avatars.contains(&created1.avatar()));
         assert!(avatars.contains(&created2.avatar()));
 
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_expanded() -> Result<(), Error> {
+        use crate::{
+            crypto::util::compress_public_key,
+            http_client::{tests::{config, mock_server}, UPSTREAM_PROOF_SERVICE},
+        };
+
+        let mut conn = establish_connection();
+        let twitter_handle: String = Faker.fake();
+        let github_handle: String = Faker.fake();
+        let linked = Secp256k1KeyPair::generate();
+        let invalid = Secp256k1KeyPair::generate();
+        find_or_create(&mut conn, "twitter", &twitter_handle, &linked.public_key)?;
+        let (github_kv, _) = find_or_create(&mut conn, "github", &github_handle, &linked.public_key)?;
+        github_kv.patch(&mut conn, &serde_json::json!({"repo": "kv_server"}))?;
+        find_or_create(&mut conn, "twitter", &twitter_handle, &invalid.public_key)?;
+
+        let body = serde_json::json!({
+            "pagination": {"total": 2, "per": 20, "current": 1, "next": 0},
+            "ids": [{
+                "persona": format!("0x{}", compress_public_key(&linked.public_key)),
+                "proofs": [
+                    {"platform": "twitter", "identity": twitter_handle, "is_valid": true},
+                    {"platform": "github", "identity": github_handle, "is_valid": true},
+                ],
+            }, {
+                "persona": format!("0x{}", compress_public_key(&invalid.public_key)),
+                "proofs": [
+                    {"platform": "twitter", "identity": twitter_handle, "is_valid": false, "invalid_reason": "Tweet deleted"},
+                ],
+            }],
+        })
+        .to_string();
+        let (base, _hits) = mock_server(vec![(200, body, 0)]).await;
+
+        let response = expanded_response(
+            &mut conn,
+            &HttpClient::new(UPSTREAM_PROOF_SERVICE, config()),
+            &base,
+            "twitter",
+            &twitter_handle,
+            None,
+        )
+        .await?;
+        assert_eq!(1, response.personas.len());
+        let persona = response.personas.first().unwrap();
+        assert_eq!(format!("0x{}", hex_public_key(&linked.public_key)), persona.avatar);
+        assert_eq!(twitter_handle, persona.linked_by.identity);
+        assert_eq!(2, persona.values.len());
+        let github = persona.values.iter().find(|v| v.platform == "github").unwrap();
+        assert_eq!(serde_json::json!({"repo": "kv_server"}), github.content);
+        assert_eq!(github_handle, github.proof.as_ref().unwrap().identity);
+
+        Ok(())
+    }
 }
@@ -0,0 +1,115 @@
+use crate::{
+    controller::{json_response, query_parse, Request, Response},
+    error::Error,
+    model::{establish_connection, receipt::Receipt},
+};
+use http::StatusCode;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReceiptResponse {
+    pub uuid: String,
+    /// Signed body. See `model::receipt::ReceiptPayload`.
+    pub payload: String,
+    /// Base64 of `eth_personalSign(payload)` by server.
+    pub signature: String,
+    /// Server public key (compressed hexstring started with `0x`).
+    pub signer: String,
+}
+
+impl From<Receipt> for ReceiptResponse {
+    fn from(receipt: Receipt) -> Self {
+        Self {
+            uuid: receipt.uuid.to_string(),
+            signature: receipt.signature_base64(),
+            signer: receipt.signer_hex(),
+            payload: receipt.payload,
+        }
+    }
+}
+
+pub async fn controller(req: Request) -> Result<Response, Error> {
+    let params = query_parse(req);
+    let uuid = ::uuid::Uuid::parse_str(
+        params
+            .get("uuid")
+            .ok_or(Error::ParamMissing("uuid".into()))?,
+    )?;
+
+    let mut conn = establish_connection();
+    let receipt = Receipt::find_by_uuid(&mut conn, uuid)?.ok_or_else(|| {
+        Error::General(
+            format!("Receipt not found: {}", uuid),
+            StatusCode::NOT_FOUND,
+        )
+    })?;
+
+    json_response(StatusCode::OK, &ReceiptResponse::from(receipt))
+}
+
+#[cfg(test)]
+mod tests {
+    use diesel::{insert_into, RunQueryDsl};
+    use fake::{Fake, Faker};
+    use http::Method;
+    use serde_json::json;
+
+    use super::*;
+    use crate::{
+        crypto::secp256k1::Secp256k1KeyPair,
+        model::kv_chains::{KVChain, NewKVChain},
+        util::{base64_to_vec, naive_now},
+    };
+
+    #[tokio::test]
+    async fn test_found() {
+        let mut conn = establish_connection();
+        let server = Secp256k1KeyPair::generate();
+        let avatar = Secp256k1KeyPair::generate();
+        let link: KVChain = insert_into(crate::schema::kv_chains::table)
+            .values(&NewKVChain {
+                uuid: ::uuid::Uuid::new_v4(),
+                persona: avatar.public_key.serialize().to_vec(),
+                platform: Faker.fake(),
+                identity: Faker.fake(),
+                patch: json!({ "test": "abc" }),
+                previous_id: None,
+                signature: vec![1],
+                signature_payload: "".into(),
+                created_at: naive_now(),
+                action: "kv".into(),
+            })
+            .get_result(&mut conn)
+            .unwrap();
+        Receipt::sign_and_save(&mut conn, &link, &server).unwrap();
+
+        let req: Request = ::http::Request::builder()
+            .method(Method::GET)
+            .uri(format!("http://localhost/test?uuid={}", link.uuid))
+            .body("".into())
+            .unwrap();
+        let resp = controller(req).await.unwrap();
+        let body: ReceiptResponse = serde_json::from_str(resp.body()).unwrap();
+        assert_eq!(body.uuid, link.uuid.to_string());
+        let recovered = Secp256k1KeyPair::recover_from_personal_signature(
+            &base64_to_vec(&body.signature).unwrap(),
+            &body.payload,
+        )
+        .unwrap();
+        assert_eq!(
+            body.signer,
+            format!("0x{}", hex::encode(recovered.serialize_compressed()))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_not_found() {
+        let req: Request = ::http::Request::builder()
+            .method(Method::GET)
+            .uri(format!("http://localhost/test?uuid={}", ::uuid::Uuid::new_v4()))
+            .body("".into())
+            .unwrap();
+        let err = controller(req).await.unwrap_err();
+        assert_eq!(err.http_status(), StatusCode::NOT_FOUND);
+    }
+}
@@ -0,0 +1,270 @@
+//! Routes of all controllers, shared by the Lambda and standalone
+//! entrypoints.  Every route is documented in `docs/api.apib`.
+
+use std::{future::Future, pin::Pin};
+
+use http::{header::ALLOW, Method, StatusCode};
+use log::info;
+
+use crate::{
+    config::{ConfigCors, C},
+    controller::{
+        cors, error_response, healthz, metrics, payload, proof, proposal, query, query_by_identity,
+        receipt, request_id, upload, webhook, Request, Response, REQUEST_ID_HEADER,
+    },
+    error::Error,
+};
+
+type Handler = fn(Request) -> Pin<Box<dyn Future<Output = Result<Response, Error>> + Send>>;
+
+pub struct Route {
+    pub method: Method,
+    /// Without base path.
+    pub path: &'static str,
+    handler: Handler,
+}
+
+lazy_static! {
+    pub static ref ROUTES: Vec<Route> = vec![
+        Route { method: Method::GET, path: "/healthz", handler: |req| Box::pin(healthz::controller(req)) },
+        Route { method: Method::GET, path: "/metrics", handler: |req| Box::pin(metrics::controller(req)) },
+        Route { method: Method::GET, path: "/v1/kv", handler: |req| Box::pin(query::controller(req)) },
+        Route { method: Method::POST, path: "/v1/kv", handler: |req| Box::pin(upload::controller(req)) },
+        Route { method: Method::GET, path: "/v1/kv/by_identity", handler: |req| Box::pin(query_by_identity::controller(req)) },
+        Route { method: Method::POST, path: "/v1/kv/payload", handler: |req| Box::pin(payload::controller(req)) },
+        Route { method: Method::GET, path: "/v1/kv/receipt", handler: |req| Box::pin(receipt::controller(req)) },
+        Route { method: Method::GET, path: "/v1/kv/proof", handler: |req| Box::pin(proof::controller(req)) },
+        Route { method: Method::GET, path: "/v1/kv/proposal", handler: |req| Box::pin(proposal::controller(req)) },
+        Route { method: Method::POST, path: "/v1/kv/proposal/signature", handler: |req| Box::pin(proposal::sign_controller(req)) },
+        Route { method: Method::POST, path: "/v1/webhook/proof", handler: |req| Box::pin(webhook::controller(req)) },
+    ];
+}
+
+/// Dispatch a request whose path starts with `base_path` (e.g. `/api`,
+/// or empty).  Unknown paths get `404`, known ones with another method
+/// get `405`.  Every response carries CORS headers of `C.web.cors`,
+/// and `X-Request-Id`.
+pub async fn route(base_path: &str, req: Request) -> Response {
+    route_with_cors(base_path, &C.web.cors, req).await
+}
+
+pub async fn route_with_cors(base_path: &str, cors_config: &ConfigCors, req: Request) -> Response {
+    info!("{} {}", req.method(), req.uri().path());
+
+    let origin = cors::origin(&req);
+    let request_id = request_id(&req);
+    let mut resp = dispatch(base_path, cors_config, &request_id, req).await;
+    cors::apply(cors_config, origin.as_ref(), &mut resp);
+    if let Ok(request_id) = request_id.parse() {
+        resp.headers_mut().insert(REQUEST_ID_HEADER, request_id);
+    }
+    resp
+}
+
+async fn dispatch(
+    base_path: &str,
+    cors_config: &ConfigCors,
+    request_id: &str,
+    req: Request,
+) -> Response {
+    let path = match strip_base_path(base_path, req.uri().path()) {
+        Some(path) => path.to_string(),
+        None => return not_found(request_id),
+    };
+    let routes: Vec<&Route> = ROUTES.iter().filter(|route| route.path == path).collect();
+    if routes.is_empty() {
+        return not_found(request_id);
+    }
+    // Preflight of a cross-origin request.
+    if req.method() == Method::OPTIONS {
+        return cors::preflight(cors_config, cors::origin(&req).as_ref());
+    }
+    match routes.iter().find(|route| route.method == *req.method()) {
+        Some(route) => (route.handler)(req)
+            .await
+            .unwrap_or_else(|err| error_response(err, Some(request_id))),
+        None => {
+            let allow = routes
+                .iter()
+                .map(|route| route.method.as_str())
+                .collect::<Vec<_>>()
+                .join(", ");
+            let mut resp = error_response(
+                Error::General("Method Not Allowed".into(), StatusCode::METHOD_NOT_ALLOWED),
+                Some(request_id),
+            );
+            resp.headers_mut().insert(ALLOW, allow.parse().unwrap());
+            resp
+        }
+    }
+}
+
+/// `path` without `base_path`.  `None` if it is not under `base_path`.
+fn strip_base_path<'a>(base_path: &str, path: &'a str) -> Option<&'a str> {
+    let base_path = base_path.trim_end_matches('/');
+    let rest = path.strip_prefix(base_path)?;
+    if rest.starts_with('/') {
+        Some(rest)
+    } else {
+        None
+    }
+}
+
+fn not_found(request_id: &str) -> Response {
+    error_response(
+        Error::General("Not Found".into(), StatusCode::NOT_FOUND),
+        Some(request_id),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use http::{
+        header::{ACCESS_CONTROL_ALLOW_ORIGIN, ACCESS_CONTROL_MAX_AGE, ORIGIN},
+        HeaderValue,
+    };
+    use serde_json::json;
+
+    use super::*;
+    use crate::controller::ErrorResponse;
+
+    const API_DOC: &str = include_str!("../../docs/api.apib");
+
+    fn request(method: Method, uri: &str) -> Request {
+        ::http::Request::builder()
+            .method(method)
+            .uri(uri)
+            .body("".into())
+            .unwrap()
+    }
+
+    /// `(METHOD, /path)` of every `## Title [METHOD /path]` in API doc.
+    fn documented_routes() -> HashSet<(String, String)> {
+        API_DOC
+            .lines()
+            .filter(|line| line.starts_with("## "))
+            .filter_map(|line| {
+                let action = line.rsplit_once('[')?.1.strip_suffix(']')?;
+                let (method, path) = action.split_once(' ')?;
+                Some((method.to_string(), path.to_string()))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_routes_match_api_doc() {
+        let routes: HashSet<(String, String)> = ROUTES
+            .iter()
+            .map(|route| (route.method.to_string(), route.path.to_string()))
+            .collect();
+        assert_eq!(routes.len(), ROUTES.len(), "duplicated route");
+
+        let documented = documented_routes();
+        let undocumented: Vec<_> = routes.difference(&documented).collect();
+        assert!(undocumented.is_empty(), "not in docs/api.apib: {:?}", undocumented);
+        let unrouted: Vec<_> = documented.difference(&routes).collect();
+        assert!(unrouted.is_empty(), "not routed: {:?}", unrouted);
+    }
+
+    #[test]
+    fn test_strip_base_path() {
+        assert_eq!(Some("/v1/kv"), strip_base_path("", "/v1/kv"));
+        assert_eq!(Some("/v1/kv"), strip_base_path("/api", "/api/v1/kv"));
+        assert_eq!(Some("/v1/kv"), strip_base_path("/api/", "/api/v1/kv"));
+        assert_eq!(None, strip_base_path("/api", "/v1/kv"));
+        assert_eq!(None, strip_base_path("/api", "/apiv1/kv"));
+    }
+
+    #[tokio::test]
+    async fn test_route() {
+        let resp = route("/api", request(Method::GET, "http://localhost/api/healthz")).await;
+        assert_eq!(StatusCode::OK, resp.status());
+
+        let resp = route("/api", request(Method::GET, "http://localhost/healthz")).await;
+        assert_eq!(StatusCode::NOT_FOUND, resp.status());
+
+        let resp = route("", request(Method::DELETE, "http://localhost/v1/kv")).await;
+        assert_eq!(StatusCode::METHOD_NOT_ALLOWED, resp.status());
+        assert_eq!("GET, POST", resp.headers()[ALLOW]);
+
+        // Controller errors are rendered.
+        let resp = route("", request(Method::GET, "http://localhost/v1/kv")).await;
+        assert_eq!(StatusCode::BAD_REQUEST, resp.status());
+    }
+
+    #[tokio::test]
+    async fn test_error_body() {
+        let mut req = request(Method::GET, "http://localhost/v1/kv");
+        req.headers_mut()
+            .insert(REQUEST_ID_HEADER, HeaderValue::from_static("given-id"));
+        let resp = route("", req).await;
+        assert_eq!("given-id", resp.headers()[REQUEST_ID_HEADER]);
+        let body: ErrorResponse = serde_json::from_str(resp.body()).unwrap();
+        assert_eq!("param_missing", body.code);
+        assert_eq!(Some(json!({"param": "avatar"})), body.details);
+        assert_eq!(Some("given-id".to_string()), body.request_id);
+
+        // Generated if not given.
+        let resp = route("", request(Method::GET, "http://localhost/nothing")).await;
+        let body: ErrorResponse = serde_json::from_str(resp.body()).unwrap();
+        assert_eq!("not_found", body.code);
+        assert_eq!(
+            body.request_id.unwrap(),
+            resp.headers()[REQUEST_ID_HEADER].to_str().unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_cors() {
+        let cors_config = ConfigCors {
+            allow_origins: vec!["https://app.example".into()],
+            ..Default::default()
+        };
+        let cross_origin = |method: Method, uri: &str| {
+            let mut req = request(method, uri);
+            req.headers_mut()
+                .insert(ORIGIN, HeaderValue::from_static("https://app.example"));
+            req
+        };
+
+        let resp = route_with_cors(
+            "/api",
+            &cors_config,
+            cross_origin(Method::OPTIONS, "http://localhost/api/v1/kv"),
+        )
+        .await;
+        assert_eq!(StatusCode::NO_CONTENT, resp.status());
+        assert_eq!(
+            "https://app.example",
+            resp.headers()[ACCESS_CONTROL_ALLOW_ORIGIN]
+        );
+        assert_eq!("86400", resp.headers()[ACCESS_CONTROL_MAX_AGE]);
+
+        // Errors of router carry CORS headers, too.
+        let resp = route_with_cors(
+            "/api",
+            &cors_config,
+            cross_origin(Method::OPTIONS, "http://localhost/api/v1/nothing"),
+        )
+        .await;
+        assert_eq!(StatusCode::NOT_FOUND, resp.status());
+        assert_eq!(
+            "https://app.example",
+            resp.headers()[ACCESS_CONTROL_ALLOW_ORIGIN]
+        );
+
+        let resp = route_with_cors(
+            "",
+            &cors_config,
+            cross_origin(Method::DELETE, "http://localhost/v1/kv"),
+        )
+        .await;
+        assert_eq!(StatusCode::METHOD_NOT_ALLOWED, resp.status());
+        assert_eq!(
+            "https://app.example",
+            resp.headers()[ACCESS_CONTROL_ALLOW_ORIGIN]
+        );
+    }
+}
@@ -0,0 +1,316 @@
+//! Standalone server: `router` behind a tower middleware stack.
+
+use std::{any::Any, time::Duration};
+
+use axum::{
+    error_handling::HandleErrorLayer,
+    extract::{DefaultBodyLimit, State},
+    middleware::{self, Next},
+    response::IntoResponse,
+    BoxError, Router,
+};
+use http::{header::CONTENT_LENGTH, HeaderName, HeaderValue, StatusCode};
+use http_body::{LengthLimitError, Limited};
+use hyper::Body as HyperBody;
+use tower::{timeout::error::Elapsed, ServiceBuilder};
+use tower_http::{
+    catch_panic::CatchPanicLayer,
+    compression::CompressionLayer,
+    limit::RequestBodyLimitLayer,
+    request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer},
+    trace::{DefaultOnResponse, TraceLayer},
+};
+use tracing::{info_span, Level};
+
+use crate::{
+    config::{ConfigWeb, C},
+    controller::{cors, error_response, router, Request, Response, REQUEST_ID_HEADER},
+    error::Error,
+};
+
+/// `web.body_limit` if not set.
+pub const DEFAULT_BODY_LIMIT: usize = 1024 * 1024;
+/// `web.timeout` if not set.
+pub const DEFAULT_TIMEOUT: u64 = 30;
+
+tokio::task_local! {
+    /// Request being served, for error responses rendered by
+    /// middlewares.
+    static REQUEST: RequestScope;
+}
+
+#[derive(Clone)]
+struct RequestScope {
+    /// `X-Request-Id`.
+    id: String,
+    origin: Option<HeaderValue>,
+}
+
+/// All routes, with (outermost first) request ID, tracing, panic
+/// catching, compression, timeout and body size limit.
+pub fn app(config: &ConfigWeb) -> Router {
+    let base_path = config.base_path.clone().unwrap_or_default();
+    let body_limit = config.body_limit.unwrap_or(DEFAULT_BODY_LIMIT);
+    let timeout = Duration::from_secs(config.timeout.unwrap_or(DEFAULT_TIMEOUT));
+    let request_id = HeaderName::from_static(REQUEST_ID_HEADER);
+
+    Router::<(), Limited<HyperBody>>::new()
+        .fallback(move |req: http::Request<Limited<HyperBody>>| {
+            entrypoint(base_path.clone(), req)
+        })
+        .layer(
+            ServiceBuilder::new()
+                .layer(SetRequestIdLayer::new(request_id.clone(), MakeRequestUuid))
+                .layer(
+                    TraceLayer::new_for_http()
+                        .make_span_with(|req: &http::Request<HyperBody>| {
+                            let request_id = req
+                                .headers()
+                                .get(REQUEST_ID_HEADER)
+                                .and_then(|id| id.to_str().ok())
+                                .unwrap_or_default();
+                            info_span!("request", method = %req.method(), uri = %req.uri(), request_id)
+                        })
+                        .on_response(DefaultOnResponse::new().level(Level::INFO)),
+                )
+                .layer(PropagateRequestIdLayer::new(request_id))
+                .layer(middleware::from_fn(scope_request))
+                .layer(CatchPanicLayer::custom(panic_response))
+                .layer(CompressionLayer::new())
+                .layer(HandleErrorLayer::new(timeout_response))
+                .timeout(timeout)
+                .layer(DefaultBodyLimit::disable())
+                .layer(middleware::from_fn_with_state(body_limit, reject_too_large))
+                .layer(RequestBodyLimitLayer::new(body_limit)),
+        )
+}
+
+/// Translates between `hyper` `Body` and our `Body`.
+async fn entrypoint(base_path: String, req: http::Request<Limited<HyperBody>>) -> Response {
+    let (parts, body) = req.into_parts();
+    let body = match hyper::body::to_bytes(body).await {
+        Ok(body) => String::from_utf8(body.to_vec())
+            .map_err(|_| Error::General("Body is not valid UTF-8".into(), StatusCode::BAD_REQUEST)),
+        Err(err) if err.is::<LengthLimitError>() => Err(Error::General(
+            "Body too large".into(),
+            StatusCode::PAYLOAD_TOO_LARGE,
+        )),
+        Err(err) => Err(Error::General(
+            format!("Failed to read body: {}", err),
+            StatusCode::BAD_REQUEST,
+        )),
+    };
+
+    match body {
+        Ok(body) => router::route(&base_path, Request::from_parts(parts, body)).await,
+        Err(err) => scoped_error_response(err),
+    }
+}
+
+async fn scope_request(
+    req: http::Request<HyperBody>,
+    next: Next<HyperBody>,
+) -> axum::response::Response {
+    let id = req
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|id| id.to_str().ok())
+        .unwrap_or_default()
+        .to_string();
+    let origin = req.headers().get(http::header::ORIGIN).cloned();
+    REQUEST.scope(RequestScope { id, origin }, next.run(req)).await
+}
+
+/// `error_response` of request being served, with CORS headers.
+fn scoped_error_response(err: Error) -> Response {
+    let scope = REQUEST.try_with(|scope| scope.clone()).ok();
+    let mut resp = error_response(err, scope.as_ref().map(|scope| scope.id.as_str()));
+    cors::apply(
+        &C.web.cors,
+        scope.as_ref().and_then(|scope| scope.origin.as_ref()),
+        &mut resp,
+    );
+    resp
+}
+
+/// `413` for a body whose `Content-Length` is over `limit`, rendered
+/// as other errors.  Bodies without it are cut while read (see
+/// `RequestBodyLimitLayer` and `entrypoint`).
+async fn reject_too_large(
+    State(limit): State<usize>,
+    req: http::Request<HyperBody>,
+    next: Next<HyperBody>,
+) -> axum::response::Response {
+    let length = req
+        .headers()
+        .get(CONTENT_LENGTH)
+        .and_then(|length| length.to_str().ok())
+        .and_then(|length| length.parse::<u64>().ok());
+    if length.is_some_and(|length| length > limit as u64) {
+        return scoped_error_response(Error::General(
+            "Body too large".into(),
+            StatusCode::PAYLOAD_TOO_LARGE,
+        ))
+        .into_response();
+    }
+    next.run(req).await
+}
+
+async fn timeout_response(err: BoxError) -> Response {
+    let err = if err.is::<Elapsed>() {
+        Error::General("Request timed out".into(), StatusCode::REQUEST_TIMEOUT)
+    } else {
+        Error::General(err.to_string(), StatusCode::INTERNAL_SERVER_ERROR)
+    };
+    scoped_error_response(err)
+}
+
+fn panic_response(_err: Box<dyn Any + Send + 'static>) -> Response {
+    scoped_error_response(Error::General(
+        "Internal server error".into(),
+        StatusCode::INTERNAL_SERVER_ERROR,
+    ))
+}
+
+/// Resolves on Ctrl-C or `SIGTERM`.
+pub async fn shutdown_signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("failed to listen to Ctrl-C")
+    };
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("failed to listen to SIGTERM")
+            .recv()
+            .await;
+    };
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use http::{header::ACCEPT_ENCODING, Method};
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::controller::ErrorResponse;
+
+    const ORIGIN: &str = "https://web.next.id";
+
+    fn config() -> ConfigWeb {
+        ConfigWeb {
+            body_limit: Some(16),
+            ..Default::default()
+        }
+    }
+
+    async fn send(req: http::Request<HyperBody>) -> axum::response::Response {
+        app(&config()).oneshot(req).await.unwrap()
+    }
+
+    fn request(method: Method, uri: &str, body: impl Into<HyperBody>) -> http::Request<HyperBody> {
+        http::Request::builder()
+            .method(method)
+            .uri(uri)
+            .body(body.into())
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_request_id() {
+        let resp = send(request(Method::GET, "/nothing", "")).await;
+        assert_eq!(StatusCode::NOT_FOUND, resp.status());
+        assert!(resp.headers().get(REQUEST_ID_HEADER).is_some());
+
+        let mut req = request(Method::GET, "/nothing", "");
+        req.headers_mut()
+            .insert(REQUEST_ID_HEADER, "given-id".parse().unwrap());
+        let resp = send(req).await;
+        assert_eq!("given-id", resp.headers()[REQUEST_ID_HEADER]);
+    }
+
+    /// Asserts `resp` is an `ErrorResponse` of given code, with CORS
+    /// headers of a request from `ORIGIN`.
+    async fn assert_error(resp: axum::response::Response, status: StatusCode, code: &str) {
+        assert_eq!(status, resp.status());
+        let mut expected: Response = http::Response::new("".into());
+        cors::apply(&C.web.cors, Some(&HeaderValue::from_static(ORIGIN)), &mut expected);
+        for (name, value) in expected.headers() {
+            assert_eq!(Some(value), resp.headers().get(name));
+        }
+        let body = hyper::body::to_bytes(resp.into_body()).await.unwrap();
+        let body: ErrorResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!(code, body.code);
+        assert!(body.request_id.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_body_limit() {
+        let mut req = request(Method::POST, "/v1/kv", "a".repeat(17));
+        req.headers_mut().insert(http::header::ORIGIN, ORIGIN.parse().unwrap());
+        let resp = send(req).await;
+        assert_error(resp, StatusCode::PAYLOAD_TOO_LARGE, "payload_too_large").await;
+
+        // Without `Content-Length`.
+        let (mut sender, body) = HyperBody::channel();
+        tokio::spawn(async move { sender.send_data("a".repeat(17).into()).await });
+        let mut req = request(Method::POST, "/v1/kv", body);
+        req.headers_mut().insert(http::header::ORIGIN, ORIGIN.parse().unwrap());
+        let resp = send(req).await;
+        assert_error(resp, StatusCode::PAYLOAD_TOO_LARGE, "payload_too_large").await;
+    }
+
+    #[tokio::test]
+    async fn test_invalid_utf8() {
+        let resp = send(request(Method::POST, "/v1/kv", vec![0xff, 0xfe])).await;
+        assert_eq!(StatusCode::BAD_REQUEST, resp.status());
+        let request_id = resp.headers()[REQUEST_ID_HEADER]
+            .to_str()
+            .unwrap()
+            .to_string();
+        let body = hyper::body::to_bytes(resp.into_body()).await.unwrap();
+        let body: ErrorResponse = serde_json::from_slice(&body).unwrap();
+        assert_eq!("bad_request", body.code);
+        assert!(body.message.contains("UTF-8"));
+        assert_eq!(Some(request_id), body.request_id);
+    }
+
+    #[tokio::test]
+    async fn test_compression() {
+        let mut req = request(Method::GET, "/healthz", "");
+        req.headers_mut()
+            .insert(ACCEPT_ENCODING, "gzip".parse().unwrap());
+        let resp = send(req).await;
+        assert_eq!(StatusCode::OK, resp.status());
+        assert_eq!("gzip", resp.headers()[http::header::CONTENT_ENCODING]);
+    }
+
+    fn scope() -> RequestScope {
+        RequestScope {
+            id: "given-id".into(),
+            origin: Some(HeaderValue::from_static(ORIGIN)),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_panic_response() {
+        let resp = REQUEST
+            .scope(scope(), async { panic_response(Box::new("boom")) })
+            .await;
+        let message = resp.body().clone();
+        assert!(!message.contains("boom"));
+        assert_error(resp.into_response(), StatusCode::INTERNAL_SERVER_ERROR, "internal_error").await;
+    }
+
+    #[tokio::test]
+    async fn test_timeout_response() {
+        let resp = REQUEST
+            .scope(scope(), timeout_response(Box::new(Elapsed::new())))
+            .await;
+        assert_error(resp.into_response(), StatusCode::REQUEST_TIMEOUT, "request_timeout").await;
+    }
+}
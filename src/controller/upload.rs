@@ -1,12 +1,25 @@
-use super::{json_response, query::query_response};
+use super::{
+    json_response, proposal,
+    query::{query_response, QueryResponse},
+    receipt::ReceiptResponse,
+};
 use crate::{
     controller::{json_parse_body, Request, Response},
     crypto::secp256k1::Secp256k1KeyPair,
     error::Error,
-    model::{self, kv_chains::NewKVChain, arweave::KVChainArweaveDocument},
-    proof_client::can_set_kv,
+    model::{
+        self,
+        arweave_outbox::ArweaveOutbox,
+        kv_chains::{self, KVChain, NewKVChain},
+        kv_payload::KVPayload,
+        multisig::{self, Proposal},
+        receipt::Receipt,
+        visibility::Reader,
+    },
+    proof_client::authority::{ProofAuthority, AUTHORITY},
     util::{base64_to_vec, timestamp_to_naive},
 };
+use diesel::{Connection, PgConnection};
 use http::StatusCode;
 use serde::{Deserialize, Serialize};
 
@@ -20,19 +33,39 @@ struct UploadRequest {
     pub uuid: String,
     pub created_at: i64,
     pub patch: serde_json::Value,
+    /// `"kv"` (default), `"visibility"` or `"multisig"`.
+    #[serde(default)]
+    pub action: Option<String>,
+}
+
+/// Same as `GET /v1/kv`, with a server-signed receipt of accepted link.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub(crate) struct UploadResponse {
+    #[serde(flatten)]
+    pub query: QueryResponse,
+    pub receipt: Option<ReceiptResponse>,
 }
 
 pub async fn controller(request: Request) -> Result<Response, Error> {
+    controller_with(request, AUTHORITY.as_ref()).await
+}
+
+/// `controller` with given proof authority.
+pub async fn controller_with(
+    request: Request,
+    authority: &dyn ProofAuthority,
+) -> Result<Response, Error> {
     let req: UploadRequest = json_parse_body(&request)?;
     let sig = base64_to_vec(&req.signature)?;
-    let avatar = req.avatar.clone();
     let persona = Secp256k1KeyPair::from_pubkey_hex(
         &req.avatar
             .or(req.persona)
             .ok_or_else(|| Error::ParamError("avatar not found".into()))?,
     )?;
     let uuid = uuid::Uuid::parse_str(&req.uuid)?;
-    can_set_kv(&persona.public_key, &req.platform, &req.identity).await?;
+    authority
+        .can_set_kv(&persona.public_key, &req.platform, &req.identity)
+        .await?;
 
     let mut conn = model::establish_connection();
     let mut new_kv = NewKVChain::for_persona(&mut conn, &persona.public_key)?;
@@ -40,44 +73,69 @@ pub async fn controller(request: Request) -> Result<Response, Error> {
     new_kv.identity = req.identity;
     new_kv.signature = sig;
     new_kv.patch = req.patch.clone();
+    new_kv.action = kv_chains::parse_action(req.action)?;
     new_kv.uuid = uuid;
     new_kv.created_at = timestamp_to_naive(req.created_at);
     new_kv.signature_payload =
         serde_json::to_string(&new_kv.generate_signature_payload()?).unwrap();
 
+    // m-of-n avatar: collect this signature into a proposal.
+    if let Some(multisig) = multisig::find_by_persona(&mut conn, &new_kv.persona)? {
+        multisig
+            .setting()?
+            .verify_signer(&new_kv.signature, &new_kv.signature_payload)?;
+        let proposal = conn.transaction::<_, Error, _>(|conn| {
+            match Proposal::find_by_uuid(conn, uuid)? {
+                Some(found) if found.persona != new_kv.persona => Err(Error::General(
+                    format!("Proposal belongs to another avatar: {}", uuid),
+                    StatusCode::FORBIDDEN,
+                )),
+                Some(found) => Ok(found),
+                None => {
+                    KVPayload::consume(conn, &persona.public_key, uuid, req.created_at)?;
+                    Proposal::create(conn, &new_kv)
+                }
+            }
+        })?;
+        return proposal::sign_proposal(conn, proposal, multisig, new_kv.signature).await;
+    }
+
+    // A replayed upload is signed on a link which is not the last one
+    // anymore, so tell it before validating signature.
+    KVPayload::ensure_unused(&mut conn, &persona.public_key, uuid)?;
     // Validate signature
     new_kv.validate()?;
 
-    let previous_arweave_id = new_kv.clone().find_last_chain_arweave(&mut conn)?;
-
-    // Try take the kvchain data upload to the arweave.
-    let arweave_document = KVChainArweaveDocument{
-        avatar: avatar.unwrap_or("".into()),
-        uuid,
-        persona: vec![],
-        platform: new_kv.platform.clone(),
-        identity: new_kv.identity.clone(),
-        patch: new_kv.patch.clone(),
-        signature: new_kv.signature.clone(),
-        created_at: new_kv.created_at,
-        signature_payload: new_kv.signature_payload.clone(),
-        previous_id: new_kv.previous_id.clone(),
-        previous_arweave_id: previous_arweave_id.clone(),
-    };
-
-    // Valid. Insert it.
-    let kv_link = new_kv.finalize(&mut conn)?;
-
-    // Apply patch
-    kv_link.perform_patch(&mut conn)?;
-
-    // Upload to arweave
-    // TODO: should make it as a background job
-    let result = arweave_document.upload_to_arweave().await.ok();
-    let _ = kv_link.insert_arweave_id(&mut conn, result);
+    // Valid. Mark the payload as used, insert it, apply patch, sign a
+    // receipt and queue it for Arweave.
+    let (kv_link, receipt) = conn.transaction::<_, Error, _>(|conn| {
+        KVPayload::consume(conn, &persona.public_key, uuid, req.created_at)?;
+        let kv_link = new_kv.finalize(conn)?;
+        kv_link.perform_patch(conn)?;
+        let receipt = Receipt::issue(conn, &kv_link)?;
+        ArweaveOutbox::enqueue(conn, &kv_link)?;
+        Ok((kv_link, receipt))
+    })?;
+
+    respond_created(&mut conn, kv_link, receipt)
+}
 
-    // All done. Build response.
-    let response = query_response(&mut conn, &persona.public_key)?;
+/// Build the response of `POST /v1/kv` for an applied link.  Arweave
+/// upload is done later by `worker::arweave`, so `arweave_id` of this
+/// link is not filled yet.
+pub(crate) fn respond_created(
+    conn: &mut PgConnection,
+    kv_link: KVChain,
+    receipt: Option<Receipt>,
+) -> Result<Response, Error> {
+    let Secp256k1KeyPair {
+        public_key: persona_key,
+        secret_key: _,
+    } = Secp256k1KeyPair::from_pubkey_vec(&kv_link.persona)?;
+    let response = UploadResponse {
+        query: query_response(conn, &persona_key, Some(&Reader::owner(&persona_key)))?,
+        receipt: receipt.map(ReceiptResponse::from),
+    };
 
     json_response(StatusCode::CREATED, &response)
 }
@@ -86,10 +144,12 @@ pub async fn controller(request: Request) -> Result<Response, Error> {
 mod tests {
     use super::*;
     use crate::{
-        controller::query::QueryResponse,
+        config::ConfigArweaveOutbox,
         crypto::util::{compress_public_key, hex_public_key},
         model::{establish_connection, kv, kv_chains::{find_all_by_identity, KVChain}},
+        proof_client::authority::MockAuthority,
         util::{naive_now, vec_to_base64},
+        worker,
     };
     use fake::{Fake, Faker};
     use http::Method;
@@ -99,31 +159,17 @@ mod tests {
     /// Create a request and send it to controller.
     /// And then return the response body.
     async fn create_req_and_send(new_kv_chain: NewKVChain, public_key: PublicKey) -> QueryResponse {
-        let req_body = UploadRequest {
-            persona: Some(compress_public_key(&public_key)),
-            avatar: Some(compress_public_key(&public_key)),
-            platform: new_kv_chain.platform.clone(),
-            identity: new_kv_chain.identity,
-            signature: vec_to_base64(&new_kv_chain.signature),
-            uuid: new_kv_chain.uuid.to_string(),
-            patch: new_kv_chain.patch.clone(),
-            created_at: new_kv_chain.created_at.timestamp(),
-        };
-
-        let req: Request = ::http::Request::builder()
-            .method(Method::POST)
-            .uri(format!("http://localhost/test"))
-            .body(serde_json::to_string(&req_body).unwrap())
-            .unwrap();
-
-        let resp = controller(req).await.unwrap();
+        let req = create_req(&new_kv_chain, &public_key);
+        let resp = controller_with(req, &MockAuthority::allow_all()).await.unwrap();
         assert_eq!(resp.status(), StatusCode::CREATED);
 
         serde_json::from_str(resp.body()).unwrap()
     }
 
+    /// Build a `NewKVChain`, and issue its sign payload as
+    /// `POST /v1/kv/payload` does.
     fn create_new_kv_chain(persona: PublicKey, platform: &String, identity: &String, patch: Value) -> NewKVChain {
-        NewKVChain {
+        let new_kv_chain = NewKVChain {
             uuid: uuid::Uuid::new_v4(),
             persona: persona.serialize().to_vec(),
             platform: platform.clone(),
@@ -133,8 +179,37 @@ mod tests {
             signature: vec![],
             signature_payload: "".into(),
             created_at: naive_now(),
-            arweave_id: None,
-        }
+            action: "kv".into(),
+        };
+        KVPayload::issue(
+            &mut establish_connection(),
+            &persona,
+            new_kv_chain.uuid,
+            new_kv_chain.created_at,
+        )
+        .unwrap();
+
+        new_kv_chain
+    }
+
+    fn create_req(new_kv_chain: &NewKVChain, public_key: &PublicKey) -> Request {
+        let req_body = UploadRequest {
+            persona: Some(compress_public_key(public_key)),
+            avatar: Some(compress_public_key(public_key)),
+            platform: new_kv_chain.platform.clone(),
+            identity: new_kv_chain.identity.clone(),
+            signature: vec_to_base64(&new_kv_chain.signature),
+            uuid: new_kv_chain.uuid.to_string(),
+            patch: new_kv_chain.patch.clone(),
+            created_at: new_kv_chain.created_at.timestamp(),
+            action: Some(new_kv_chain.action.clone()),
+        };
+
+        ::http::Request::builder()
+            .method(Method::POST)
+            .uri(format!("http://localhost/test"))
+            .body(serde_json::to_string(&req_body).unwrap())
+            .unwrap()
     }
 
     #[tokio::test]
@@ -182,6 +257,23 @@ mod tests {
         assert_eq!(proof.content, json!({"test2": "new kv"}));
     }
 
+    /// Upload every queued link of given identity with a fake uploader,
+    /// as `worker::arweave` does.
+    async fn process_outbox(conn: &mut PgConnection, platform: &str, identity: &str) {
+        let config = ConfigArweaveOutbox::default();
+        let mut links = find_all_by_identity(conn, platform, identity).unwrap();
+        links.sort_by_key(|link| link.id);
+        for link in links {
+            let entry = ArweaveOutbox::find_by_kv_chain(conn, link.id).unwrap().unwrap();
+            let uploaded = worker::arweave::process(conn, &entry, &config, |document| async move {
+                Ok(format!("tx-{}", document.uuid))
+            })
+            .await
+            .unwrap();
+            assert!(uploaded);
+        }
+    }
+
     #[tokio::test]
     async fn test_newly_upload_to_arweave_and_query() {
         let keypair = Secp256k1KeyPair::generate();
@@ -193,16 +285,19 @@ mod tests {
 
         let resp_body = create_req_and_send(new_kv_chain.clone(), keypair.public_key).await;
         assert_eq!(1, resp_body.proofs.len());
+        // Response doesn't wait for Arweave.
+        assert_eq!(None, resp_body.proofs.first().unwrap().arweave_id);
 
-        // we check the arweave_id from the response whether is same with database.
-        let from_response_arweave_id = resp_body.proofs.first().unwrap().arweave_id.clone();
-        assert!(from_response_arweave_id != None);
-
-        // take arweave id from kv_chains table
         let mut conn = establish_connection();
+        process_outbox(&mut conn, &platform, &identity).await;
+
+        // take arweave id from kv_chains table, and compare with query result.
         let kv_chain_vec = find_all_by_identity(&mut conn, &platform, &identity).unwrap();
         assert_eq!(1, kv_chain_vec.len());
-        assert_eq!(from_response_arweave_id, kv_chain_vec[0].arweave_id);
+        let expected = Some(format!("tx-{}", new_kv_chain.uuid));
+        assert_eq!(expected, kv_chain_vec[0].arweave_id());
+        let response = query_response(&mut conn, &keypair.public_key, None).unwrap();
+        assert_eq!(expected, response.proofs.first().unwrap().arweave_id);
     }
 
     #[tokio::test]
@@ -215,32 +310,147 @@ mod tests {
         let mut first_kv_chain = create_new_kv_chain(
             keypair.public_key, &platform, &identity, json!({"first": "first"}));
         first_kv_chain.signature = first_kv_chain.sign(&keypair).unwrap();
-
-        let resp_body = create_req_and_send(first_kv_chain.clone(), keypair.public_key).await;
-        assert_eq!(1, resp_body.proofs.len());
-        let primitive_arweave_id_in_kv = resp_body.proofs.first().unwrap().arweave_id.clone();
+        create_req_and_send(first_kv_chain.clone(), keypair.public_key).await;
 
         let mut second_kv_chain = create_new_kv_chain(
             keypair.public_key, &platform, &identity, json!({"second": "second"}));
         let last_link = KVChain::find_last_link(&mut conn, &keypair.public_key).unwrap();
-        second_kv_chain.previous_id = if let Some(last_link_instance) = last_link {
-            Some(last_link_instance.id)
-        } else {
-            None
-        };
-
+        second_kv_chain.previous_id = last_link.map(|last_link_instance| last_link_instance.id);
         second_kv_chain.signature = second_kv_chain.sign(&keypair).unwrap();
+        create_req_and_send(second_kv_chain.clone(), keypair.public_key).await;
 
-        let resp_body = create_req_and_send(second_kv_chain.clone(), keypair.public_key).await;
-        assert_eq!(1, resp_body.proofs.len());
-        let current_arweave_id_in_kv = resp_body.proofs.first().unwrap().arweave_id.clone();
-        assert_ne!(primitive_arweave_id_in_kv, current_arweave_id_in_kv);
+        process_outbox(&mut conn, &platform, &identity).await;
 
         // take arweave id from kv_chains table
         let kv_chain_vec = find_all_by_identity(&mut conn, &platform, &identity).unwrap();
         assert_eq!(2, kv_chain_vec.len());
-        assert_eq!(primitive_arweave_id_in_kv, kv_chain_vec[0].arweave_id);
-        assert_eq!(current_arweave_id_in_kv, kv_chain_vec[1].arweave_id);
+        let first_arweave_id = Some(format!("tx-{}", first_kv_chain.uuid));
+        let second_arweave_id = Some(format!("tx-{}", second_kv_chain.uuid));
+        assert_eq!(first_arweave_id, kv_chain_vec[0].arweave_id());
+        assert_eq!(second_arweave_id, kv_chain_vec[1].arweave_id());
+        assert_eq!(first_arweave_id, kv_chain_vec[1].find_previous_arweave(&mut conn).unwrap());
+
+        // kv follows the latest link.
+        let response = query_response(&mut conn, &keypair.public_key, None).unwrap();
+        assert_eq!(second_arweave_id, response.proofs.first().unwrap().arweave_id);
+    }
+
+    #[tokio::test]
+    async fn test_visibility_action() {
+        let keypair = Secp256k1KeyPair::generate();
+        let platform: String = Faker.fake();
+        let identity: String = Faker.fake();
+        let mut kv_link = create_new_kv_chain(
+            keypair.public_key, &platform, &identity, json!({"public": 1, "private": 2}));
+        kv_link.signature = kv_link.sign(&keypair).unwrap();
+        create_req_and_send(kv_link, keypair.public_key).await;
+
+        let mut conn = establish_connection();
+        let mut visibility_link = create_new_kv_chain(
+            keypair.public_key, &platform, &identity, json!({"private": {"visibility": "private"}}));
+        visibility_link.previous_id = KVChain::find_last_link(&mut conn, &keypair.public_key)
+            .unwrap()
+            .map(|link| link.id);
+        visibility_link.action = kv_chains::ACTION_VISIBILITY.into();
+        visibility_link.signature = visibility_link.sign(&keypair).unwrap();
+        // Owner still reads everything.
+        let resp_body = create_req_and_send(visibility_link, keypair.public_key).await;
+        assert_eq!(json!({"public": 1, "private": 2}), resp_body.proofs.first().unwrap().content);
+
+        // Others don't.
+        let response = query_response(&mut conn, &keypair.public_key, None).unwrap();
+        assert_eq!(json!({"public": 1}), response.proofs.first().unwrap().content);
+    }
+
+    #[tokio::test]
+    async fn test_proposal_of_other_avatar() {
+        let mut conn = establish_connection();
+        let signer = Secp256k1KeyPair::generate();
+        let (victim, attacker) = (Secp256k1KeyPair::generate(), Secp256k1KeyPair::generate());
+        for avatar in [&victim, &attacker] {
+            multisig::find_or_create(&mut conn, &avatar.public_key)
+                .unwrap()
+                .patch(
+                    &mut conn,
+                    &json!({"threshold": 1, "signers": [compress_public_key(&signer.public_key)]}),
+                )
+                .unwrap();
+        }
+        let mut proposed = create_new_kv_chain(
+            victim.public_key, &Faker.fake(), &Faker.fake(), json!({"test": "victim"}));
+        proposed.signature_payload =
+            serde_json::to_string(&proposed.generate_signature_payload().unwrap()).unwrap();
+        Proposal::create(&mut conn, &proposed).unwrap();
+
+        // Same uuid, signed for another avatar.
+        let mut new_kv_chain = create_new_kv_chain(
+            attacker.public_key, &Faker.fake(), &Faker.fake(), json!({"test": "attacker"}));
+        new_kv_chain.uuid = proposed.uuid;
+        new_kv_chain.signature = new_kv_chain.sign(&signer).unwrap();
+        let err = controller_with(
+            create_req(&new_kv_chain, &attacker.public_key),
+            &MockAuthority::allow_all(),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(StatusCode::FORBIDDEN, err.http_status());
+        assert!(Proposal::find_by_uuid(&mut conn, proposed.uuid)
+            .unwrap()
+            .unwrap()
+            .signatures(&mut conn)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_replay_rejected() {
+        let keypair = Secp256k1KeyPair::generate();
+        let mut new_kv_chain = create_new_kv_chain(
+            keypair.public_key, &Faker.fake(), &Faker.fake(), json!({"test": "replay"}));
+        new_kv_chain.signature = new_kv_chain.sign(&keypair).unwrap();
+
+        create_req_and_send(new_kv_chain.clone(), keypair.public_key).await;
+        let result = controller_with(
+            create_req(&new_kv_chain, &keypair.public_key),
+            &MockAuthority::allow_all(),
+        )
+        .await;
+        assert!(matches!(result, Err(Error::PayloadAlreadyUsed(_))));
+    }
+
+    #[tokio::test]
+    async fn test_payload_not_issued() {
+        let keypair = Secp256k1KeyPair::generate();
+        let mut new_kv_chain = create_new_kv_chain(
+            keypair.public_key, &Faker.fake(), &Faker.fake(), json!({"test": "abc"}));
+        // A well-formed, signed but never issued uuid.
+        new_kv_chain.uuid = uuid::Uuid::new_v4();
+        new_kv_chain.signature = new_kv_chain.sign(&keypair).unwrap();
+
+        let result = controller_with(
+            create_req(&new_kv_chain, &keypair.public_key),
+            &MockAuthority::allow_all(),
+        )
+        .await;
+        assert!(matches!(result, Err(Error::PayloadNotIssued(_))));
+    }
+
+    #[tokio::test]
+    async fn test_proof_missing() {
+        let keypair = Secp256k1KeyPair::generate();
+        let mut new_kv_chain = create_new_kv_chain(
+            keypair.public_key, &"twitter".into(), &Faker.fake(), json!({"test": "abc"}));
+        new_kv_chain.signature = new_kv_chain.sign(&keypair).unwrap();
+        let authority = MockAuthority::default().with_proof(&keypair.public_key, "twitter", "someone_else");
+
+        let err = controller_with(create_req(&new_kv_chain, &keypair.public_key), &authority)
+            .await
+            .unwrap_err();
+        assert_eq!(StatusCode::BAD_REQUEST, err.http_status());
+        assert!(err.to_string().contains("Proof not found"));
+        assert!(find_all_by_identity(&mut establish_connection(), "twitter", &new_kv_chain.identity)
+            .unwrap()
+            .is_empty());
     }
 
     // NOTE: test below is created with `persona:` sig payload.
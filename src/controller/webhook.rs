@@ -0,0 +1,273 @@
+use crate::{
+    config::{ConfigWebhook, C},
+    controller::{json_parse_body, json_response, Request, Response},
+    crypto::{
+        secp256k1::Secp256k1KeyPair,
+        util::{compress_public_key, hmac_sha256_verify},
+    },
+    error::Error,
+    model::{establish_connection, kv},
+    proof_client::cache::PROOF_CACHE,
+    util::{base64_to_vec, naive_now, timestamp_to_naive},
+};
+use http::StatusCode;
+use serde::{Deserialize, Serialize};
+
+/// Header carrying signature of request body.
+pub const SIGNATURE_HEADER: &str = "X-Signature";
+
+pub const EVENT_PROOF_CREATED: &str = "proof_created";
+pub const EVENT_PROOF_REVOKED: &str = "proof_revoked";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ProofEvent {
+    /// `proof_created` or `proof_revoked`.
+    pub event: String,
+    /// Persona public key (hexstring).
+    pub persona: String,
+    pub platform: String,
+    pub identity: String,
+    pub created_at: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ProofEventResponse {
+    /// KV records marked / unmarked as revoked.  Events older than (or
+    /// as old as) the last applied one are ignored.
+    pub affected: usize,
+}
+
+pub async fn controller(req: Request) -> Result<Response, Error> {
+    let config = C.webhook.as_ref().ok_or_else(|| {
+        Error::General("Webhook is not enabled.".into(), StatusCode::NOT_FOUND)
+    })?;
+    controller_with(req, config).await
+}
+
+/// `controller` with given webhook config.
+pub async fn controller_with(req: Request, config: &ConfigWebhook) -> Result<Response, Error> {
+    verify_signature(&req, config)?;
+    let event: ProofEvent = json_parse_body(&req)?;
+    if (naive_now().timestamp() - event.created_at).abs() > config.window {
+        return Err(Error::ParamError(format!(
+            "created_at is out of {} seconds window",
+            config.window
+        )));
+    }
+    let revoked = match event.event.as_str() {
+        EVENT_PROOF_CREATED => false,
+        EVENT_PROOF_REVOKED => true,
+        other => return Err(Error::ParamError(format!("unknown event: {}", other))),
+    };
+    let Secp256k1KeyPair {
+        public_key,
+        secret_key: _,
+    } = Secp256k1KeyPair::from_pubkey_hex(&event.persona)?;
+
+    PROOF_CACHE.invalidate(&format!("0x{}", compress_public_key(&public_key)));
+    let mut conn = establish_connection();
+    let affected = kv::mark_revoked(
+        &mut conn,
+        &public_key,
+        &event.platform,
+        &event.identity,
+        revoked,
+        timestamp_to_naive(event.created_at),
+    )?;
+
+    json_response(StatusCode::OK, &ProofEventResponse { affected })
+}
+
+/// Accepts `sha256=<hex HMAC-SHA256 of body>` or `secp256k1=<base64
+/// eth_personalSign of body>`, whichever is configured.
+fn verify_signature(req: &Request, config: &ConfigWebhook) -> Result<(), Error> {
+    let unauthorized =
+        |reason: &str| Error::General(format!("Webhook signature: {}", reason), StatusCode::UNAUTHORIZED);
+    let header = req
+        .headers()
+        .get(SIGNATURE_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .ok_or_else(|| unauthorized("missing"))?;
+    let (scheme, signature) = header.split_once('=').ok_or_else(|| unauthorized("malformed"))?;
+
+    match scheme {
+        "sha256" if !config.hmac_secret.is_empty() => {
+            let given = hex::decode(signature).map_err(|_| unauthorized("malformed"))?;
+            if !hmac_sha256_verify(config.hmac_secret.as_bytes(), req.body().as_bytes(), &given) {
+                return Err(unauthorized("mismatch"));
+            }
+            Ok(())
+        }
+        "secp256k1" if !config.public_key.is_empty() => {
+            let expected = Secp256k1KeyPair::from_pubkey_hex(&config.public_key)?;
+            let sig = base64_to_vec(signature).map_err(|_| unauthorized("malformed"))?;
+            let recovered = Secp256k1KeyPair::recover_from_personal_signature(&sig, req.body())
+                .map_err(|_| unauthorized("mismatch"))?;
+            if recovered != expected.public_key {
+                return Err(unauthorized("mismatch"));
+            }
+            Ok(())
+        }
+        _ => Err(unauthorized("scheme not accepted")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use fake::{Fake, Faker};
+    use http::Method;
+
+    use super::*;
+    use crate::{crypto::util::hmac_sha256, model::kv::find_or_create, util::vec_to_base64};
+
+    const SECRET: &str = "test-secret";
+
+    fn event_body(event: &str, persona: &Secp256k1KeyPair, platform: &str, identity: &str) -> String {
+        event_body_at(event, persona, platform, identity, naive_now().timestamp())
+    }
+
+    fn event_body_at(
+        event: &str,
+        persona: &Secp256k1KeyPair,
+        platform: &str,
+        identity: &str,
+        created_at: i64,
+    ) -> String {
+        serde_json::to_string(&ProofEvent {
+            event: event.into(),
+            persona: format!("0x{}", compress_public_key(&persona.public_key)),
+            platform: platform.into(),
+            identity: identity.into(),
+            created_at,
+        })
+        .unwrap()
+    }
+
+    fn signed_req(body: String, signature: &str) -> Request {
+        ::http::Request::builder()
+            .method(Method::POST)
+            .uri("http://localhost/test")
+            .header(SIGNATURE_HEADER, signature)
+            .body(body)
+            .unwrap()
+    }
+
+    fn hmac_header(body: &str) -> String {
+        format!("sha256={}", hex::encode(hmac_sha256(SECRET.as_bytes(), body.as_bytes())))
+    }
+
+    fn config() -> ConfigWebhook {
+        ConfigWebhook {
+            hmac_secret: SECRET.into(),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_revoke_and_create() {
+        let mut conn = establish_connection();
+        let persona = Secp256k1KeyPair::generate();
+        let identity: String = Faker.fake();
+        find_or_create(&mut conn, "twitter", &identity, &persona.public_key).unwrap();
+
+        let body = event_body(EVENT_PROOF_REVOKED, &persona, "twitter", &identity);
+        let resp = controller_with(signed_req(body.clone(), &hmac_header(&body)), &config())
+            .await
+            .unwrap();
+        let resp_body: ProofEventResponse = serde_json::from_str(resp.body()).unwrap();
+        assert_eq!(1, resp_body.affected);
+        let (record, _) = find_or_create(&mut conn, "twitter", &identity, &persona.public_key).unwrap();
+        assert!(record.revoked_at.is_some());
+
+        // One second later: events of the same second are duplicates.
+        let body = event_body_at(
+            EVENT_PROOF_CREATED,
+            &persona,
+            "twitter",
+            &identity,
+            naive_now().timestamp() + 1,
+        );
+        controller_with(signed_req(body.clone(), &hmac_header(&body)), &config())
+            .await
+            .unwrap();
+        let (record, _) = find_or_create(&mut conn, "twitter", &identity, &persona.public_key).unwrap();
+        assert!(record.revoked_at.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_stale_and_duplicated_events() {
+        let mut conn = establish_connection();
+        let persona = Secp256k1KeyPair::generate();
+        let identity: String = Faker.fake();
+        find_or_create(&mut conn, "twitter", &identity, &persona.public_key).unwrap();
+        let now = naive_now().timestamp();
+        let send = |body: String| async move {
+            let resp = controller_with(signed_req(body.clone(), &hmac_header(&body)), &config())
+                .await
+                .unwrap();
+            serde_json::from_str::<ProofEventResponse>(resp.body()).unwrap().affected
+        };
+
+        let revoked = event_body_at(EVENT_PROOF_REVOKED, &persona, "twitter", &identity, now);
+        assert_eq!(1, send(revoked.clone()).await);
+        // Duplicated
+        assert_eq!(0, send(revoked).await);
+        // Delivered late: created before the revocation.
+        let created = event_body_at(EVENT_PROOF_CREATED, &persona, "twitter", &identity, now - 10);
+        assert_eq!(0, send(created).await);
+        let (record, _) = find_or_create(&mut conn, "twitter", &identity, &persona.public_key).unwrap();
+        assert!(record.revoked_at.is_some());
+
+        let created = event_body_at(EVENT_PROOF_CREATED, &persona, "twitter", &identity, now + 1);
+        assert_eq!(1, send(created).await);
+        let (record, _) = find_or_create(&mut conn, "twitter", &identity, &persona.public_key).unwrap();
+        assert!(record.revoked_at.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_bad_hmac() {
+        let persona = Secp256k1KeyPair::generate();
+        let body = event_body(EVENT_PROOF_REVOKED, &persona, "twitter", "alice");
+        let tampered = body.replace("alice", "bob");
+
+        let err = controller_with(signed_req(tampered, &hmac_header(&body)), &config())
+            .await
+            .unwrap_err();
+        assert_eq!(StatusCode::UNAUTHORIZED, err.http_status());
+
+        // secp256k1 is not configured.
+        let err = controller_with(signed_req(body, "secp256k1=AAAA"), &config())
+            .await
+            .unwrap_err();
+        assert_eq!(StatusCode::UNAUTHORIZED, err.http_status());
+    }
+
+    #[tokio::test]
+    async fn test_secp256k1() {
+        let source = Secp256k1KeyPair::generate();
+        let config = ConfigWebhook {
+            public_key: compress_public_key(&source.public_key),
+            ..Default::default()
+        };
+        let persona = Secp256k1KeyPair::generate();
+        let body = event_body(EVENT_PROOF_REVOKED, &persona, "twitter", "alice");
+
+        let sig = source.personal_sign(&body).unwrap();
+        let resp = controller_with(
+            signed_req(body.clone(), &format!("secp256k1={}", vec_to_base64(&sig))),
+            &config,
+        )
+        .await
+        .unwrap();
+        assert_eq!(StatusCode::OK, resp.status());
+
+        let outsider_sig = Secp256k1KeyPair::generate().personal_sign(&body).unwrap();
+        let err = controller_with(
+            signed_req(body, &format!("secp256k1={}", vec_to_base64(&outsider_sig))),
+            &config,
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(StatusCode::UNAUTHORIZED, err.http_status());
+    }
+}
@@ -0,0 +1,226 @@
+use crate::{crypto::secp256k1::Secp256k1KeyPair, error::Error, util::base64_to_vec};
+use libsecp256k1::PublicKey;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+/// Value of `type` field which marks a JSON object as an encrypted envelope.
+pub const ENVELOPE_TYPE: &str = "kv_encrypted";
+/// Only supported `algorithm` for now.
+/// Content key is wrapped to each recipient using ECIES over
+/// secp256k1, content itself is encrypted with AES-256-GCM.
+pub const ENVELOPE_ALGORITHM: &str = "ecies-secp256k1+aes-256-gcm";
+
+/// AES-GCM nonce length.
+const NONCE_LENGTH: usize = 12;
+/// Uncompressed ephemeral pubkey (65) + wrapped 32-bytes key + GCM tag (16).
+const MIN_WRAPPED_KEY_LENGTH: usize = 65 + 32 + 16;
+
+/// Client-side encrypted value. Server never sees its plaintext.
+///
+/// ```json
+/// {
+///   "type": "kv_encrypted",
+///   "version": "1",
+///   "algorithm": "ecies-secp256k1+aes-256-gcm",
+///   "nonce": "BASE64",
+///   "ciphertext": "BASE64",
+///   "recipients": [{"public_key": "0x02...", "encrypted_key": "BASE64"}]
+/// }
+/// ```
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EncryptedEnvelope {
+    #[serde(rename = "type")]
+    pub envelope_type: String,
+    pub version: String,
+    pub algorithm: String,
+    pub nonce: String,
+    pub ciphertext: String,
+    pub recipients: Vec<EnvelopeRecipient>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EnvelopeRecipient {
+    /// Recipient public key (both compressed / uncompressed and with/without `0x` are OK).
+    pub public_key: String,
+    /// Base64 of content key wrapped by ECIES to `public_key`.
+    pub encrypted_key: String,
+}
+
+impl EncryptedEnvelope {
+    /// Returns `true` if given JSON value claims to be an envelope.
+    pub fn is_envelope(value: &Value) -> bool {
+        value.get("type").and_then(Value::as_str) == Some(ENVELOPE_TYPE)
+    }
+
+    /// Parse and check shape of an envelope.
+    pub fn parse(value: &Value) -> Result<Self, Error> {
+        let envelope: Self = serde_json::from_value(value.clone())
+            .map_err(|e| Error::EnvelopeError(e.to_string()))?;
+        envelope.validate()?;
+        Ok(envelope)
+    }
+
+    fn validate(&self) -> Result<(), Error> {
+        if self.version != "1" {
+            return Err(Error::EnvelopeError(format!(
+                "unsupported version: {}",
+                self.version
+            )));
+        }
+        if self.algorithm != ENVELOPE_ALGORITHM {
+            return Err(Error::EnvelopeError(format!(
+                "unsupported algorithm: {}",
+                self.algorithm
+            )));
+        }
+        if base64_to_vec(&self.nonce)?.len() != NONCE_LENGTH {
+            return Err(Error::EnvelopeError("invalid nonce length".into()));
+        }
+        if base64_to_vec(&self.ciphertext)?.is_empty() {
+            return Err(Error::EnvelopeError("empty ciphertext".into()));
+        }
+        if self.recipients.is_empty() {
+            return Err(Error::EnvelopeError("no recipient".into()));
+        }
+        for recipient in self.recipients.iter() {
+            Secp256k1KeyPair::from_pubkey_hex(&recipient.public_key)?;
+            if base64_to_vec(&recipient.encrypted_key)?.len() < MIN_WRAPPED_KEY_LENGTH {
+                return Err(Error::EnvelopeError(format!(
+                    "invalid encrypted_key for {}",
+                    recipient.public_key
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns `true` if given public key is one of recipients.
+    pub fn is_addressed_to(&self, public_key: &PublicKey) -> bool {
+        self.recipients.iter().any(|recipient| {
+            Secp256k1KeyPair::from_pubkey_hex(&recipient.public_key)
+                .map(|pair| pair.public_key == *public_key)
+                .unwrap_or(false)
+        })
+    }
+}
+
+/// Check all envelopes found in given KV content.
+pub fn validate_content(content: &Value) -> Result<(), Error> {
+    match content {
+        Value::Object(map) => {
+            if EncryptedEnvelope::is_envelope(content) {
+                EncryptedEnvelope::parse(content)?;
+                return Ok(());
+            }
+            map.values().try_for_each(validate_content)
+        }
+        Value::Array(list) => list.iter().try_for_each(validate_content),
+        _ => Ok(()),
+    }
+}
+
+/// Keep only envelopes addressed to given public key (and the path to
+/// them) in KV content. `None` if nothing left.
+pub fn filter_for_recipient(content: &Value, public_key: &PublicKey) -> Option<Value> {
+    match content {
+        Value::Object(map) => {
+            if EncryptedEnvelope::is_envelope(content) {
+                return EncryptedEnvelope::parse(content)
+                    .ok()
+                    .filter(|envelope| envelope.is_addressed_to(public_key))
+                    .map(|_| content.clone());
+            }
+            let filtered: Map<String, Value> = map
+                .iter()
+                .filter_map(|(k, v)| filter_for_recipient(v, public_key).map(|v| (k.clone(), v)))
+                .collect();
+            if filtered.is_empty() {
+                None
+            } else {
+                Some(Value::Object(filtered))
+            }
+        }
+        Value::Array(list) => {
+            let filtered: Vec<Value> = list
+                .iter()
+                .filter_map(|v| filter_for_recipient(v, public_key))
+                .collect();
+            if filtered.is_empty() {
+                None
+            } else {
+                Some(Value::Array(filtered))
+            }
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{crypto::util::compress_public_key, util::vec_to_base64};
+    use serde_json::json;
+
+    fn envelope_for(recipients: &[&PublicKey]) -> Value {
+        json!({
+            "type": ENVELOPE_TYPE,
+            "version": "1",
+            "algorithm": ENVELOPE_ALGORITHM,
+            "nonce": vec_to_base64(&vec![0; NONCE_LENGTH]),
+            "ciphertext": vec_to_base64(&vec![1; 48]),
+            "recipients": recipients.iter().map(|pk| json!({
+                "public_key": format!("0x{}", compress_public_key(pk)),
+                "encrypted_key": vec_to_base64(&vec![2; MIN_WRAPPED_KEY_LENGTH]),
+            })).collect::<Vec<Value>>(),
+        })
+    }
+
+    #[test]
+    fn test_validate_content() -> Result<(), Error> {
+        let recipient = Secp256k1KeyPair::generate();
+        let envelope = envelope_for(&[&recipient.public_key]);
+        validate_content(&json!({"plain": "text", "private": {"tip": envelope.clone()}}))?;
+
+        // Plaintext smuggled into an envelope
+        let mut with_plaintext = envelope.clone();
+        with_plaintext["plaintext"] = json!("secret");
+        assert!(validate_content(&json!({ "tip": with_plaintext })).is_err());
+
+        let mut no_recipient = envelope.clone();
+        no_recipient["recipients"] = json!([]);
+        assert!(validate_content(&json!([no_recipient])).is_err());
+
+        let mut bad_ciphertext = envelope;
+        bad_ciphertext["ciphertext"] = json!("not base64!");
+        assert!(validate_content(&json!({ "tip": bad_ciphertext })).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_filter_for_recipient() {
+        let alice = Secp256k1KeyPair::generate();
+        let bob = Secp256k1KeyPair::generate();
+        let for_alice = envelope_for(&[&alice.public_key]);
+        let for_both = envelope_for(&[&alice.public_key, &bob.public_key]);
+        let content = json!({
+            "plain": "text",
+            "alice": for_alice.clone(),
+            "nested": {"both": for_both.clone(), "other": 1},
+        });
+
+        assert_eq!(
+            Some(json!({"alice": for_alice, "nested": {"both": for_both.clone()}})),
+            filter_for_recipient(&content, &alice.public_key)
+        );
+        assert_eq!(
+            Some(json!({"nested": {"both": for_both}})),
+            filter_for_recipient(&content, &bob.public_key)
+        );
+        assert_eq!(
+            None,
+            filter_for_recipient(&content, &Secp256k1KeyPair::generate().public_key)
+        );
+    }
+}
@@ -1,2 +1,3 @@
+pub mod envelope;
 pub mod secp256k1;
 pub mod util;
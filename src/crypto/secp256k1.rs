@@ -85,6 +85,30 @@ impl Secp256k1KeyPair {
         })
     }
 
+    /// Parse secret key from hexstring. Both `0x...` and raw
+    /// hexstring are supported.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use kv_server::crypto::secp256k1::Secp256k1KeyPair;
+    /// # use kv_server::crypto::util::compress_public_key;
+    /// let pair = Secp256k1KeyPair::from_secret_key_hex("0xb5466835b2228927d8dc1194cf8e6f52ba4b4cdb49cc954f31565d0c30fd44c8").unwrap();
+    /// # assert!(pair.secret_key.is_some());
+    /// # assert_eq!(66, compress_public_key(&pair.public_key).len());
+    /// ```
+    pub fn from_secret_key_hex(secret_key_hex: &str) -> Result<Self, Error> {
+        let hex = secret_key_hex.strip_prefix("0x").unwrap_or(secret_key_hex);
+        let secret_key_bytes = hex::decode(hex).map_err(|e| Error::from(e))?;
+        let secret_key =
+            SecretKey::parse_slice(secret_key_bytes.as_slice()).map_err(|e| Error::from(e))?;
+
+        Ok(Self {
+            public_key: PublicKey::from_secret_key(&secret_key),
+            secret_key: Some(secret_key),
+        })
+    }
+
     /// `web3.eth.personal.sign`
     /// # Examples
     ///
@@ -250,6 +274,7 @@ mod tests {
             }),
             created_at: 1650007736,
             previous: None,
+            action: None,
         };
         let payload_string = serde_json::to_string(&payload)?;
         let signature = util::base64_to_vec("N1RKoa9le6dUdhCl+OO2FZuCWk20AdwMORCuyPqyn4kZW/+D+pvvYsuA6XQJwIzNmCwoj7eHuCF6mPds0fbmDwE=")?;
@@ -277,6 +302,7 @@ mod tests {
             patch: json!({"com.maskbook.tip":[{"created_at":"1650188620","identity":"0x8c5494d05b4f18639834a0f1f4577d5c0a67adf0","invalid_reason":"","isDefault":0,"isPublic":1,"is_valid":true,"last_checked_at":"1650188620","platform":"ethereum"},{"created_at":"1650195158","identity":"0x2ec8ebb0a8eaa40e4ce620cf9f84a96df68d4669","invalid_reason":"","isDefault":1,"isPublic":1,"is_valid":true,"last_checked_at":"1650195158","platform":"ethereum"}]}),
             created_at: 1650209531,
             previous: None,
+            action: None,
         };
         let expected_payload = r#"{"version":"1","uuid":"b333f060-2cdd-4a7f-8fb1-c790c0fadc20","avatar":"04e108f03e61a7e24dbd91a4eb621e3759fd1c2adb0fd6e3ec44e1a0f5bb45fa90d83378348df27416d1f6bf7c15f4220bfce331684ccefae5d07b9f4bab9fdb61","platform":"nextid","identity":"0x03e108f03e61a7e24dbd91a4eb621e3759fd1c2adb0fd6e3ec44e1a0f5bb45fa90","patch":{"com.maskbook.tip":[{"created_at":"1650188620","identity":"0x8c5494d05b4f18639834a0f1f4577d5c0a67adf0","invalid_reason":"","isDefault":0,"isPublic":1,"is_valid":true,"last_checked_at":"1650188620","platform":"ethereum"},{"created_at":"1650195158","identity":"0x2ec8ebb0a8eaa40e4ce620cf9f84a96df68d4669","invalid_reason":"","isDefault":1,"isPublic":1,"is_valid":true,"last_checked_at":"1650195158","platform":"ethereum"}]},"created_at":1650209531,"previous":null}"#;
 
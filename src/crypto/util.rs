@@ -1,6 +1,10 @@
+use hmac::{Hmac, Mac};
 use libsecp256k1::PublicKey;
+use sha2::Sha256;
 use sha3::{Digest, Keccak256};
 
+type HmacSha256 = Hmac<Sha256>;
+
 /// Returns compressed public key (in hexstring, without `0x`).
 pub fn compress_public_key(pk: &PublicKey) -> String {
     let compressed = pk.serialize_compressed();
@@ -28,3 +32,50 @@ pub fn hash_keccak256(message: &str) -> [u8; 32] {
     hasher.update(message);
     hasher.finalize().into()
 }
+
+/// Ethereum address of a public key (lowercase hexstring with `0x`).
+/// # Example
+///
+/// ```rust
+/// # use kv_server::crypto::secp256k1::Secp256k1KeyPair;
+/// # use kv_server::crypto::util::ethereum_address;
+/// let pair = Secp256k1KeyPair::from_secret_key_hex(
+///     "0000000000000000000000000000000000000000000000000000000000000001",
+/// ).unwrap();
+/// assert_eq!(
+///     "0x7e5f4552091a69125d5dfcb7b8c2659029395bdf",
+///     ethereum_address(&pair.public_key)
+/// );
+/// ```
+pub fn ethereum_address(pk: &PublicKey) -> String {
+    let mut hasher = Keccak256::default();
+    // Skip `0x04` prefix of uncompressed key.
+    hasher.update(&pk.serialize()[1..]);
+    let hashed: [u8; 32] = hasher.finalize().into();
+    format!("0x{}", hex::encode(&hashed[12..]))
+}
+
+/// HMAC-SHA256(key, message)
+/// # Example
+///
+/// ```rust
+/// # use kv_server::crypto::util::hmac_sha256;
+/// # use hex_literal::hex;
+/// #
+/// // RFC 4231, test case 2
+/// let result = hmac_sha256(b"Jefe", b"what do ya want for nothing?");
+/// let expected: [u8; 32] = hex!("5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843");
+/// assert_eq!(result, expected);
+/// ```
+pub fn hmac_sha256(key: &[u8], message: &[u8]) -> [u8; 32] {
+    let mut mac = HmacSha256::new_from_slice(key).expect("HMAC accepts keys of any size");
+    mac.update(message);
+    mac.finalize().into_bytes().into()
+}
+
+/// Check `tag` against HMAC-SHA256(key, message), in constant time.
+pub fn hmac_sha256_verify(key: &[u8], message: &[u8], tag: &[u8]) -> bool {
+    let mut mac = HmacSha256::new_from_slice(key).expect("HMAC accepts keys of any size");
+    mac.update(message);
+    mac.verify_slice(tag).is_ok()
+}
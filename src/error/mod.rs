@@ -1,11 +1,18 @@
 use lambda_http::http::StatusCode;
+use serde::{Serialize, Serializer};
+use serde_json::json;
 use thiserror::Error;
 
+use crate::http_client::UPSTREAM_PROOF_SERVICE;
+
 #[derive(Error, Debug)]
 pub enum Error {
     // general
     #[error("{0}")]
     General(String, StatusCode),
+    /// Like `General`, with a code of its own.
+    #[error("{1}")]
+    Coded(ErrorCode, String, StatusCode),
     // http
     #[error("Param missing: {0}")]
     ParamMissing(String),
@@ -37,12 +44,30 @@ pub enum Error {
     UrlParseError(#[from] url::ParseError),
     #[error("arweave error: {0}")]
     ArweaveError(#[from] arweave_rs::error::Error),
+    /// Upstream (one of `http_client::UPSTREAM_*`) can't be reached.
+    #[error("{0} unavailable: {1}")]
+    UpstreamUnavailable(&'static str, String),
+    /// Proof is marked invalid by ProofService, for given `invalid_reason`.
+    #[error("Proof is invalid: {0}")]
+    ProofInvalid(String),
+    // replay protection
+    #[error("Payload not issued: {0}")]
+    PayloadNotIssued(String),
+    #[error("Payload expired: {0}")]
+    PayloadExpired(String),
+    #[error("Payload already used: {0}")]
+    PayloadAlreadyUsed(String),
+    #[error("Payload created_at mismatch: {0}")]
+    PayloadTimestampMismatch(String),
+    #[error("Encrypted envelope error: {0}")]
+    EnvelopeError(String),
 }
 
 impl Error {
     pub fn http_status(&self) -> StatusCode {
         match self {
             Error::General(_, status) => *status,
+            Error::Coded(_, _, status) => *status,
             Error::ParamMissing(_) => StatusCode::BAD_REQUEST,
             Error::ParamError(_) => StatusCode::BAD_REQUEST,
             Error::BodyMissing => StatusCode::BAD_REQUEST,
@@ -58,6 +83,288 @@ impl Error {
             Error::UuidParseError(_) => StatusCode::BAD_REQUEST,
             Error::UrlParseError(_) => StatusCode::BAD_REQUEST,
             Error::ArweaveError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            Error::UpstreamUnavailable(_, _) => StatusCode::SERVICE_UNAVAILABLE,
+            Error::ProofInvalid(_) => StatusCode::BAD_REQUEST,
+            Error::PayloadNotIssued(_) => StatusCode::BAD_REQUEST,
+            Error::PayloadExpired(_) => StatusCode::BAD_REQUEST,
+            Error::PayloadAlreadyUsed(_) => StatusCode::CONFLICT,
+            Error::PayloadTimestampMismatch(_) => StatusCode::BAD_REQUEST,
+            Error::EnvelopeError(_) => StatusCode::BAD_REQUEST,
+        }
+    }
+
+    /// Stable code of this error, for clients to match on instead of
+    /// `to_string()`.
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Error::General(_, status) => ErrorCode::from_status(*status),
+            Error::Coded(code, _, _) => *code,
+            Error::ParamMissing(_) => ErrorCode::ParamMissing,
+            Error::ParamError(_) => ErrorCode::ParamInvalid,
+            Error::BodyMissing => ErrorCode::BodyMissing,
+            Error::ParseError(_) => ErrorCode::JsonInvalid,
+            Error::HttpError(_) => ErrorCode::InternalError,
+            Error::ConfigError(_) => ErrorCode::ConfigError,
+            Error::DatabaseError(_) => ErrorCode::DatabaseError,
+            Error::CryptoError(_) => ErrorCode::CryptoError,
+            Error::HexError(_) => ErrorCode::HexInvalid,
+            Error::HttpClientError(_) => ErrorCode::UpstreamError,
+            Error::SignatureValidationError(_) => ErrorCode::SignatureInvalid,
+            Error::Base64Error(_) => ErrorCode::Base64Invalid,
+            Error::UuidParseError(_) => ErrorCode::UuidInvalid,
+            Error::UrlParseError(_) => ErrorCode::UrlInvalid,
+            Error::ArweaveError(_) => ErrorCode::ArweaveError,
+            Error::UpstreamUnavailable(UPSTREAM_PROOF_SERVICE, _) => {
+                ErrorCode::ProofServiceUnavailable
+            }
+            Error::UpstreamUnavailable(_, _) => ErrorCode::UpstreamUnavailable,
+            Error::ProofInvalid(_) => ErrorCode::ProofInvalid,
+            Error::PayloadNotIssued(_) => ErrorCode::PayloadNotIssued,
+            Error::PayloadExpired(_) => ErrorCode::PayloadExpired,
+            Error::PayloadAlreadyUsed(_) => ErrorCode::PayloadAlreadyUsed,
+            Error::PayloadTimestampMismatch(_) => ErrorCode::PayloadTimestampMismatch,
+            Error::EnvelopeError(_) => ErrorCode::EnvelopeInvalid,
+        }
+    }
+
+    /// Machine-readable context of this error, if any.
+    pub fn details(&self) -> Option<serde_json::Value> {
+        match self {
+            Error::ParamMissing(param) => Some(json!({ "param": param })),
+            Error::UpstreamUnavailable(upstream, _) => Some(json!({ "upstream": upstream })),
+            Error::ProofInvalid(reason) => Some(json!({ "invalid_reason": reason })),
+            _ => None,
         }
     }
 }
+
+/// `code` of error responses.  Listed in `docs/api.apib`: never rename
+/// or reuse one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorCode {
+    // by HTTP status, for `Error::General`
+    BadRequest,
+    Unauthorized,
+    PaymentRequired,
+    Forbidden,
+    NotFound,
+    MethodNotAllowed,
+    RequestTimeout,
+    Conflict,
+    PayloadTooLarge,
+    InternalError,
+    BadGateway,
+    ServiceUnavailable,
+    // request
+    ParamMissing,
+    ParamInvalid,
+    BodyMissing,
+    JsonInvalid,
+    HexInvalid,
+    Base64Invalid,
+    UuidInvalid,
+    UrlInvalid,
+    CryptoError,
+    SignatureInvalid,
+    EnvelopeInvalid,
+    // replay protection
+    PayloadNotIssued,
+    PayloadExpired,
+    PayloadAlreadyUsed,
+    PayloadTimestampMismatch,
+    // server
+    ConfigError,
+    DatabaseError,
+    ArweaveError,
+    UpstreamError,
+    UpstreamUnavailable,
+    // proof_client
+    ProofServiceUnavailable,
+    ProofServiceError,
+    PersonaNotFound,
+    ProofNotFound,
+    ProofInvalid,
+    IdentityNotAccepted,
+    IdentityPersonaMismatch,
+    EthereumRpcError,
+    EthereumAddressNotOwned,
+}
+
+impl ErrorCode {
+    pub const ALL: &'static [ErrorCode] = &[
+        ErrorCode::BadRequest,
+        ErrorCode::Unauthorized,
+        ErrorCode::PaymentRequired,
+        ErrorCode::Forbidden,
+        ErrorCode::NotFound,
+        ErrorCode::MethodNotAllowed,
+        ErrorCode::RequestTimeout,
+        ErrorCode::Conflict,
+        ErrorCode::PayloadTooLarge,
+        ErrorCode::InternalError,
+        ErrorCode::BadGateway,
+        ErrorCode::ServiceUnavailable,
+        ErrorCode::ParamMissing,
+        ErrorCode::ParamInvalid,
+        ErrorCode::BodyMissing,
+        ErrorCode::JsonInvalid,
+        ErrorCode::HexInvalid,
+        ErrorCode::Base64Invalid,
+        ErrorCode::UuidInvalid,
+        ErrorCode::UrlInvalid,
+        ErrorCode::CryptoError,
+        ErrorCode::SignatureInvalid,
+        ErrorCode::EnvelopeInvalid,
+        ErrorCode::PayloadNotIssued,
+        ErrorCode::PayloadExpired,
+        ErrorCode::PayloadAlreadyUsed,
+        ErrorCode::PayloadTimestampMismatch,
+        ErrorCode::ConfigError,
+        ErrorCode::DatabaseError,
+        ErrorCode::ArweaveError,
+        ErrorCode::UpstreamError,
+        ErrorCode::UpstreamUnavailable,
+        ErrorCode::ProofServiceUnavailable,
+        ErrorCode::ProofServiceError,
+        ErrorCode::PersonaNotFound,
+        ErrorCode::ProofNotFound,
+        ErrorCode::ProofInvalid,
+        ErrorCode::IdentityNotAccepted,
+        ErrorCode::IdentityPersonaMismatch,
+        ErrorCode::EthereumRpcError,
+        ErrorCode::EthereumAddressNotOwned,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorCode::BadRequest => "bad_request",
+            ErrorCode::Unauthorized => "unauthorized",
+            ErrorCode::PaymentRequired => "payment_required",
+            ErrorCode::Forbidden => "forbidden",
+            ErrorCode::NotFound => "not_found",
+            ErrorCode::MethodNotAllowed => "method_not_allowed",
+            ErrorCode::RequestTimeout => "request_timeout",
+            ErrorCode::Conflict => "conflict",
+            ErrorCode::PayloadTooLarge => "payload_too_large",
+            ErrorCode::InternalError => "internal_error",
+            ErrorCode::BadGateway => "bad_gateway",
+            ErrorCode::ServiceUnavailable => "service_unavailable",
+            ErrorCode::ParamMissing => "param_missing",
+            ErrorCode::ParamInvalid => "param_invalid",
+            ErrorCode::BodyMissing => "body_missing",
+            ErrorCode::JsonInvalid => "json_invalid",
+            ErrorCode::HexInvalid => "hex_invalid",
+            ErrorCode::Base64Invalid => "base64_invalid",
+            ErrorCode::UuidInvalid => "uuid_invalid",
+            ErrorCode::UrlInvalid => "url_invalid",
+            ErrorCode::CryptoError => "crypto_error",
+            ErrorCode::SignatureInvalid => "signature_invalid",
+            ErrorCode::EnvelopeInvalid => "envelope_invalid",
+            ErrorCode::PayloadNotIssued => "payload_not_issued",
+            ErrorCode::PayloadExpired => "payload_expired",
+            ErrorCode::PayloadAlreadyUsed => "payload_already_used",
+            ErrorCode::PayloadTimestampMismatch => "payload_timestamp_mismatch",
+            ErrorCode::ConfigError => "config_error",
+            ErrorCode::DatabaseError => "database_error",
+            ErrorCode::ArweaveError => "arweave_error",
+            ErrorCode::UpstreamError => "upstream_error",
+            ErrorCode::UpstreamUnavailable => "upstream_unavailable",
+            ErrorCode::ProofServiceUnavailable => "proof_service_unavailable",
+            ErrorCode::ProofServiceError => "proof_service_error",
+            ErrorCode::PersonaNotFound => "persona_not_found",
+            ErrorCode::ProofNotFound => "proof_not_found",
+            ErrorCode::ProofInvalid => "proof_invalid",
+            ErrorCode::IdentityNotAccepted => "identity_not_accepted",
+            ErrorCode::IdentityPersonaMismatch => "identity_persona_mismatch",
+            ErrorCode::EthereumRpcError => "ethereum_rpc_error",
+            ErrorCode::EthereumAddressNotOwned => "ethereum_address_not_owned",
+        }
+    }
+
+    /// Code of an `Error::General` of given status.
+    pub fn from_status(status: StatusCode) -> Self {
+        match status {
+            StatusCode::UNAUTHORIZED => ErrorCode::Unauthorized,
+            StatusCode::PAYMENT_REQUIRED => ErrorCode::PaymentRequired,
+            StatusCode::FORBIDDEN => ErrorCode::Forbidden,
+            StatusCode::NOT_FOUND => ErrorCode::NotFound,
+            StatusCode::METHOD_NOT_ALLOWED => ErrorCode::MethodNotAllowed,
+            StatusCode::REQUEST_TIMEOUT => ErrorCode::RequestTimeout,
+            StatusCode::CONFLICT => ErrorCode::Conflict,
+            StatusCode::PAYLOAD_TOO_LARGE => ErrorCode::PayloadTooLarge,
+            StatusCode::BAD_GATEWAY => ErrorCode::BadGateway,
+            StatusCode::SERVICE_UNAVAILABLE => ErrorCode::ServiceUnavailable,
+            status if status.is_server_error() => ErrorCode::InternalError,
+            _ => ErrorCode::BadRequest,
+        }
+    }
+}
+
+impl Serialize for ErrorCode {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    const API_DOC: &str = include_str!("../../docs/api.apib");
+
+    #[test]
+    fn test_codes_match_api_doc() {
+        let codes: HashSet<&str> = ErrorCode::ALL.iter().map(|code| code.as_str()).collect();
+        assert_eq!(codes.len(), ErrorCode::ALL.len(), "duplicated code");
+
+        // `| code | status | meaning |` rows of the table in API doc.
+        let documented: HashSet<&str> = API_DOC
+            .lines()
+            .filter_map(|line| line.strip_prefix("| `")?.split_once('`'))
+            .map(|(code, _)| code)
+            .collect();
+        let undocumented: Vec<_> = codes.difference(&documented).collect();
+        assert!(
+            undocumented.is_empty(),
+            "not in docs/api.apib: {:?}",
+            undocumented
+        );
+        let unknown: Vec<_> = documented.difference(&codes).collect();
+        assert!(unknown.is_empty(), "not in ErrorCode: {:?}", unknown);
+    }
+
+    #[test]
+    fn test_code() {
+        assert_eq!(
+            ErrorCode::NotFound,
+            Error::General("nope".into(), StatusCode::NOT_FOUND).code()
+        );
+        assert_eq!(
+            ErrorCode::InternalError,
+            Error::General("nope".into(), StatusCode::GATEWAY_TIMEOUT).code()
+        );
+        let err = Error::Coded(
+            ErrorCode::ProofNotFound,
+            "nope".into(),
+            StatusCode::BAD_REQUEST,
+        );
+        assert_eq!(ErrorCode::ProofNotFound, err.code());
+        assert_eq!("nope", err.to_string());
+        assert_eq!(
+            Some(json!({"param": "avatar"})),
+            Error::ParamMissing("avatar".into()).details()
+        );
+        let err = Error::ProofInvalid("Tweet deleted".into());
+        assert_eq!(ErrorCode::ProofInvalid, err.code());
+        assert_eq!(
+            Some(json!({"invalid_reason": "Tweet deleted"})),
+            err.details()
+        );
+        assert_eq!(
+            "\"proof_not_found\"",
+            serde_json::to_string(&ErrorCode::ProofNotFound).unwrap()
+        );
+    }
+}
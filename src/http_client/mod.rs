@@ -0,0 +1,372 @@
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use http::{header::CONTENT_TYPE, Request, StatusCode, Uri};
+use hyper::{body::HttpBody as _, client::HttpConnector, Body, Client};
+use hyper_tls::HttpsConnector;
+use rand::Rng;
+
+use crate::{config::ConfigHttpClient, error::Error};
+
+/// Names of upstream services, as shown in `Error::UpstreamUnavailable`.
+pub const UPSTREAM_PROOF_SERVICE: &str = "ProofService";
+pub const UPSTREAM_ARWEAVE: &str = "Arweave gateway";
+pub const UPSTREAM_IPFS: &str = "IPFS";
+pub const UPSTREAM_S3: &str = "S3";
+pub const UPSTREAM_ETHEREUM: &str = "Ethereum RPC";
+
+/// HTTP client to an upstream service with timeouts, retries and a
+/// circuit breaker.  See `ConfigHttpClient`.
+pub struct HttpClient {
+    upstream: &'static str,
+    client: Client<HttpsConnector<HttpConnector>>,
+    config: ConfigHttpClient,
+    breaker: Mutex<Breaker>,
+}
+
+#[derive(Default)]
+struct Breaker {
+    /// Failed calls in a row.
+    failures: u32,
+    /// Calls fail fast until then.
+    open_until: Option<Instant>,
+    /// When the single call let through after cooldown (half-open
+    /// state) was started.  Others fail fast while it is in flight.
+    probe_started_at: Option<Instant>,
+}
+
+impl HttpClient {
+    /// Client to `upstream` (one of `UPSTREAM_*`).
+    pub fn new(upstream: &'static str, config: ConfigHttpClient) -> Self {
+        let mut http = HttpConnector::new();
+        http.enforce_http(false);
+        http.set_connect_timeout(Some(Duration::from_millis(config.connect_timeout)));
+        let https = HttpsConnector::new_with_connector(http);
+        let client = Client::builder().build::<_, Body>(https);
+
+        Self {
+            upstream,
+            client,
+            config,
+            breaker: Mutex::new(Breaker::default()),
+        }
+    }
+
+    /// GET given URL, returns status and body of the response.
+    /// Transport errors, timeouts and 5xx responses are retried.
+    pub async fn get(&self, url: &str) -> Result<(StatusCode, Vec<u8>), Error> {
+        let uri = parse_uri(url)?;
+        self.check_breaker()?;
+
+        let mut attempt: u32 = 0;
+        loop {
+            let result = self
+                .send_once(Request::get(uri.clone()).body(Body::empty())?)
+                .await;
+            let failed = match &result {
+                Ok((status, _)) => status.is_server_error(),
+                Err(_) => true,
+            };
+            if !failed {
+                self.record_success();
+                return result;
+            }
+            if attempt >= self.config.retries {
+                self.record_failure();
+                return result;
+            }
+            attempt += 1;
+            tokio::time::sleep(self.backoff(attempt)).await;
+        }
+    }
+
+    /// GET given URL, returns status and body of the response.
+    /// Not retried.
+    pub async fn get_once(&self, url: &str) -> Result<(StatusCode, Vec<u8>), Error> {
+        self.send(Request::get(parse_uri(url)?).body(Body::empty())?)
+            .await
+    }
+
+    /// POST JSON body to given URL, returns status and body of the
+    /// response.  Not retried.
+    pub async fn post_json(&self, url: &str, body: String) -> Result<(StatusCode, Vec<u8>), Error> {
+        let request = Request::post(parse_uri(url)?)
+            .header(CONTENT_TYPE, "application/json")
+            .body(Body::from(body))?;
+        self.send(request).await
+    }
+
+    /// Send given request, returns status and body of the response.
+    /// Not retried.
+    pub async fn send(&self, request: Request<Body>) -> Result<(StatusCode, Vec<u8>), Error> {
+        self.check_breaker()?;
+
+        let result = self.send_once(request).await;
+        match &result {
+            Ok((status, _)) if !status.is_server_error() => self.record_success(),
+            _ => self.record_failure(),
+        }
+        result
+    }
+
+    /// If the circuit breaker is open now.
+    pub fn is_open(&self) -> bool {
+        let breaker = self.breaker.lock().unwrap();
+        matches!(breaker.open_until, Some(until) if Instant::now() < until)
+    }
+
+    async fn send_once(&self, request: Request<Body>) -> Result<(StatusCode, Vec<u8>), Error> {
+        let fetch = async {
+            let mut resp = self.client.request(request).await?;
+            let mut body: Vec<u8> = vec![];
+            while let Some(chunk) = resp.body_mut().data().await {
+                body.extend_from_slice(&chunk?);
+            }
+            Ok::<_, Error>((resp.status(), body))
+        };
+
+        tokio::time::timeout(Duration::from_millis(self.config.timeout), fetch)
+            .await
+            .map_err(|_| Error::UpstreamUnavailable(self.upstream, "request timed out".into()))?
+    }
+
+    /// Wait before `attempt`-th retry: exponential backoff plus jitter.
+    fn backoff(&self, attempt: u32) -> Duration {
+        let base = self
+            .config
+            .retry_backoff
+            .saturating_mul(1 << (attempt - 1).min(16));
+        let jitter = if base > 0 {
+            rand::thread_rng().gen_range(0..=base)
+        } else {
+            0
+        };
+
+        Duration::from_millis(base + jitter)
+    }
+
+    fn check_breaker(&self) -> Result<(), Error> {
+        let mut breaker = self.breaker.lock().unwrap();
+        match breaker.open_until {
+            Some(until) if Instant::now() < until => Err(Error::UpstreamUnavailable(
+                self.upstream,
+                "circuit breaker is open".into(),
+            )),
+            Some(_) => {
+                // Cooldown passed: let one call probe upstream.  A probe
+                // which never finished (e.g. dropped) expires after
+                // another cooldown.  `failures` is kept, so its failure
+                // re-opens the breaker.
+                let cooldown = Duration::from_millis(self.config.breaker_cooldown);
+                match breaker.probe_started_at {
+                    Some(started_at) if started_at.elapsed() < cooldown => {
+                        Err(Error::UpstreamUnavailable(
+                            self.upstream,
+                            "circuit breaker is half-open".into(),
+                        ))
+                    }
+                    _ => {
+                        breaker.probe_started_at = Some(Instant::now());
+                        Ok(())
+                    }
+                }
+            }
+            None => Ok(()),
+        }
+    }
+
+    fn record_success(&self) {
+        let mut breaker = self.breaker.lock().unwrap();
+        breaker.failures = 0;
+        breaker.open_until = None;
+        breaker.probe_started_at = None;
+    }
+
+    fn record_failure(&self) {
+        let mut breaker = self.breaker.lock().unwrap();
+        breaker.probe_started_at = None;
+        breaker.failures += 1;
+        if self.config.breaker_threshold > 0 && breaker.failures >= self.config.breaker_threshold {
+            breaker.open_until =
+                Some(Instant::now() + Duration::from_millis(self.config.breaker_cooldown));
+        }
+    }
+}
+
+pub(crate) fn parse_uri(url: &str) -> Result<Uri, Error> {
+    url.parse()
+        .map_err(|_| Error::ParamError(format!("invalid URL: {}", url)))
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::{
+        convert::Infallible,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+    };
+
+    use hyper::{
+        body::Bytes,
+        service::{make_service_fn, service_fn},
+        Request, Response, Server,
+    };
+
+    use super::*;
+
+    pub(crate) fn config() -> ConfigHttpClient {
+        ConfigHttpClient {
+            connect_timeout: 1000,
+            timeout: 1000,
+            retries: 0,
+            retry_backoff: 1,
+            breaker_threshold: 0,
+            breaker_cooldown: 60000,
+        }
+    }
+
+    /// Start a local upstream answering n-th request with n-th
+    /// `(status, body, delay_ms)` of `script` (the last one repeats).
+    pub(crate) async fn mock_server<B: Into<Bytes>>(script: Vec<(u16, B, u64)>) -> (String, Arc<AtomicUsize>) {
+        let hits = Arc::new(AtomicUsize::new(0));
+        let script: Arc<Vec<(u16, Bytes, u64)>> = Arc::new(
+            script
+                .into_iter()
+                .map(|(status, body, delay)| (status, body.into(), delay))
+                .collect(),
+        );
+        let counter = hits.clone();
+        let make_svc = make_service_fn(move |_conn| {
+            let counter = counter.clone();
+            let script = script.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |_req: Request<Body>| {
+                    let nth = counter.fetch_add(1, Ordering::SeqCst);
+                    let (status, body, delay) = script[nth.min(script.len() - 1)].clone();
+                    async move {
+                        tokio::time::sleep(Duration::from_millis(delay)).await;
+                        Ok::<_, Infallible>(
+                            Response::builder()
+                                .status(status)
+                                .body(Body::from(body))
+                                .unwrap(),
+                        )
+                    }
+                }))
+            }
+        });
+        let server = Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_svc);
+        let base = format!("http://{}", server.local_addr());
+        tokio::spawn(server);
+
+        (base, hits)
+    }
+
+    #[tokio::test]
+    async fn test_retry_server_error() {
+        let (base, hits) = mock_server(vec![(503, "busy", 0), (200, "ok", 0)]).await;
+        let client = HttpClient::new(UPSTREAM_IPFS, ConfigHttpClient {
+            retries: 2,
+            ..config()
+        });
+
+        let (status, body) = client.get(&base).await.unwrap();
+        assert_eq!(2, hits.load(Ordering::SeqCst));
+        assert_eq!(StatusCode::OK, status);
+        assert_eq!(b"ok".to_vec(), body);
+    }
+
+    #[tokio::test]
+    async fn test_no_retry_client_error() {
+        let (base, hits) = mock_server(vec![(404, "not found", 0)]).await;
+        let client = HttpClient::new(UPSTREAM_IPFS, ConfigHttpClient {
+            retries: 2,
+            ..config()
+        });
+
+        let (status, _) = client.get(&base).await.unwrap();
+        assert_eq!(1, hits.load(Ordering::SeqCst));
+        assert_eq!(StatusCode::NOT_FOUND, status);
+    }
+
+    #[tokio::test]
+    async fn test_timeout() {
+        let (base, hits) = mock_server(vec![(200, "slow", 500)]).await;
+        let client = HttpClient::new(UPSTREAM_ARWEAVE, ConfigHttpClient {
+            timeout: 50,
+            retries: 1,
+            ..config()
+        });
+
+        let err = client.get(&base).await.unwrap_err();
+        assert!(matches!(err, Error::UpstreamUnavailable(UPSTREAM_ARWEAVE, _)));
+        assert_eq!(StatusCode::SERVICE_UNAVAILABLE, err.http_status());
+        assert_eq!("Arweave gateway unavailable: request timed out", err.to_string());
+        assert_eq!(2, hits.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn test_circuit_breaker() {
+        let (base, hits) = mock_server(vec![(500, "down", 0)]).await;
+        let client = HttpClient::new(UPSTREAM_S3, ConfigHttpClient {
+            breaker_threshold: 2,
+            ..config()
+        });
+
+        for _ in 0..2 {
+            let (status, _) = client.get(&base).await.unwrap();
+            assert_eq!(StatusCode::INTERNAL_SERVER_ERROR, status);
+        }
+        assert!(client.is_open());
+
+        // Fails fast without touching upstream.
+        let err = client.get(&base).await.unwrap_err();
+        assert!(matches!(err, Error::UpstreamUnavailable(UPSTREAM_S3, _)));
+        assert_eq!(2, hits.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn test_circuit_breaker_recovers() {
+        let (base, _hits) = mock_server(vec![(500, "{}", 0), (200, "ok", 0)]).await;
+        let client = HttpClient::new(UPSTREAM_S3, ConfigHttpClient {
+            breaker_threshold: 1,
+            breaker_cooldown: 20,
+            ..config()
+        });
+
+        assert_eq!(StatusCode::INTERNAL_SERVER_ERROR, client.get(&base).await.unwrap().0);
+        assert!(client.is_open());
+        tokio::time::sleep(Duration::from_millis(40)).await;
+        assert_eq!(StatusCode::OK, client.get(&base).await.unwrap().0);
+        assert!(!client.is_open());
+    }
+
+    #[tokio::test]
+    async fn test_circuit_breaker_single_probe() {
+        let (base, hits) = mock_server(vec![(500, "{}", 0), (200, "ok", 100)]).await;
+        let client = HttpClient::new(UPSTREAM_S3, ConfigHttpClient {
+            breaker_threshold: 1,
+            breaker_cooldown: 20,
+            ..config()
+        });
+
+        assert_eq!(StatusCode::INTERNAL_SERVER_ERROR, client.get(&base).await.unwrap().0);
+        tokio::time::sleep(Duration::from_millis(40)).await;
+
+        // Only the first call after cooldown reaches upstream.
+        let (probe, other) = tokio::join!(client.get(&base), async {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            client.get(&base).await
+        });
+        assert_eq!(StatusCode::OK, probe.unwrap().0);
+        assert!(matches!(other.unwrap_err(), Error::UpstreamUnavailable(UPSTREAM_S3, _)));
+        assert_eq!(2, hits.load(Ordering::SeqCst));
+
+        // Closed again after the probe succeeded.
+        assert_eq!(StatusCode::OK, client.get(&base).await.unwrap().0);
+    }
+}
@@ -8,7 +8,10 @@ pub mod config;
 pub mod controller;
 pub mod crypto;
 pub mod error;
+pub mod http_client;
+pub mod metrics;
 pub mod model;
 pub mod proof_client;
 mod schema;
 pub mod util;
+pub mod worker;
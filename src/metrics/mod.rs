@@ -0,0 +1,135 @@
+//! Process-wide counters and gauges, rendered in Prometheus text
+//! format by `GET /metrics`.
+
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+
+/// Monotonically increasing counter.
+pub struct Counter {
+    pub name: &'static str,
+    pub help: &'static str,
+    value: AtomicU64,
+}
+
+impl Counter {
+    pub const fn new(name: &'static str, help: &'static str) -> Self {
+        Self {
+            name,
+            help,
+            value: AtomicU64::new(0),
+        }
+    }
+
+    pub fn inc(&self) {
+        self.value.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> u64 {
+        self.value.load(Ordering::Relaxed)
+    }
+
+    fn render(&self) -> String {
+        format!(
+            "# HELP {name} {help}\n# TYPE {name} counter\n{name} {value}\n",
+            name = self.name,
+            help = self.help,
+            value = self.get()
+        )
+    }
+}
+
+/// Value which can go up and down.
+pub struct Gauge {
+    pub name: &'static str,
+    pub help: &'static str,
+    value: AtomicI64,
+}
+
+impl Gauge {
+    pub const fn new(name: &'static str, help: &'static str) -> Self {
+        Self {
+            name,
+            help,
+            value: AtomicI64::new(0),
+        }
+    }
+
+    pub fn set(&self, value: i64) {
+        self.value.store(value, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> i64 {
+        self.value.load(Ordering::Relaxed)
+    }
+
+    fn render(&self) -> String {
+        format!(
+            "# HELP {name} {help}\n# TYPE {name} gauge\n{name} {value}\n",
+            name = self.name,
+            help = self.help,
+            value = self.get()
+        )
+    }
+}
+
+pub static PROOF_CACHE_HITS: Counter = Counter::new(
+    "kv_proof_cache_hits_total",
+    "ProofService lookups served from cache.",
+);
+pub static PROOF_CACHE_MISSES: Counter = Counter::new(
+    "kv_proof_cache_misses_total",
+    "ProofService lookups which went to ProofService.",
+);
+
+pub static ARWEAVE_WALLET_BALANCE: Gauge = Gauge::new(
+    "kv_arweave_wallet_balance_winston",
+    "Last known balance of Arweave wallet.",
+);
+pub static ARWEAVE_WALLET_RESERVE: Gauge = Gauge::new(
+    "kv_arweave_wallet_reserve_winston",
+    "Balance Arweave uploads must leave in wallet.",
+);
+pub static ARWEAVE_SPENT_TODAY: Gauge = Gauge::new(
+    "kv_arweave_spent_today_winston",
+    "Fees paid for Arweave transactions today (UTC).",
+);
+pub static ARWEAVE_TRANSACTIONS_TODAY: Gauge = Gauge::new(
+    "kv_arweave_transactions_today",
+    "Arweave transactions posted today (UTC).",
+);
+pub static ARWEAVE_OUTBOX_DEAD: Gauge = Gauge::new(
+    "kv_arweave_outbox_dead",
+    "Archive outbox entries given up after max_attempts failures.",
+);
+pub static ARWEAVE_UPLOADS_REFUSED: Counter = Counter::new(
+    "kv_arweave_uploads_refused_total",
+    "Arweave uploads refused because wallet balance is below reserve.",
+);
+
+/// Cache hit rate of ProofService lookups, in `0.0..=1.0`.
+pub fn proof_cache_hit_rate() -> f64 {
+    let hits = PROOF_CACHE_HITS.get();
+    let total = hits + PROOF_CACHE_MISSES.get();
+    if total == 0 {
+        0.0
+    } else {
+        hits as f64 / total as f64
+    }
+}
+
+/// Render all metrics in Prometheus text format.
+pub fn render() -> String {
+    let mut result = String::new();
+    result.push_str(&PROOF_CACHE_HITS.render());
+    result.push_str(&PROOF_CACHE_MISSES.render());
+    result.push_str(&format!(
+        "# HELP kv_proof_cache_hit_rate Hit rate of ProofService lookup cache.\n# TYPE kv_proof_cache_hit_rate gauge\nkv_proof_cache_hit_rate {}\n",
+        proof_cache_hit_rate()
+    ));
+    result.push_str(&ARWEAVE_WALLET_BALANCE.render());
+    result.push_str(&ARWEAVE_WALLET_RESERVE.render());
+    result.push_str(&ARWEAVE_SPENT_TODAY.render());
+    result.push_str(&ARWEAVE_TRANSACTIONS_TODAY.render());
+    result.push_str(&ARWEAVE_UPLOADS_REFUSED.render());
+    result.push_str(&ARWEAVE_OUTBOX_DEAD.render());
+    result
+}
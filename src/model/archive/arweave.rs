@@ -0,0 +1,37 @@
+use async_trait::async_trait;
+
+use super::{ArchiveSink, SINK_ARWEAVE};
+use crate::{
+    config::ConfigArwave,
+    error::Error,
+    model::arweave::{restore::Restorer, KVChainArweaveDocument},
+};
+
+/// One transaction per document, signed by the wallet (JWK) of
+/// `config.jwt`.
+pub struct ArweaveSink {
+    config: ConfigArwave,
+    restorer: Restorer,
+}
+
+impl ArweaveSink {
+    pub fn new(config: ConfigArwave) -> Self {
+        let restorer = Restorer::new(&config.url, config.client.clone());
+        Self { config, restorer }
+    }
+}
+
+#[async_trait]
+impl ArchiveSink for ArweaveSink {
+    fn name(&self) -> &'static str {
+        SINK_ARWEAVE
+    }
+
+    async fn put(&self, document: &KVChainArweaveDocument) -> Result<String, Error> {
+        document.upload_with(&self.config).await
+    }
+
+    async fn get(&self, content_id: &str) -> Result<KVChainArweaveDocument, Error> {
+        self.restorer.fetch(content_id).await
+    }
+}
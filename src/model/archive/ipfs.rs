@@ -0,0 +1,91 @@
+use async_trait::async_trait;
+use http::{header::CONTENT_TYPE, Request, StatusCode};
+use hyper::Body;
+use serde::Deserialize;
+
+use super::{ArchiveSink, SINK_IPFS};
+use crate::{
+    config::ConfigArchiveIpfs,
+    error::Error,
+    model::arweave::KVChainArweaveDocument,
+    http_client::{parse_uri, HttpClient, UPSTREAM_IPFS},
+};
+
+const BOUNDARY: &str = "kv-server-archive-boundary";
+
+/// IPFS node with a Kubo-compatible HTTP RPC API (`/api/v0/*`).
+/// Content id is the CID (v1) of the document.
+pub struct IpfsSink {
+    api: String,
+    pin: bool,
+    client: HttpClient,
+}
+
+#[derive(Deserialize, Debug)]
+struct AddResponse {
+    #[serde(rename = "Hash")]
+    hash: String,
+}
+
+impl IpfsSink {
+    pub fn new(config: ConfigArchiveIpfs) -> Self {
+        Self {
+            api: config.api.trim_end_matches('/').into(),
+            pin: config.pin,
+            client: HttpClient::new(UPSTREAM_IPFS, config.client),
+        }
+    }
+
+    async fn call(&self, request: Request<Body>) -> Result<Vec<u8>, Error> {
+        let (status, body) = self.client.send(request).await?;
+        if !status.is_success() {
+            return Err(Error::General(
+                format!(
+                    "IPFS API error: {} {}",
+                    status,
+                    String::from_utf8_lossy(&body)
+                ),
+                StatusCode::BAD_GATEWAY,
+            ));
+        }
+        Ok(body)
+    }
+}
+
+#[async_trait]
+impl ArchiveSink for IpfsSink {
+    fn name(&self) -> &'static str {
+        SINK_IPFS
+    }
+
+    /// `POST /api/v0/add`
+    async fn put(&self, document: &KVChainArweaveDocument) -> Result<String, Error> {
+        let url = format!(
+            "{}/api/v0/add?pin={}&cid-version=1",
+            self.api, self.pin
+        );
+        let mut body = format!(
+            "--{}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"{}.json\"\r\nContent-Type: application/json\r\n\r\n",
+            BOUNDARY, document.uuid
+        )
+        .into_bytes();
+        body.extend(serde_json::to_vec(document)?);
+        body.extend(format!("\r\n--{}--\r\n", BOUNDARY).into_bytes());
+        let request = Request::post(parse_uri(&url)?)
+            .header(
+                CONTENT_TYPE,
+                format!("multipart/form-data; boundary={}", BOUNDARY),
+            )
+            .body(Body::from(body))?;
+
+        let response: AddResponse = serde_json::from_slice(&self.call(request).await?)?;
+        Ok(response.hash)
+    }
+
+    /// `POST /api/v0/cat`
+    async fn get(&self, content_id: &str) -> Result<KVChainArweaveDocument, Error> {
+        let url = format!("{}/api/v0/cat?arg={}", self.api, content_id);
+        let request = Request::post(parse_uri(&url)?).body(Body::empty())?;
+        KVChainArweaveDocument::parse(&self.call(request).await?)
+    }
+}
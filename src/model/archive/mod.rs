@@ -0,0 +1,80 @@
+mod tests;
+pub mod arweave;
+pub mod ipfs;
+pub mod s3;
+
+use async_trait::async_trait;
+use diesel::PgConnection;
+use http::StatusCode;
+
+use crate::{
+    config::ConfigArchive,
+    error::Error,
+    model::{
+        arweave::KVChainArweaveDocument,
+        kv_chains::{KVChain, ACTION_KV},
+        visibility,
+    },
+};
+
+pub use self::{arweave::ArweaveSink, ipfs::IpfsSink, s3::S3Sink};
+
+/// Key of Arweave in `kv_chains.archive_ids`.
+pub const SINK_ARWEAVE: &str = "arweave";
+pub const SINK_IPFS: &str = "ipfs";
+pub const SINK_S3: &str = "s3";
+
+/// Permanent storage of `kv_chains` links.
+///
+/// Arweave is the primary one: it is chained by `previous_arweave_id`
+/// and tracked until mined (see `worker::arweave`).  Others mirror the
+/// same documents.
+#[async_trait]
+pub trait ArchiveSink: Send + Sync {
+    /// Key in `kv_chains.archive_ids` and `kv_arweave_outbox.sink`.
+    fn name(&self) -> &'static str;
+
+    /// Store given document, returns its content id in this sink.
+    async fn put(&self, document: &KVChainArweaveDocument) -> Result<String, Error>;
+
+    /// Fetch a document by its content id.
+    async fn get(&self, content_id: &str) -> Result<KVChainArweaveDocument, Error>;
+}
+
+/// Names of configured sinks other than Arweave.
+pub fn mirror_names(config: &ConfigArchive) -> Vec<&'static str> {
+    let mut names = vec![];
+    if config.ipfs.is_some() {
+        names.push(SINK_IPFS);
+    }
+    if config.s3.is_some() {
+        names.push(SINK_S3);
+    }
+    names
+}
+
+/// Configured sinks other than Arweave.
+pub fn mirrors(config: &ConfigArchive) -> Vec<Box<dyn ArchiveSink>> {
+    let mut sinks: Vec<Box<dyn ArchiveSink>> = vec![];
+    if let Some(ipfs) = &config.ipfs {
+        sinks.push(Box::new(IpfsSink::new(ipfs.clone())));
+    }
+    if let Some(s3) = &config.s3 {
+        sinks.push(Box::new(S3Sink::new(s3.clone())));
+    }
+    sinks
+}
+
+/// Refuse to archive a KV link which patches a namespace its persona
+/// has made private (e.g. before archiving was enabled).
+pub fn ensure_public(conn: &mut PgConnection, link: &KVChain) -> Result<(), Error> {
+    if link.action != ACTION_KV
+        || visibility::redact_content(conn, &link.persona, &link.patch, None)? == link.patch
+    {
+        return Ok(());
+    }
+    Err(Error::General(
+        "Link patches a private namespace: not archived".into(),
+        StatusCode::FORBIDDEN,
+    ))
+}
@@ -0,0 +1,156 @@
+use async_trait::async_trait;
+use http::{header::CONTENT_TYPE, Method, Request, StatusCode};
+use hyper::Body;
+use sha2::{Digest, Sha256};
+
+use super::{ArchiveSink, SINK_S3};
+use crate::{
+    config::ConfigArchiveS3,
+    crypto::util::hmac_sha256,
+    error::Error,
+    model::arweave::KVChainArweaveDocument,
+    http_client::{parse_uri, HttpClient, UPSTREAM_S3},
+};
+
+/// S3-compatible object store, authenticated by AWS Signature V4.
+/// Content id is the object key (`{prefix}{uuid}.json`).
+pub struct S3Sink {
+    config: ConfigArchiveS3,
+    client: HttpClient,
+}
+
+impl S3Sink {
+    pub fn new(config: ConfigArchiveS3) -> Self {
+        Self {
+            client: HttpClient::new(UPSTREAM_S3, config.client.clone()),
+            config,
+        }
+    }
+
+    /// Path-style URL of given object.  `prefix` is expected to be URL-safe.
+    fn object_url(&self, key: &str) -> String {
+        format!(
+            "{}/{}/{}",
+            self.config.endpoint.trim_end_matches('/'),
+            self.config.bucket,
+            key
+        )
+    }
+
+    async fn call(&self, method: Method, key: &str, body: Vec<u8>) -> Result<Vec<u8>, Error> {
+        let uri = parse_uri(&self.object_url(key))?;
+        let host = uri
+            .authority()
+            .map(|authority| authority.to_string())
+            .ok_or_else(|| Error::ParamError(format!("invalid S3 endpoint: {}", self.config.endpoint)))?;
+        let payload_hash = hex::encode(Sha256::digest(&body));
+        let amz_date = chrono::Utc::now().format("%Y%m%dT%H%M%SZ").to_string();
+        let mut headers = vec![
+            ("host".to_string(), host),
+            ("x-amz-content-sha256".to_string(), payload_hash.clone()),
+            ("x-amz-date".to_string(), amz_date.clone()),
+        ];
+        if method == Method::PUT {
+            headers.push((CONTENT_TYPE.to_string(), "application/json".to_string()));
+        }
+        let authorization = sign_v4(
+            &self.config,
+            method.as_str(),
+            uri.path(),
+            "",
+            &headers,
+            &payload_hash,
+            &amz_date,
+        );
+
+        let mut request = Request::builder().method(method).uri(uri);
+        for (name, value) in headers.iter().filter(|(name, _)| name != "host") {
+            request = request.header(name.as_str(), value.as_str());
+        }
+        let request = request
+            .header("authorization", authorization)
+            .body(Body::from(body))?;
+        let (status, response) = self.client.send(request).await?;
+        if !status.is_success() {
+            return Err(Error::General(
+                format!(
+                    "S3 error on {}: {} {}",
+                    key,
+                    status,
+                    String::from_utf8_lossy(&response)
+                ),
+                StatusCode::BAD_GATEWAY,
+            ));
+        }
+        Ok(response)
+    }
+}
+
+#[async_trait]
+impl ArchiveSink for S3Sink {
+    fn name(&self) -> &'static str {
+        SINK_S3
+    }
+
+    /// `PUT /{bucket}/{key}`
+    async fn put(&self, document: &KVChainArweaveDocument) -> Result<String, Error> {
+        let key = format!("{}{}.json", self.config.prefix, document.uuid);
+        self.call(Method::PUT, &key, serde_json::to_vec(document)?).await?;
+        Ok(key)
+    }
+
+    /// `GET /{bucket}/{key}`
+    async fn get(&self, content_id: &str) -> Result<KVChainArweaveDocument, Error> {
+        KVChainArweaveDocument::parse(&self.call(Method::GET, content_id, vec![]).await?)
+    }
+}
+
+/// `Authorization` header of AWS Signature V4 (service `s3`).
+/// `amz_date` is `YYYYMMDDTHHMMSSZ`, same as `x-amz-date` in `headers`.
+pub fn sign_v4(
+    config: &ConfigArchiveS3,
+    method: &str,
+    canonical_uri: &str,
+    canonical_query: &str,
+    headers: &[(String, String)],
+    payload_hash: &str,
+    amz_date: &str,
+) -> String {
+    let mut headers: Vec<(String, String)> = headers
+        .iter()
+        .map(|(name, value)| (name.to_lowercase(), value.trim().to_string()))
+        .collect();
+    headers.sort();
+    let canonical_headers: String = headers
+        .iter()
+        .map(|(name, value)| format!("{}:{}\n", name, value))
+        .collect();
+    let signed_headers = headers
+        .iter()
+        .map(|(name, _)| name.as_str())
+        .collect::<Vec<_>>()
+        .join(";");
+    let canonical_request = format!(
+        "{}\n{}\n{}\n{}\n{}\n{}",
+        method, canonical_uri, canonical_query, canonical_headers, signed_headers, payload_hash
+    );
+
+    let date = &amz_date[..8];
+    let scope = format!("{}/{}/s3/aws4_request", date, config.region);
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+        amz_date,
+        scope,
+        hex::encode(Sha256::digest(canonical_request.as_bytes()))
+    );
+    let key_date = hmac_sha256(format!("AWS4{}", config.secret_key).as_bytes(), date.as_bytes());
+    let key_region = hmac_sha256(&key_date, config.region.as_bytes());
+    let key_service = hmac_sha256(&key_region, b"s3");
+    let key_signing = hmac_sha256(&key_service, b"aws4_request");
+    let signature = hex::encode(hmac_sha256(&key_signing, string_to_sign.as_bytes()));
+
+    format!(
+        "AWS4-HMAC-SHA256 Credential={}/{},SignedHeaders={},Signature={}",
+        config.access_key, scope, signed_headers, signature
+    )
+}
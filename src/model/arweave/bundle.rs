@@ -0,0 +1,313 @@
+//! ANS-104 bundles: many signed data items posted in a single Arweave
+//! transaction.  See https://github.com/ArweaveTeam/arweave-standards/blob/master/ans/ANS-104.md
+
+use std::path::Path;
+
+use http::StatusCode;
+use rsa::{
+    pss::BlindedSigningKey,
+    signature::{RandomizedSigner, SignatureEncoding},
+    BigUint, RsaPrivateKey,
+};
+use serde::Deserialize;
+use sha2::{Digest, Sha256, Sha384};
+
+use crate::error::Error;
+
+/// Signature type of Arweave (RSA-PSS, 4096 bits) keys.
+pub const SIGNATURE_TYPE_ARWEAVE: u16 = 1;
+/// Length of both signature and owner of `SIGNATURE_TYPE_ARWEAVE`.
+pub const ARWEAVE_KEY_LENGTH: usize = 512;
+
+pub const TAG_BUNDLE_FORMAT: &str = "Bundle-Format";
+pub const TAG_BUNDLE_VERSION: &str = "Bundle-Version";
+
+/// Signs data items.
+pub trait DataItemSigner: Send + Sync {
+    fn signature_type(&self) -> u16;
+    /// Public key.
+    fn owner(&self) -> Vec<u8>;
+    fn sign(&self, message: &[u8]) -> Result<Vec<u8>, Error>;
+}
+
+/// Arweave wallet (JWK), the same one `upload_to_arweave` uses.
+pub struct JwkSigner {
+    signing_key: BlindedSigningKey<Sha256>,
+    owner: Vec<u8>,
+}
+
+#[derive(Deserialize)]
+struct Jwk {
+    n: String,
+    e: String,
+    d: String,
+    p: String,
+    q: String,
+}
+
+impl JwkSigner {
+    pub fn from_jwk_path(path: &Path) -> Result<Self, Error> {
+        let jwk: Jwk = serde_json::from_slice(&std::fs::read(path).map_err(|e| {
+            Error::General(format!("Arweave wallet: {}", e), StatusCode::INTERNAL_SERVER_ERROR)
+        })?)?;
+        let decode = |field: &str| -> Result<Vec<u8>, Error> {
+            Ok(base64::decode_config(field, base64::URL_SAFE_NO_PAD)?)
+        };
+        let owner = decode(&jwk.n)?;
+        let key = RsaPrivateKey::from_components(
+            BigUint::from_bytes_be(&owner),
+            BigUint::from_bytes_be(&decode(&jwk.e)?),
+            BigUint::from_bytes_be(&decode(&jwk.d)?),
+            vec![
+                BigUint::from_bytes_be(&decode(&jwk.p)?),
+                BigUint::from_bytes_be(&decode(&jwk.q)?),
+            ],
+        )
+        .map_err(|e| Error::General(format!("Arweave wallet: {}", e), StatusCode::INTERNAL_SERVER_ERROR))?;
+
+        Ok(Self {
+            signing_key: BlindedSigningKey::<Sha256>::new(key),
+            owner,
+        })
+    }
+}
+
+impl DataItemSigner for JwkSigner {
+    fn signature_type(&self) -> u16 {
+        SIGNATURE_TYPE_ARWEAVE
+    }
+
+    fn owner(&self) -> Vec<u8> {
+        self.owner.clone()
+    }
+
+    fn sign(&self, message: &[u8]) -> Result<Vec<u8>, Error> {
+        Ok(self
+            .signing_key
+            .sign_with_rng(&mut rand::thread_rng(), message)
+            .to_vec())
+    }
+}
+
+/// A signed ANS-104 data item (binary form).
+#[derive(Clone, Debug)]
+pub struct DataItem {
+    /// Base64url of SHA-256 of signature.
+    pub id: String,
+    pub bytes: Vec<u8>,
+}
+
+impl DataItem {
+    /// Sign `data` with `tags` (no target, no anchor).
+    pub fn sign(
+        signer: &dyn DataItemSigner,
+        tags: &[(String, String)],
+        data: &[u8],
+    ) -> Result<Self, Error> {
+        let signature_type = signer.signature_type();
+        let owner = signer.owner();
+        let tag_bytes = encode_tags(tags);
+        let message = deep_hash(&DeepHashItem::List(vec![
+            DeepHashItem::Blob(b"dataitem".to_vec()),
+            DeepHashItem::Blob(b"1".to_vec()),
+            DeepHashItem::Blob(signature_type.to_string().into_bytes()),
+            DeepHashItem::Blob(owner.clone()),
+            // target, anchor
+            DeepHashItem::Blob(vec![]),
+            DeepHashItem::Blob(vec![]),
+            DeepHashItem::Blob(tag_bytes.clone()),
+            DeepHashItem::Blob(data.to_vec()),
+        ]));
+        let signature = signer.sign(&message)?;
+
+        let mut bytes = Vec::with_capacity(2 + signature.len() + owner.len() + 18 + tag_bytes.len() + data.len());
+        bytes.extend_from_slice(&signature_type.to_le_bytes());
+        bytes.extend_from_slice(&signature);
+        bytes.extend_from_slice(&owner);
+        // target, anchor: absent
+        bytes.push(0);
+        bytes.push(0);
+        bytes.extend_from_slice(&(tags.len() as u64).to_le_bytes());
+        bytes.extend_from_slice(&(tag_bytes.len() as u64).to_le_bytes());
+        bytes.extend_from_slice(&tag_bytes);
+        bytes.extend_from_slice(data);
+
+        Ok(Self {
+            id: base64::encode_config(Sha256::digest(&signature), base64::URL_SAFE_NO_PAD),
+            bytes,
+        })
+    }
+}
+
+/// Binary bundle of given data items, to be posted as data of a
+/// transaction tagged with `bundle_tags()`.
+pub fn bundle(items: &[DataItem]) -> Result<Vec<u8>, Error> {
+    let mut bytes = u256_le(items.len() as u64).to_vec();
+    for item in items.iter() {
+        bytes.extend_from_slice(&u256_le(item.bytes.len() as u64));
+        bytes.extend_from_slice(&base64::decode_config(&item.id, base64::URL_SAFE_NO_PAD)?);
+    }
+    for item in items.iter() {
+        bytes.extend_from_slice(&item.bytes);
+    }
+
+    Ok(bytes)
+}
+
+pub fn bundle_tags() -> Vec<(String, String)> {
+    vec![
+        (TAG_BUNDLE_FORMAT.to_string(), "binary".to_string()),
+        (TAG_BUNDLE_VERSION.to_string(), "2.0.0".to_string()),
+    ]
+}
+
+fn u256_le(value: u64) -> [u8; 32] {
+    let mut bytes = [0u8; 32];
+    bytes[..8].copy_from_slice(&value.to_le_bytes());
+    bytes
+}
+
+pub enum DeepHashItem {
+    Blob(Vec<u8>),
+    List(Vec<DeepHashItem>),
+}
+
+/// SHA-384 based `deepHash` of arweave-js.
+pub fn deep_hash(item: &DeepHashItem) -> Vec<u8> {
+    match item {
+        DeepHashItem::Blob(data) => {
+            let tag = Sha384::digest(format!("blob{}", data.len()).as_bytes());
+            let mut hasher = Sha384::new();
+            hasher.update(tag);
+            hasher.update(Sha384::digest(data));
+            hasher.finalize().to_vec()
+        }
+        DeepHashItem::List(children) => {
+            let mut acc = Sha384::digest(format!("list{}", children.len()).as_bytes()).to_vec();
+            for child in children.iter() {
+                let mut hasher = Sha384::new();
+                hasher.update(&acc);
+                hasher.update(deep_hash(child));
+                acc = hasher.finalize().to_vec();
+            }
+            acc
+        }
+    }
+}
+
+/// Avro encoding of `[{name: bytes, value: bytes}]`.
+pub fn encode_tags(tags: &[(String, String)]) -> Vec<u8> {
+    let mut bytes = vec![];
+    if !tags.is_empty() {
+        avro_long(&mut bytes, tags.len() as i64);
+        for (name, value) in tags.iter() {
+            avro_long(&mut bytes, name.len() as i64);
+            bytes.extend_from_slice(name.as_bytes());
+            avro_long(&mut bytes, value.len() as i64);
+            bytes.extend_from_slice(value.as_bytes());
+        }
+    }
+    // End of array.
+    bytes.push(0);
+    bytes
+}
+
+/// Zigzag varint.
+fn avro_long(bytes: &mut Vec<u8>, value: i64) {
+    let mut n = ((value << 1) ^ (value >> 63)) as u64;
+    while n & !0x7f != 0 {
+        bytes.push(((n & 0x7f) | 0x80) as u8);
+        n >>= 7;
+    }
+    bytes.push(n as u8);
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Deterministic signer.  Signature is SHA-256 of message, repeated.
+    pub(crate) struct FakeSigner;
+
+    impl DataItemSigner for FakeSigner {
+        fn signature_type(&self) -> u16 {
+            SIGNATURE_TYPE_ARWEAVE
+        }
+
+        fn owner(&self) -> Vec<u8> {
+            vec![7; ARWEAVE_KEY_LENGTH]
+        }
+
+        fn sign(&self, message: &[u8]) -> Result<Vec<u8>, Error> {
+            Ok(Sha256::digest(message).repeat(ARWEAVE_KEY_LENGTH / 32))
+        }
+    }
+
+    #[test]
+    fn test_avro_tags() {
+        assert_eq!(vec![0], encode_tags(&[]));
+        let tags = vec![("a".to_string(), "bc".to_string())];
+        // count 1 => 2, len 1 => 2, len 2 => 4
+        assert_eq!(vec![2, 2, b'a', 4, b'b', b'c', 0], encode_tags(&tags));
+
+        let mut bytes = vec![];
+        avro_long(&mut bytes, 64);
+        assert_eq!(vec![0x80, 0x01], bytes);
+        let mut bytes = vec![];
+        avro_long(&mut bytes, -1);
+        assert_eq!(vec![1], bytes);
+    }
+
+    #[test]
+    fn test_deep_hash() {
+        let blob = deep_hash(&DeepHashItem::Blob(b"abc".to_vec()));
+        let mut expected = Sha384::new();
+        expected.update(Sha384::digest(b"blob3"));
+        expected.update(Sha384::digest(b"abc"));
+        assert_eq!(expected.finalize().to_vec(), blob);
+
+        let list = deep_hash(&DeepHashItem::List(vec![DeepHashItem::Blob(b"abc".to_vec())]));
+        let mut expected = Sha384::new();
+        expected.update(Sha384::digest(b"list1"));
+        expected.update(&blob);
+        assert_eq!(expected.finalize().to_vec(), list);
+    }
+
+    #[test]
+    fn test_data_item_layout() {
+        let tags = vec![("App-Name".to_string(), "kv_server".to_string())];
+        let item = DataItem::sign(&FakeSigner, &tags, b"{}").unwrap();
+        let tag_bytes = encode_tags(&tags);
+
+        assert_eq!(&[1, 0], &item.bytes[..2]);
+        let signature = &item.bytes[2..2 + ARWEAVE_KEY_LENGTH];
+        assert_eq!(
+            base64::encode_config(Sha256::digest(signature), base64::URL_SAFE_NO_PAD),
+            item.id
+        );
+        let rest = &item.bytes[2 + 2 * ARWEAVE_KEY_LENGTH..];
+        assert_eq!(&[0, 0], &rest[..2]);
+        assert_eq!(1u64.to_le_bytes(), rest[2..10]);
+        assert_eq!((tag_bytes.len() as u64).to_le_bytes(), rest[10..18]);
+        assert_eq!(tag_bytes.as_slice(), &rest[18..18 + tag_bytes.len()]);
+        assert_eq!(b"{}", &rest[18 + tag_bytes.len()..]);
+    }
+
+    #[test]
+    fn test_bundle_layout() {
+        let first = DataItem::sign(&FakeSigner, &[], b"first").unwrap();
+        let second = DataItem::sign(&FakeSigner, &[], b"second").unwrap();
+        let bytes = bundle(&[first.clone(), second.clone()]).unwrap();
+
+        assert_eq!(u256_le(2), bytes[..32]);
+        assert_eq!(u256_le(first.bytes.len() as u64), bytes[32..64]);
+        assert_eq!(
+            base64::decode_config(&first.id, base64::URL_SAFE_NO_PAD).unwrap(),
+            bytes[64..96]
+        );
+        assert_eq!(u256_le(second.bytes.len() as u64), bytes[96..128]);
+        let body = &bytes[32 + 2 * 64..];
+        assert_eq!(first.bytes.as_slice(), &body[..first.bytes.len()]);
+        assert_eq!(second.bytes.as_slice(), &body[first.bytes.len()..]);
+    }
+}
@@ -1,33 +1,213 @@
 mod tests;
+pub mod bundle;
+pub mod restore;
+pub mod wallet;
 
 use std::path::PathBuf;
 
 use ::uuid::Uuid;
 use chrono::NaiveDateTime;
-use arweave_rs::{Arweave, crypto::base64::Base64};
+use arweave_rs::{
+    Arweave,
+    crypto::base64::Base64,
+    transaction::tags::{FromUtf8Strs, Tag},
+};
+use http::StatusCode;
+use libsecp256k1::PublicKey;
+use log::warn;
 use serde::{Deserialize, Serialize};
 use url::Url;
 
-use crate::{config::C, error::Error};
+use self::{
+    bundle::{DataItem, DataItemSigner},
+    wallet::WALLET,
+};
+
+use crate::{
+    config::{ConfigArwave, ConfigArweaveTags, C},
+    crypto::{
+        secp256k1::Secp256k1KeyPair,
+        util::{compress_public_key, hex_public_key},
+    },
+    error::Error,
+    model::{
+        establish_connection,
+        kv_chains::{KVChain, SignPayload},
+        multisig::SignerSignature,
+    },
+    http_client::HttpClient,
+};
+
+pub const TAG_APP_NAME: &str = "App-Name";
+pub const TAG_APP_VERSION: &str = "App-Version";
+pub const TAG_CONTENT_TYPE: &str = "Content-Type";
+/// Compressed avatar public key, `0x` prefixed.
+pub const TAG_AVATAR: &str = "Avatar";
+pub const TAG_PLATFORM: &str = "Platform";
+pub const TAG_IDENTITY: &str = "Identity";
+pub const TAG_UUID: &str = "Uuid";
+/// `previous_arweave_id` of the document.
+pub const TAG_PREVIOUS: &str = "Previous-Tx";
+
+/// Current `schema_version` of `KVChainArweaveDocument`.
+pub const SCHEMA_VERSION: u32 = 2;
 
 /// A KVChainArweaveDocument is a struct that represents the data that is uploaded to Arweave.
 /// It is a subset of the KVChain struct, and is used to permantently store the data on Arweave.
-#[derive(Clone, Debug, Serialize, Deserialize)]
+/// See `docs/arweave.md` for its JSON form.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct KVChainArweaveDocument {
+    pub schema_version: u32,
+    /// Avatar public key (uncompressed hexstring started with `0x`).
     pub avatar: String,
     pub uuid: Uuid,
-    pub persona: Vec<u8>,
     pub platform: String,
     pub identity: String,
     pub patch: serde_json::Value,
+    /// Base64 in JSON.
+    #[serde(with = "base64_bytes")]
     pub signature: Vec<u8>,
+    /// UNIX timestamp (second) in JSON.
+    #[serde(with = "chrono::naive::serde::ts_seconds")]
     pub created_at: NaiveDateTime,
     pub signature_payload: String,
+    /// `id` of previous link in the DB which uploaded it.
     pub previous_id: Option<i32>,
     pub previous_arweave_id: Option<String>,
+    /// Signatures of m-of-n signers.  Omitted for single-key avatars.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub signer_signatures: Vec<SignerSignature>,
+}
+
+/// Document uploaded before `schema_version` was introduced.
+#[derive(Deserialize, Debug)]
+struct LegacyDocument {
+    /// Raw string given by client: compressed or not, maybe empty.
+    avatar: String,
+    uuid: Uuid,
+    /// Always empty.
+    #[serde(default)]
+    persona: Vec<u8>,
+    platform: String,
+    identity: String,
+    patch: serde_json::Value,
+    signature: Vec<u8>,
+    created_at: NaiveDateTime,
+    signature_payload: String,
+    previous_id: Option<i32>,
+    previous_arweave_id: Option<String>,
+}
+
+pub(crate) mod base64_bytes {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(bytes: &Vec<u8>, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&base64::encode(bytes))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let encoded = String::deserialize(deserializer)?;
+        base64::decode(encoded).map_err(serde::de::Error::custom)
+    }
 }
 
 impl KVChainArweaveDocument {
+    /// Document of an accepted link.
+    pub fn from_link(link: &KVChain, previous_arweave_id: Option<String>) -> Self {
+        Self {
+            schema_version: SCHEMA_VERSION,
+            avatar: format!("0x{}", hex::encode(&link.persona)),
+            uuid: link.uuid,
+            platform: link.platform.clone(),
+            identity: link.identity.clone(),
+            patch: link.patch.clone(),
+            signature: link.signature.clone(),
+            created_at: link.created_at,
+            signature_payload: link.signature_payload.clone(),
+            previous_id: link.previous_id,
+            previous_arweave_id,
+            // Written by `Proposal::try_finalize` only.
+            signer_signatures: link.signer_signatures().unwrap_or_default(),
+        }
+    }
+
+    /// Parse a document of any `schema_version`, converted into current one.
+    pub fn parse(data: &[u8]) -> Result<Self, Error> {
+        let value: serde_json::Value = serde_json::from_slice(data)?;
+        match value.get("schema_version").and_then(|version| version.as_u64()) {
+            Some(version) if version == SCHEMA_VERSION as u64 => Ok(serde_json::from_value(value)?),
+            Some(version) => Err(Error::ParamError(format!(
+                "unknown document schema_version: {}",
+                version
+            ))),
+            None => Self::from_legacy(serde_json::from_value(value)?),
+        }
+    }
+
+    fn from_legacy(legacy: LegacyDocument) -> Result<Self, Error> {
+        // Avatar given by client may be anything: trust the signed one.
+        let avatar_hex = if !legacy.persona.is_empty() {
+            hex::encode(&legacy.persona)
+        } else {
+            serde_json::from_str::<SignPayload>(&legacy.signature_payload)?.avatar
+        };
+        let avatar = Secp256k1KeyPair::from_pubkey_hex(&avatar_hex)
+            .or_else(|_| Secp256k1KeyPair::from_pubkey_hex(&legacy.avatar))?
+            .public_key;
+
+        Ok(Self {
+            schema_version: SCHEMA_VERSION,
+            avatar: format!("0x{}", hex_public_key(&avatar)),
+            uuid: legacy.uuid,
+            platform: legacy.platform,
+            identity: legacy.identity,
+            patch: legacy.patch,
+            signature: legacy.signature,
+            created_at: legacy.created_at,
+            signature_payload: legacy.signature_payload,
+            previous_id: legacy.previous_id,
+            previous_arweave_id: legacy.previous_arweave_id,
+            signer_signatures: vec![],
+        })
+    }
+
+    /// Tags of the transaction of this document.
+    pub fn tags(&self, config: &ConfigArweaveTags) -> Vec<(String, String)> {
+        let mut tags = vec![
+            (TAG_APP_NAME.to_string(), config.app_name.clone()),
+            (TAG_APP_VERSION.to_string(), config.app_version.clone()),
+            (TAG_CONTENT_TYPE.to_string(), "application/json".to_string()),
+        ];
+        for field in config.link.iter() {
+            let tag = match field.as_str() {
+                "avatar" => Secp256k1KeyPair::from_pubkey_hex(&self.avatar)
+                    .ok()
+                    .map(|keypair| (TAG_AVATAR, format!("0x{}", compress_public_key(&keypair.public_key)))),
+                "platform" => Some((TAG_PLATFORM, self.platform.clone())),
+                "identity" => Some((TAG_IDENTITY, self.identity.clone())),
+                "uuid" => Some((TAG_UUID, self.uuid.to_string())),
+                "previous" => self
+                    .previous_arweave_id
+                    .clone()
+                    .filter(|previous| !previous.is_empty())
+                    .map(|previous| (TAG_PREVIOUS, previous)),
+                _ => None,
+            };
+            if let Some((name, value)) = tag {
+                tags.push((name.to_string(), value));
+            }
+        }
+        let mut extra: Vec<(String, String)> = config
+            .extra
+            .iter()
+            .map(|(name, value)| (name.clone(), value.clone()))
+            .collect();
+        extra.sort();
+        tags.extend(extra);
+
+        tags
+    }
+
     // If arweave configuration is missing, returns Ok("".to_string())
     pub async fn upload_to_arweave(self) -> Result<String, Error> {
         // Arweave configuration is not set. Return empty string
@@ -36,30 +216,133 @@ impl KVChainArweaveDocument {
         }
         let arweave_config = C.arweave.clone().unwrap();
 
-        // create the signer
-        let arweave_url = Url::parse(&arweave_config.url)?;
-        let client = Arweave::from_keypair_path(
-            PathBuf::from(&arweave_config.jwt),
-            arweave_url.clone()
-        )?;
-
-        let target = Base64(vec![]);
-        let data = serde_json::to_vec(&self)?;
-        // query the fee of upload and create the transaction
-        let fee = client.get_fee(target.clone(), data.clone()).await?;
-        let send_transaction = client.create_transaction(
-            target,
-            vec![],
-            data,
-            0,
-            fee,
-            true
-        ).await?;
-
-        let signed_transaction = client.sign_transaction(send_transaction)?;
-        let result = client.post_transaction(&signed_transaction).await?;
-
-        // return the transcation id to user
-        Ok(result.0)
+        self.upload_with(&arweave_config).await
+    }
+
+    /// Post this document with given wallet and gateway.  Returns id
+    /// of the transaction.
+    pub async fn upload_with(&self, config: &ConfigArwave) -> Result<String, Error> {
+        let tags = self.tags(&config.tags);
+        let data = serde_json::to_vec(self)?;
+        post_transaction(config, data, &tags).await
+    }
+
+    /// This document as an ANS-104 data item.
+    pub fn to_data_item(
+        &self,
+        signer: &dyn DataItemSigner,
+        config: &ConfigArweaveTags,
+    ) -> Result<DataItem, Error> {
+        DataItem::sign(signer, &self.tags(config), &serde_json::to_vec(self)?)
+    }
+}
+
+/// Post given data items in a single bundle transaction.  Returns id
+/// of the bundle transaction.
+pub async fn upload_bundle(items: &[DataItem]) -> Result<String, Error> {
+    if C.arweave.is_none() {
+        return Ok("".into())
+    }
+    let arweave_config = C.arweave.clone().unwrap();
+    post_transaction(&arweave_config, bundle::bundle(items)?, &bundle::bundle_tags()).await
+}
+
+async fn post_transaction(
+    arweave_config: &ConfigArwave,
+    data: Vec<u8>,
+    tags: &[(String, String)],
+) -> Result<String, Error> {
+    // create the signer
+    let arweave_url = Url::parse(&arweave_config.url)?;
+    let client = Arweave::from_keypair_path(
+        PathBuf::from(&arweave_config.jwt),
+        arweave_url.clone()
+    )?;
+
+    let target = Base64(vec![]);
+    let tags = tags
+        .iter()
+        .map(|(name, value)| Tag::<Base64>::from_utf8_strs(name, value))
+        .collect::<Result<Vec<_>, _>>()?;
+    // query the fee of upload and create the transaction
+    let fee = client.get_fee(target.clone(), data.clone()).await?;
+    // Released if posting fails.
+    let reservation = match WALLET.as_ref() {
+        Some(wallet) => Some(wallet.reserve_fee(u128::from(fee)).await?),
+        None => None,
+    };
+    // Tags are given in full: no automatic `Content-Type`.
+    let send_transaction = client.create_transaction(
+        target,
+        tags,
+        data,
+        0,
+        fee,
+        false
+    ).await?;
+
+    let signed_transaction = client.sign_transaction(send_transaction)?;
+    let result = client.post_transaction(&signed_transaction).await?;
+    if let Some(reservation) = reservation {
+        // Already paid: only log if it can't be recorded.
+        if let Err(err) = reservation.paid(&mut establish_connection()) {
+            warn!("Arweave spend of {} is not recorded: {}", result.0, err);
+        }
+    }
+
+    // return the transcation id to user
+    Ok(result.0)
+}
+
+/// Tags to search documents of this server by, optionally only the
+/// ones of given avatar.
+pub fn scan_tags(config: &ConfigArweaveTags, avatar: Option<&PublicKey>) -> Vec<(String, String)> {
+    let mut tags = vec![(TAG_APP_NAME.to_string(), config.app_name.clone())];
+    if let Some(avatar) = avatar {
+        tags.push((TAG_AVATAR.to_string(), format!("0x{}", compress_public_key(avatar))));
+    }
+    tags
+}
+
+/// Status of a posted transaction, as Arweave gateway reports.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ArweaveTxStatus {
+    /// Known by gateway, not mined yet.
+    Pending,
+    Confirmed { block_height: i64 },
+    /// Gateway doesn't know it (not propagated yet, or dropped).
+    NotFound,
+}
+
+#[derive(Deserialize, Debug)]
+struct TxStatusResponse {
+    block_height: i64,
+}
+
+/// `GET {gateway}/tx/{id}/status`
+pub async fn get_tx_status(
+    client: &HttpClient,
+    gateway: &str,
+    transaction_id: &str,
+) -> Result<ArweaveTxStatus, Error> {
+    let url = format!(
+        "{}/tx/{}/status",
+        gateway.trim_end_matches('/'),
+        transaction_id
+    );
+    let (status, body) = client.get(&url).await?;
+    match status {
+        StatusCode::OK => {
+            let response: TxStatusResponse = serde_json::from_slice(&body)?;
+            Ok(ArweaveTxStatus::Confirmed {
+                block_height: response.block_height,
+            })
+        }
+        StatusCode::ACCEPTED => Ok(ArweaveTxStatus::Pending),
+        StatusCode::NOT_FOUND => Ok(ArweaveTxStatus::NotFound),
+        other => Err(Error::General(
+            format!("Arweave gateway error: {}", other),
+            StatusCode::BAD_GATEWAY,
+        )),
     }
 }
@@ -0,0 +1,546 @@
+use std::collections::{BTreeMap, HashSet};
+
+use diesel::{Connection, PgConnection};
+use http::StatusCode;
+use libsecp256k1::PublicKey;
+use log::{info, warn};
+use serde::Deserialize;
+use serde_json::json;
+
+use super::KVChainArweaveDocument;
+use crate::{
+    config::ConfigHttpClient,
+    crypto::secp256k1::Secp256k1KeyPair,
+    error::Error,
+    model::{
+        kv_chains::{self, KVChain, NewKVChain, SignPayload},
+        multisig::{self, SignerSignature},
+    },
+    http_client::{HttpClient, UPSTREAM_ARWEAVE},
+    util::vec_to_base64,
+};
+
+/// Max links followed from a head transaction.
+const MAX_CHAIN_LENGTH: usize = 100_000;
+
+const SCAN_QUERY: &str = "query($tags: [TagFilter!], $after: String) { \
+    transactions(tags: $tags, after: $after, first: 100, sort: HEIGHT_ASC) { \
+    pageInfo { hasNextPage } edges { cursor node { id } } } }";
+
+/// Reads `KVChainArweaveDocument`s back from an Arweave gateway.
+pub struct Restorer {
+    pub gateway: String,
+    client: HttpClient,
+}
+
+#[derive(Debug, Default)]
+pub struct RestoreReport {
+    /// Links written into DB.
+    pub restored: usize,
+    /// Transactions not restored, with the reason.
+    pub skipped: Vec<(String, String)>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ScanResponse {
+    data: ScanData,
+}
+
+#[derive(Deserialize, Debug)]
+struct ScanData {
+    transactions: ScanTransactions,
+}
+
+#[derive(Deserialize, Debug)]
+struct ScanTransactions {
+    #[serde(rename = "pageInfo")]
+    page_info: ScanPageInfo,
+    edges: Vec<ScanEdge>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ScanPageInfo {
+    #[serde(rename = "hasNextPage")]
+    has_next_page: bool,
+}
+
+#[derive(Deserialize, Debug)]
+struct ScanEdge {
+    cursor: String,
+    node: ScanNode,
+}
+
+#[derive(Deserialize, Debug)]
+struct ScanNode {
+    id: String,
+}
+
+impl Restorer {
+    pub fn new(gateway: &str, client_config: ConfigHttpClient) -> Self {
+        Self {
+            gateway: gateway.trim_end_matches('/').into(),
+            client: HttpClient::new(UPSTREAM_ARWEAVE, client_config),
+        }
+    }
+
+    /// `GET {gateway}/tx/{id}/data`
+    pub async fn fetch(&self, transaction_id: &str) -> Result<KVChainArweaveDocument, Error> {
+        let url = format!("{}/tx/{}/data", self.gateway, transaction_id);
+        let (status, body) = self.client.get(&url).await?;
+        if status == StatusCode::NOT_FOUND {
+            // Data items of a bundle are not transactions: gateways
+            // serve their raw data instead.
+            let url = format!("{}/raw/{}", self.gateway, transaction_id);
+            let (status, body) = self.client.get(&url).await?;
+            Self::check_status(transaction_id, status)?;
+            return KVChainArweaveDocument::parse(&body);
+        }
+        Self::check_status(transaction_id, status)?;
+        // Data is returned in base64url.
+        let encoded = String::from_utf8_lossy(&body);
+        let data = base64::decode_config(encoded.trim(), base64::URL_SAFE_NO_PAD)?;
+        KVChainArweaveDocument::parse(&data)
+    }
+
+    fn check_status(transaction_id: &str, status: StatusCode) -> Result<(), Error> {
+        if status.is_success() {
+            Ok(())
+        } else {
+            Err(Error::General(
+                format!("Arweave gateway error on {}: {}", transaction_id, status),
+                StatusCode::BAD_GATEWAY,
+            ))
+        }
+    }
+
+    /// Documents of the chain ending at `head`, by following
+    /// `previous_arweave_id`.  Oldest first.
+    pub async fn follow(
+        &self,
+        head: &str,
+    ) -> Result<Vec<(String, KVChainArweaveDocument)>, Error> {
+        let mut chain = vec![];
+        let mut seen = HashSet::new();
+        let mut next = Some(head.to_string());
+        while let Some(transaction_id) = next {
+            if !seen.insert(transaction_id.clone()) || chain.len() >= MAX_CHAIN_LENGTH {
+                return Err(Error::General(
+                    format!("Arweave chain loops at {}", transaction_id),
+                    StatusCode::BAD_GATEWAY,
+                ));
+            }
+            let document = self.fetch(&transaction_id).await?;
+            next = document
+                .previous_arweave_id
+                .clone()
+                .filter(|previous| !previous.is_empty());
+            chain.push((transaction_id, document));
+        }
+        chain.reverse();
+
+        Ok(chain)
+    }
+
+    /// Ids of transactions having all given tags, through
+    /// `POST {gateway}/graphql`.  Oldest first.
+    pub async fn scan(&self, tags: &[(String, String)]) -> Result<Vec<String>, Error> {
+        let tag_filters: Vec<serde_json::Value> = tags
+            .iter()
+            .map(|(name, value)| json!({"name": name, "values": [value]}))
+            .collect();
+        let url = format!("{}/graphql", self.gateway);
+        let mut ids = vec![];
+        let mut after: Option<String> = None;
+        loop {
+            let request = json!({
+                "query": SCAN_QUERY,
+                "variables": {"tags": tag_filters, "after": after},
+            });
+            let (status, body) = self.client.post_json(&url, request.to_string()).await?;
+            if !status.is_success() {
+                return Err(Error::General(
+                    format!("Arweave GraphQL error: {}", status),
+                    StatusCode::BAD_GATEWAY,
+                ));
+            }
+            let response: ScanResponse = serde_json::from_slice(&body)?;
+            let transactions = response.data.transactions;
+            after = transactions.edges.last().map(|edge| edge.cursor.clone());
+            ids.extend(transactions.edges.into_iter().map(|edge| edge.node.id));
+            if !transactions.page_info.has_next_page || after.is_none() {
+                break;
+            }
+        }
+
+        Ok(ids)
+    }
+
+    /// Restore the chain ending at `head`.
+    pub async fn restore_from_head(
+        &self,
+        conn: &mut PgConnection,
+        head: &str,
+    ) -> Result<RestoreReport, Error> {
+        let documents = self.follow(head).await?;
+        restore(conn, documents)
+    }
+
+    /// Restore every document found by `scan`.  Transactions which are
+    /// not a `KVChainArweaveDocument` are skipped.
+    pub async fn restore_by_tags(
+        &self,
+        conn: &mut PgConnection,
+        tags: &[(String, String)],
+    ) -> Result<RestoreReport, Error> {
+        let mut documents = vec![];
+        let mut unreadable = vec![];
+        for transaction_id in self.scan(tags).await? {
+            match self.fetch(&transaction_id).await {
+                Ok(document) => documents.push((transaction_id, document)),
+                Err(err) => unreadable.push((transaction_id, err.to_string())),
+            }
+        }
+        let mut report = restore(conn, documents)?;
+        report.skipped.extend(unreadable);
+
+        Ok(report)
+    }
+}
+
+/// Rebuild `kv_chains` and `kv` from given documents (any order).
+/// Links of each avatar are re-ordered by `previous` of their sign
+/// payload, and every signature is verified again before applying.
+/// Avatars which already have links in DB are left untouched.
+pub fn restore(
+    conn: &mut PgConnection,
+    documents: Vec<(String, KVChainArweaveDocument)>,
+) -> Result<RestoreReport, Error> {
+    let mut report = RestoreReport::default();
+    let mut avatars: BTreeMap<Vec<u8>, Vec<(String, KVChainArweaveDocument, SignPayload)>> =
+        BTreeMap::new();
+    let mut seen = HashSet::new();
+    for (transaction_id, document) in documents {
+        let payload = match check_payload(&document) {
+            Ok(payload) => payload,
+            Err(err) => {
+                report.skipped.push((transaction_id, err.to_string()));
+                continue;
+            }
+        };
+        // Re-uploaded links share the same uuid.
+        if !seen.insert(document.uuid) {
+            report
+                .skipped
+                .push((transaction_id, format!("duplicated link {}", document.uuid)));
+            continue;
+        }
+        let persona_key = match Secp256k1KeyPair::from_pubkey_hex(&payload.avatar) {
+            Ok(keypair) => keypair.public_key,
+            Err(err) => {
+                report.skipped.push((transaction_id, err.to_string()));
+                continue;
+            }
+        };
+        avatars
+            .entry(persona_key.serialize().to_vec())
+            .or_default()
+            .push((transaction_id, document, payload));
+    }
+
+    for (persona_vec, links) in avatars {
+        let persona_key = Secp256k1KeyPair::from_pubkey_vec(&persona_vec)?.public_key;
+        restore_avatar(conn, &persona_key, links, &mut report)?;
+    }
+    info!(
+        "Restored {} link(s) from Arweave, {} skipped",
+        report.restored,
+        report.skipped.len()
+    );
+
+    Ok(report)
+}
+
+fn restore_avatar(
+    conn: &mut PgConnection,
+    persona_key: &PublicKey,
+    mut links: Vec<(String, KVChainArweaveDocument, SignPayload)>,
+    report: &mut RestoreReport,
+) -> Result<(), Error> {
+    if KVChain::find_last_link(conn, persona_key)?.is_some() {
+        for (transaction_id, _, _) in links {
+            report
+                .skipped
+                .push((transaction_id, "avatar already has links in DB".into()));
+        }
+        return Ok(());
+    }
+
+    let mut previous: Option<KVChain> = None;
+    loop {
+        let previous_signature = previous
+            .as_ref()
+            .map(|link| vec_to_base64(&link.signature));
+        let position = match links
+            .iter()
+            .position(|(_, _, payload)| payload.previous == previous_signature)
+        {
+            Some(position) => position,
+            None => break,
+        };
+        let (transaction_id, document, payload) = links.remove(position);
+        match restore_link(conn, persona_key, previous.as_ref(), &transaction_id, document, payload) {
+            Ok(link) => {
+                report.restored += 1;
+                previous = Some(link);
+            }
+            Err(err) => {
+                warn!("Arweave transaction {} is not restored: {}", transaction_id, err);
+                report.skipped.push((transaction_id, err.to_string()));
+                break;
+            }
+        }
+    }
+    for (transaction_id, _, _) in links {
+        report
+            .skipped
+            .push((transaction_id, "not linked to restored chain".into()));
+    }
+
+    Ok(())
+}
+
+fn restore_link(
+    conn: &mut PgConnection,
+    persona_key: &PublicKey,
+    previous: Option<&KVChain>,
+    transaction_id: &str,
+    document: KVChainArweaveDocument,
+    payload: SignPayload,
+) -> Result<KVChain, Error> {
+    let new_kv = NewKVChain {
+        uuid: document.uuid,
+        persona: persona_key.serialize().to_vec(),
+        platform: document.platform,
+        identity: document.identity,
+        patch: document.patch,
+        previous_id: previous.map(|link| link.id),
+        signature: document.signature,
+        signature_payload: document.signature_payload,
+        created_at: document.created_at,
+        action: kv_chains::parse_action(payload.action)?,
+    };
+    verify_signature(conn, &new_kv, &document.signer_signatures)?;
+
+    conn.transaction::<_, Error, _>(|conn| {
+        let mut link = new_kv.finalize(conn)?;
+        if !document.signer_signatures.is_empty() {
+            link = link.insert_signer_signatures(conn, &document.signer_signatures)?;
+        }
+        link.perform_patch(conn)?;
+        link.insert_arweave_id(conn, Some(transaction_id.into()))?;
+        Ok(link)
+    })
+}
+
+/// Sign payload of given document, which must describe the same link.
+fn check_payload(document: &KVChainArweaveDocument) -> Result<SignPayload, Error> {
+    let payload: SignPayload = serde_json::from_str(&document.signature_payload)?;
+    if payload.uuid != document.uuid
+        || payload.platform != document.platform
+        || payload.identity != document.identity
+        || payload.patch != document.patch
+        || payload.created_at != document.created_at.timestamp()
+    {
+        return Err(Error::SignatureValidationError(
+            "document differs from its sign payload".into(),
+        ));
+    }
+
+    Ok(payload)
+}
+
+/// Signed by avatar itself, or by enough signers of its m-of-n
+/// settings restored so far.
+fn verify_signature(
+    conn: &mut PgConnection,
+    new_kv: &NewKVChain,
+    signer_signatures: &[SignerSignature],
+) -> Result<(), Error> {
+    match multisig::find_by_persona(conn, &new_kv.persona)? {
+        Some(multisig) => multisig
+            .setting()?
+            .verify_signatures(signer_signatures, &new_kv.signature_payload),
+        None => new_kv.validate(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDateTime;
+    use fake::{Fake, Faker};
+    use serde_json::Value;
+
+    use super::*;
+    use crate::{
+        crypto::util::hex_public_key,
+        model::{arweave::SCHEMA_VERSION, establish_connection, kv},
+        http_client::tests::{config, mock_server},
+        util::timestamp_to_naive,
+    };
+
+    /// A signed document, as uploaded by `worker::arweave`.
+    fn signed_document(
+        keypair: &Secp256k1KeyPair,
+        identity: &str,
+        patch: Value,
+        previous: Option<(&str, &KVChainArweaveDocument)>,
+    ) -> KVChainArweaveDocument {
+        let created_at: NaiveDateTime = timestamp_to_naive(1_700_000_000);
+        let payload = SignPayload {
+            version: "1".into(),
+            uuid: ::uuid::Uuid::new_v4(),
+            avatar: hex_public_key(&keypair.public_key),
+            platform: "twitter".into(),
+            identity: identity.into(),
+            patch: patch.clone(),
+            created_at: created_at.timestamp(),
+            previous: previous.map(|(_, document)| vec_to_base64(&document.signature)),
+            action: None,
+        };
+        let signature_payload = serde_json::to_string(&payload).unwrap();
+        KVChainArweaveDocument {
+            schema_version: SCHEMA_VERSION,
+            avatar: format!("0x{}", hex_public_key(&keypair.public_key)),
+            uuid: payload.uuid,
+            platform: payload.platform,
+            identity: identity.into(),
+            patch,
+            signature: keypair.personal_sign(&signature_payload).unwrap(),
+            created_at,
+            signature_payload,
+            previous_id: None,
+            previous_arweave_id: previous.map(|(transaction_id, _)| transaction_id.to_string()),
+            signer_signatures: vec![],
+        }
+    }
+
+    /// Body of `GET /tx/{id}/data`.
+    fn data_body(document: &KVChainArweaveDocument) -> String {
+        base64::encode_config(serde_json::to_vec(document).unwrap(), base64::URL_SAFE_NO_PAD)
+    }
+
+    fn find_kv(conn: &mut PgConnection, keypair: &Secp256k1KeyPair, identity: &str) -> kv::KV {
+        kv::find_or_create(conn, "twitter", identity, &keypair.public_key)
+            .unwrap()
+            .0
+    }
+
+    #[tokio::test]
+    async fn test_restore_from_head() {
+        let mut conn = establish_connection();
+        let keypair = Secp256k1KeyPair::generate();
+        let identity: String = Faker.fake();
+        let first = signed_document(&keypair, &identity, json!({"a": 1, "b": 2}), None);
+        let second = signed_document(&keypair, &identity, json!({"a": null, "c": 3}), Some(("tx-1", &first)));
+        // Followed from head backwards.
+        let (gateway, hits) = mock_server(vec![(200, data_body(&second), 0), (200, data_body(&first), 0)]).await;
+        let restorer = Restorer::new(&gateway, config());
+
+        let report = restorer.restore_from_head(&mut conn, "tx-2").await.unwrap();
+        assert_eq!(2, hits.load(std::sync::atomic::Ordering::SeqCst));
+        assert_eq!(2, report.restored);
+        assert!(report.skipped.is_empty());
+
+        let record = find_kv(&mut conn, &keypair, &identity);
+        assert_eq!(json!({"b": 2, "c": 3}), record.content);
+        assert_eq!(Some("tx-2".to_string()), record.arweave_id);
+        let links = kv_chains::find_all_by_identity(&mut conn, "twitter", &identity).unwrap();
+        assert_eq!(2, links.len());
+        let restored_second = links.iter().find(|link| link.uuid == second.uuid).unwrap();
+        let restored_first = links.iter().find(|link| link.uuid == first.uuid).unwrap();
+        assert_eq!(Some(restored_first.id), restored_second.previous_id);
+        assert_eq!(Some("tx-1".to_string()), restored_first.arweave_id());
+
+        // Not twice.
+        let report = restore(&mut conn, vec![("tx-1".into(), first)]).unwrap();
+        assert_eq!(0, report.restored);
+        assert_eq!(1, report.skipped.len());
+    }
+
+    #[tokio::test]
+    async fn test_fetch_data_item() {
+        let keypair = Secp256k1KeyPair::generate();
+        let document = signed_document(&keypair, "yeiwb", json!({"a": 1}), None);
+        let raw = serde_json::to_string(&document).unwrap();
+        // `/tx/{id}/data` does not know data items, `/raw/{id}` does.
+        let (gateway, hits) = mock_server(vec![(404, "Not Found".into(), 0), (200, raw, 0)]).await;
+        let restorer = Restorer::new(&gateway, config());
+
+        assert_eq!(document, restorer.fetch("item-1").await.unwrap());
+        assert_eq!(2, hits.load(std::sync::atomic::Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn test_reject_tampered() {
+        let mut conn = establish_connection();
+        let keypair = Secp256k1KeyPair::generate();
+        let identity: String = Faker.fake();
+        let first = signed_document(&keypair, &identity, json!({"a": 1}), None);
+        let mut second = signed_document(&keypair, &identity, json!({"a": 2}), Some(("tx-1", &first)));
+        let third = signed_document(&keypair, &identity, json!({"a": 3}), Some(("tx-2", &second)));
+
+        // Patch differs from signed one.
+        let mut tampered = second.clone();
+        tampered.patch = json!({"a": "evil"});
+        let report = restore(&mut conn, vec![("tx-2".into(), tampered)]).unwrap();
+        assert_eq!(0, report.restored);
+
+        // Signed by someone else.
+        second.signature = Secp256k1KeyPair::generate()
+            .personal_sign(&second.signature_payload)
+            .unwrap();
+        let report = restore(
+            &mut conn,
+            vec![("tx-3".into(), third), ("tx-2".into(), second), ("tx-1".into(), first)],
+        )
+        .unwrap();
+        // Chain stops at forged link.
+        assert_eq!(1, report.restored);
+        assert_eq!(2, report.skipped.len());
+        assert_eq!(json!({"a": 1}), find_kv(&mut conn, &keypair, &identity).content);
+    }
+
+    #[tokio::test]
+    async fn test_restore_by_tags() {
+        let mut conn = establish_connection();
+        let keypair = Secp256k1KeyPair::generate();
+        let identity: String = Faker.fake();
+        let first = signed_document(&keypair, &identity, json!({"a": 1}), None);
+        // Uploaded before `previous_arweave_id` was filled.
+        let mut second = signed_document(&keypair, &identity, json!({"b": 2}), Some(("", &first)));
+        second.previous_arweave_id = None;
+        let (gateway, _hits) = mock_server(vec![
+            (
+                200,
+                r#"{"data": {"transactions": {"pageInfo": {"hasNextPage": false}, "edges": [
+                    {"cursor": "c1", "node": {"id": "tx-2"}},
+                    {"cursor": "c2", "node": {"id": "tx-other"}},
+                    {"cursor": "c3", "node": {"id": "tx-1"}}
+                ]}}}"#
+                .into(),
+                0,
+            ),
+            (200, data_body(&second), 0),
+            (200, "not-a-document".into(), 0),
+            (200, data_body(&first), 0),
+        ])
+        .await;
+        let restorer = Restorer::new(&gateway, config());
+        let tags = vec![("App-Name".to_string(), "kv_server".to_string())];
+
+        let report = restorer.restore_by_tags(&mut conn, &tags).await.unwrap();
+        assert_eq!(2, report.restored);
+        assert_eq!(vec!["tx-other".to_string()], report.skipped.iter().map(|(id, _)| id.clone()).collect::<Vec<_>>());
+        assert_eq!(json!({"a": 1, "b": 2}), find_kv(&mut conn, &keypair, &identity).content);
+    }
+}
@@ -7,16 +7,18 @@ mod tests {
     use url::Url;
     use uuid::Uuid;
 
-    use crate::model::arweave::KVChainArweaveDocument;
-    use crate::config::C;
-    use crate::util::naive_now;
+    use crate::model::arweave::{scan_tags, KVChainArweaveDocument, SCHEMA_VERSION};
+    use serde_json::json;
+    use crate::config::{ConfigArweaveTags, C};
+    use crate::crypto::{secp256k1::Secp256k1KeyPair, util::{compress_public_key, hex_public_key}};
+    use crate::util::{naive_now, timestamp_to_naive};
 
 
     fn prepare_test_document() -> KVChainArweaveDocument {
         KVChainArweaveDocument{
+            schema_version: SCHEMA_VERSION,
             avatar: "sample".into(),
             uuid: Uuid::new_v4(),
-            persona: vec![],
             platform: "twitter".into(),
             identity: "".into(),
             patch: "".into(),
@@ -25,6 +27,7 @@ mod tests {
             signature_payload: "".into(),
             previous_id: None,
             previous_arweave_id: None,
+            signer_signatures: vec![],
         }
     }
 
@@ -66,4 +69,107 @@ mod tests {
         assert!(status_code == 200 || status_code == 202);
     }
 
+    #[test]
+    fn test_tags() {
+        let keypair = Secp256k1KeyPair::generate();
+        let avatar = format!("0x{}", compress_public_key(&keypair.public_key));
+        let mut document = prepare_test_document();
+        document.avatar = format!("0x{}", hex_public_key(&keypair.public_key));
+        document.identity = "alice".into();
+        document.previous_arweave_id = Some("tx-previous".into());
+        let mut config = ConfigArweaveTags::default();
+        config.extra.insert("Network".into(), "testnet".into());
+
+        let tags = document.tags(&config);
+        let tag = |name: &str| tags.iter().find(|(n, _)| n == name).map(|(_, v)| v.clone());
+        assert_eq!(Some("kv_server".to_string()), tag("App-Name"));
+        assert_eq!(Some(env!("CARGO_PKG_VERSION").to_string()), tag("App-Version"));
+        assert_eq!(Some("application/json".to_string()), tag("Content-Type"));
+        assert_eq!(Some(avatar.clone()), tag("Avatar"));
+        assert_eq!(Some("twitter".to_string()), tag("Platform"));
+        assert_eq!(Some("alice".to_string()), tag("Identity"));
+        assert_eq!(Some(document.uuid.to_string()), tag("Uuid"));
+        assert_eq!(Some("tx-previous".to_string()), tag("Previous-Tx"));
+        assert_eq!(Some("testnet".to_string()), tag("Network"));
+
+        // Per-link tags are configurable.
+        config.link = vec!["uuid".into()];
+        document.previous_arweave_id = None;
+        let tags = document.tags(&config);
+        assert_eq!(5, tags.len());
+        assert!(!tags.iter().any(|(name, _)| name == "Avatar" || name == "Previous-Tx"));
+
+        let scan = scan_tags(&config, Some(&keypair.public_key));
+        assert_eq!(
+            vec![("App-Name".to_string(), "kv_server".to_string()), ("Avatar".to_string(), avatar)],
+            scan
+        );
+    }
+
+    #[test]
+    fn test_document_encoding() {
+        let keypair = Secp256k1KeyPair::generate();
+        let mut document = prepare_test_document();
+        document.avatar = format!("0x{}", hex_public_key(&keypair.public_key));
+        document.signature = vec![1, 2, 3];
+        document.created_at = timestamp_to_naive(1_700_000_000);
+
+        let value = serde_json::to_value(&document).unwrap();
+        assert_eq!(json!(2), value["schema_version"]);
+        assert_eq!(json!("AQID"), value["signature"]);
+        assert_eq!(json!(1_700_000_000), value["created_at"]);
+        assert!(value.get("persona").is_none());
+
+        let parsed = KVChainArweaveDocument::parse(&serde_json::to_vec(&value).unwrap()).unwrap();
+        assert_eq!(document, parsed);
+
+        let mut unknown = value.clone();
+        unknown["schema_version"] = json!(99);
+        assert!(KVChainArweaveDocument::parse(&serde_json::to_vec(&unknown).unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_parse_legacy_document() {
+        let keypair = Secp256k1KeyPair::generate();
+        let uuid = Uuid::new_v4();
+        let signature_payload = json!({
+            "version": "1",
+            "uuid": uuid,
+            "avatar": hex_public_key(&keypair.public_key),
+            "platform": "twitter",
+            "identity": "alice",
+            "patch": {"a": 1},
+            "created_at": 1_700_000_000,
+            "previous": null,
+        })
+        .to_string();
+        // As uploaded by old `upload::controller`: raw avatar given by
+        // client, empty persona, signature in number array.
+        let legacy = json!({
+            "avatar": compress_public_key(&keypair.public_key),
+            "uuid": uuid,
+            "persona": [],
+            "platform": "twitter",
+            "identity": "alice",
+            "patch": {"a": 1},
+            "signature": [1, 2, 3],
+            "created_at": "2023-11-14T22:13:20",
+            "signature_payload": signature_payload,
+            "previous_id": 42,
+            "previous_arweave_id": null,
+        });
+
+        let parsed = KVChainArweaveDocument::parse(&serde_json::to_vec(&legacy).unwrap()).unwrap();
+        assert_eq!(SCHEMA_VERSION, parsed.schema_version);
+        assert_eq!(format!("0x{}", hex_public_key(&keypair.public_key)), parsed.avatar);
+        assert_eq!(vec![1, 2, 3], parsed.signature);
+        assert_eq!(1_700_000_000, parsed.created_at.timestamp());
+        assert_eq!(Some(42), parsed.previous_id);
+
+        // Avatar is empty.
+        let mut empty_avatar = legacy.clone();
+        empty_avatar["avatar"] = json!("");
+        let parsed = KVChainArweaveDocument::parse(&serde_json::to_vec(&empty_avatar).unwrap()).unwrap();
+        assert_eq!(format!("0x{}", hex_public_key(&keypair.public_key)), parsed.avatar);
+    }
 }
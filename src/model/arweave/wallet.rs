@@ -0,0 +1,417 @@
+//! Balance and spend of the Arweave wallet which pays for uploads.
+
+use std::{
+    path::Path,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use diesel::PgConnection;
+use http::StatusCode;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    config::{ConfigArwave, ConfigArweaveWallet, ConfigHttpClient, C},
+    error::Error,
+    metrics,
+    model::arweave_spend::ArweaveSpend,
+    util::naive_now,
+    http_client::{HttpClient, UPSTREAM_ARWEAVE},
+};
+
+lazy_static! {
+    /// Wallet of `C.arweave`.  `None` if Arweave is not configured, or
+    /// its key file can't be read.
+    pub static ref WALLET: Option<Wallet> = C.arweave.as_ref().and_then(|config| {
+        Wallet::from_config(config)
+            .map_err(|err| warn!("Arweave wallet is not monitored: {}", err))
+            .ok()
+    });
+}
+
+pub struct Wallet {
+    /// Base64url of SHA-256 of public key (`n` of JWK).
+    pub address: String,
+    gateway: String,
+    /// Winston.
+    reserve: u128,
+    balance_ttl: Duration,
+    client: HttpClient,
+    cached: Mutex<Cached>,
+}
+
+/// What is known of the wallet in this process.
+#[derive(Default)]
+struct Cached {
+    /// Balance last fetched from gateway (minus fees paid since).
+    balance: Option<u128>,
+    /// Last balance fetch, successful or not.
+    checked_at: Option<Instant>,
+    /// Error of last balance fetch, if it failed.
+    error: Option<String>,
+    /// Today's spend, as last read or recorded.
+    spend: Option<ArweaveSpend>,
+    /// Fees of transactions being posted (see `FeeReservation`).
+    reserved: u128,
+}
+
+/// Fee taken from available balance by `Wallet::reserve_fee` while its
+/// transaction is posted.  Released on drop, unless `paid`.
+pub struct FeeReservation<'a> {
+    wallet: &'a Wallet,
+    fee: u128,
+}
+
+impl FeeReservation<'_> {
+    /// Transaction is posted: record its fee (see `Wallet::record_spend`).
+    pub fn paid(self, conn: &mut PgConnection) -> Result<(), Error> {
+        self.wallet.record_spend(conn, self.fee)
+    }
+}
+
+impl Drop for FeeReservation<'_> {
+    fn drop(&mut self) {
+        let mut cached = self.wallet.cached.lock().unwrap();
+        cached.reserved = cached.reserved.saturating_sub(self.fee);
+    }
+}
+
+/// Wallet numbers shown by `/healthz`.  Winston amounts are decimal
+/// strings, as they may not fit in a JSON number.
+#[derive(Serialize, Debug)]
+pub struct WalletStatus {
+    pub address: String,
+    /// Last known balance.  `None` if never fetched.
+    pub balance: Option<String>,
+    /// Error of last balance fetch.  `balance` is then a stale one.
+    pub balance_error: Option<String>,
+    pub reserve: String,
+    /// Balance is below reserve: uploads are refused.
+    pub below_reserve: bool,
+    /// Fees paid today (UTC).  `None` if not known yet.
+    pub spent_today: Option<String>,
+    pub transactions_today: Option<i32>,
+}
+
+#[derive(Deserialize)]
+struct JwkOwner {
+    n: String,
+}
+
+impl Wallet {
+    /// Wallet of given key file (JWK).
+    pub fn from_config(config: &ConfigArwave) -> Result<Self, Error> {
+        let jwk = std::fs::read(Path::new(&config.jwt)).map_err(|e| {
+            Error::General(format!("Arweave wallet: {}", e), StatusCode::INTERNAL_SERVER_ERROR)
+        })?;
+        let owner: JwkOwner = serde_json::from_slice(&jwk)?;
+        let owner = base64::decode_config(owner.n, base64::URL_SAFE_NO_PAD)?;
+
+        Ok(Self::new(
+            &address_of(&owner),
+            &config.url,
+            &config.wallet,
+            config.client.clone(),
+        ))
+    }
+
+    pub fn new(
+        address: &str,
+        gateway: &str,
+        config: &ConfigArweaveWallet,
+        client_config: ConfigHttpClient,
+    ) -> Self {
+        Self {
+            address: address.into(),
+            gateway: gateway.trim_end_matches('/').into(),
+            reserve: config.reserve as u128,
+            balance_ttl: Duration::from_secs(config.balance_ttl),
+            client: HttpClient::new(UPSTREAM_ARWEAVE, client_config),
+            cached: Mutex::new(Cached::default()),
+        }
+    }
+
+    /// Current balance (winston).  Fetched (and failed) balance is
+    /// cached for `balance_ttl`.
+    pub async fn balance(&self) -> Result<u128, Error> {
+        if let Some(result) = self.fresh_balance() {
+            return result;
+        }
+        self.fetch_balance(true).await
+    }
+
+    /// Reserve `fee` of a transaction to post.  Refused (`402`) if it
+    /// would take balance, minus fees reserved by others, below reserve.
+    pub async fn reserve_fee(&self, fee: u128) -> Result<FeeReservation<'_>, Error> {
+        let fetched = self.balance().await?;
+
+        // Checked and taken at once: concurrent uploads can't all pass
+        // on the same balance.
+        let mut cached = self.cached.lock().unwrap();
+        let available = cached
+            .balance
+            .unwrap_or(fetched)
+            .saturating_sub(cached.reserved);
+        if available < fee.saturating_add(self.reserve) {
+            metrics::ARWEAVE_UPLOADS_REFUSED.inc();
+            return Err(Error::General(
+                format!(
+                    "Arweave wallet balance {} cannot pay fee {} above reserve {} (winston)",
+                    available, fee, self.reserve
+                ),
+                StatusCode::PAYMENT_REQUIRED,
+            ));
+        }
+        cached.reserved += fee;
+
+        Ok(FeeReservation { wallet: self, fee })
+    }
+
+    /// Record `fee` of a posted transaction.
+    pub fn record_spend(&self, conn: &mut PgConnection, fee: u128) -> Result<(), Error> {
+        if let Some(balance) = self.cached.lock().unwrap().balance.as_mut() {
+            *balance = balance.saturating_sub(fee);
+            metrics::ARWEAVE_WALLET_BALANCE.set(saturating_i64(*balance));
+        }
+        let spend = ArweaveSpend::record(conn, saturating_i64(fee))?;
+        self.cache_spend(spend);
+        Ok(())
+    }
+
+    /// Read today's spend, which may be paid by another process (see
+    /// `arweave_worker`).  Updates metrics, too.
+    pub fn refresh_spend(&self, conn: &mut PgConnection) -> Result<(), Error> {
+        self.cache_spend(ArweaveSpend::today(conn)?);
+        Ok(())
+    }
+
+    /// Last known balance, reserve and today's spend.  Balance is
+    /// fetched once (not retried) if older than `balance_ttl`; spend is
+    /// not read from database.  Updates metrics, too.
+    pub async fn status(&self) -> WalletStatus {
+        if self.fresh_balance().is_none() {
+            // Failure is kept in `cached`.
+            let _ = self.fetch_balance(false).await;
+        }
+        metrics::ARWEAVE_WALLET_RESERVE.set(saturating_i64(self.reserve));
+
+        let cached = self.cached.lock().unwrap();
+        let spend = cached
+            .spend
+            .as_ref()
+            .filter(|spend| spend.day == naive_now().date());
+        WalletStatus {
+            address: self.address.clone(),
+            balance: cached.balance.map(|balance| balance.to_string()),
+            balance_error: cached.error.clone(),
+            reserve: self.reserve.to_string(),
+            below_reserve: cached.balance.map(|balance| balance < self.reserve).unwrap_or(false),
+            spent_today: spend.map(|spend| spend.winston.to_string()),
+            transactions_today: spend.map(|spend| spend.transactions),
+        }
+    }
+
+    /// Cached result of last balance fetch, if done in `balance_ttl`.
+    fn fresh_balance(&self) -> Option<Result<u128, Error>> {
+        let cached = self.cached.lock().unwrap();
+        let checked_at = cached.checked_at?;
+        if checked_at.elapsed() >= self.balance_ttl {
+            return None;
+        }
+        Some(match (&cached.error, cached.balance) {
+            (None, Some(balance)) => Ok(balance),
+            (error, _) => Err(Error::General(
+                error.clone().unwrap_or_default(),
+                StatusCode::BAD_GATEWAY,
+            )),
+        })
+    }
+
+    /// `GET {gateway}/wallet/{address}/balance`, in winston.  Result
+    /// is cached, failure too.
+    async fn fetch_balance(&self, retry: bool) -> Result<u128, Error> {
+        let url = format!("{}/wallet/{}/balance", self.gateway, self.address);
+        let response = if retry {
+            self.client.get(&url).await
+        } else {
+            self.client.get_once(&url).await
+        };
+        let result = response.and_then(parse_balance);
+
+        let mut cached = self.cached.lock().unwrap();
+        cached.checked_at = Some(Instant::now());
+        match &result {
+            Ok(balance) => {
+                cached.balance = Some(*balance);
+                cached.error = None;
+                metrics::ARWEAVE_WALLET_BALANCE.set(saturating_i64(*balance));
+            }
+            Err(err) => {
+                warn!("Arweave wallet balance: {}", err);
+                cached.error = Some(err.to_string());
+            }
+        }
+        result
+    }
+
+    fn cache_spend(&self, spend: ArweaveSpend) {
+        metrics::ARWEAVE_SPENT_TODAY.set(spend.winston);
+        metrics::ARWEAVE_TRANSACTIONS_TODAY.set(spend.transactions as i64);
+        self.cached.lock().unwrap().spend = Some(spend);
+    }
+}
+
+/// Arweave address of given public key (`n` of JWK).
+pub fn address_of(owner: &[u8]) -> String {
+    base64::encode_config(Sha256::digest(owner), base64::URL_SAFE_NO_PAD)
+}
+
+/// Balance (winston) in given response of `/wallet/{address}/balance`.
+fn parse_balance((status, body): (StatusCode, Vec<u8>)) -> Result<u128, Error> {
+    if status != StatusCode::OK {
+        return Err(Error::General(
+            format!("Arweave gateway error: {}", status),
+            StatusCode::BAD_GATEWAY,
+        ));
+    }
+    String::from_utf8_lossy(&body).trim().parse().map_err(|_| {
+        Error::General(
+            "Arweave gateway returned an invalid balance".into(),
+            StatusCode::BAD_GATEWAY,
+        )
+    })
+}
+
+fn saturating_i64(winston: u128) -> i64 {
+    i64::try_from(winston).unwrap_or(i64::MAX)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::Ordering;
+
+    use super::*;
+    use crate::{
+        model::establish_connection,
+        http_client::tests::{config, mock_server},
+    };
+
+    fn wallet(gateway: &str, reserve: u64) -> Wallet {
+        Wallet::new(
+            "wallet-address",
+            gateway,
+            &ConfigArweaveWallet {
+                reserve,
+                balance_ttl: 60,
+            },
+            config(),
+        )
+    }
+
+    /// Wallet which fetches balance every time, with retries.
+    fn uncached_wallet(gateway: &str) -> Wallet {
+        Wallet::new(
+            "wallet-address",
+            gateway,
+            &ConfigArweaveWallet {
+                reserve: 0,
+                balance_ttl: 0,
+            },
+            ConfigHttpClient {
+                retries: 2,
+                ..config()
+            },
+        )
+    }
+
+    #[test]
+    fn test_address_of() {
+        assert_eq!(
+            base64::encode_config(Sha256::digest([1, 2, 3]), base64::URL_SAFE_NO_PAD),
+            address_of(&[1, 2, 3])
+        );
+        assert_eq!(43, address_of(&[7; 512]).len());
+    }
+
+    #[tokio::test]
+    async fn test_balance_is_cached() {
+        let (gateway, hits) = mock_server(vec![(200, "1000\n", 0)]).await;
+        let wallet = wallet(&gateway, 0);
+
+        assert_eq!(1000, wallet.balance().await.unwrap());
+        assert_eq!(1000, wallet.balance().await.unwrap());
+        assert_eq!(1, hits.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn test_refuse_below_reserve() {
+        let (gateway, _hits) = mock_server(vec![(200, "1000", 0)]).await;
+        let wallet = wallet(&gateway, 500);
+
+        assert!(wallet.reserve_fee(500).await.is_ok());
+        let err = wallet.reserve_fee(501).await.err().unwrap();
+        assert_eq!(StatusCode::PAYMENT_REQUIRED, err.http_status());
+
+        // Paid fees are taken from cached balance.
+        let mut conn = establish_connection();
+        wallet.reserve_fee(400).await.unwrap().paid(&mut conn).unwrap();
+        assert_eq!(600, wallet.balance().await.unwrap());
+        assert!(wallet.reserve_fee(101).await.is_err());
+
+        let status = wallet.status().await;
+        assert_eq!(Some("600".to_string()), status.balance);
+        assert_eq!("500", status.reserve);
+        assert!(!status.below_reserve);
+        assert!(status.transactions_today.unwrap() >= 1);
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_reservations() {
+        let (gateway, _hits) = mock_server(vec![(200, "1000", 0)]).await;
+        let wallet = wallet(&gateway, 500);
+
+        let first = wallet.reserve_fee(300).await.unwrap();
+        // Balance covers only one of them above reserve.
+        assert!(wallet.reserve_fee(300).await.is_err());
+        drop(first);
+        assert!(wallet.reserve_fee(300).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_gateway_error() {
+        let (gateway, hits) = mock_server(vec![(404, "Not Found", 0)]).await;
+        let wallet = wallet(&gateway, 0);
+
+        let err = wallet.balance().await.unwrap_err();
+        assert_eq!(StatusCode::BAD_GATEWAY, err.http_status());
+        // Failure is cached, too.
+        let err = wallet.balance().await.unwrap_err();
+        assert_eq!(StatusCode::BAD_GATEWAY, err.http_status());
+        let status = wallet.status().await;
+        assert_eq!(None, status.balance);
+        assert!(status.balance_error.is_some());
+        assert!(!status.below_reserve);
+        assert_eq!(None, status.spent_today);
+        assert_eq!(1, hits.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn test_status_serves_last_known() {
+        let (gateway, hits) = mock_server(vec![(200, "1000", 0), (503, "busy", 0)]).await;
+        let wallet = uncached_wallet(&gateway);
+
+        assert_eq!(Some("1000".to_string()), wallet.status().await.balance);
+        assert_eq!(1, hits.load(Ordering::SeqCst));
+
+        // Fetched once, not retried; stale balance is still shown.
+        let status = wallet.status().await;
+        assert_eq!(2, hits.load(Ordering::SeqCst));
+        assert_eq!(Some("1000".to_string()), status.balance);
+        assert!(status.balance_error.is_some());
+
+        wallet.refresh_spend(&mut establish_connection()).unwrap();
+        assert!(wallet.status().await.spent_today.is_some());
+    }
+}
@@ -0,0 +1,252 @@
+mod tests;
+
+use chrono::{Duration, NaiveDateTime};
+use diesel::{insert_into, prelude::*, PgConnection};
+use log::error;
+
+use crate::{
+    config::{ConfigArweaveOutbox, C},
+    error::Error,
+    model::{
+        archive::{mirror_names, SINK_ARWEAVE},
+        kv_chains::KVChain,
+    },
+    schema::{kv_arweave_outbox, kv_arweave_outbox::dsl::*},
+    util::naive_now,
+};
+
+/// `state` of an entry waiting for (next) attempt.
+pub const OUTBOX_PENDING: &str = "pending";
+/// `state` of an uploaded entry.
+pub const OUTBOX_DONE: &str = "done";
+/// `state` of an entry refused by `archive::ensure_public`: its link is
+/// never archived, and `last_error` has the reason.
+pub const OUTBOX_SKIPPED: &str = "skipped";
+/// `state` of an entry given up after `max_attempts` failures (dead
+/// letter).  `requeue` brings it back.
+pub const OUTBOX_DEAD: &str = "dead";
+
+/// A KVChain link waiting to be uploaded to Arweave, or to another
+/// archive sink (see `model::archive`).
+#[derive(Identifiable, Queryable, Debug, Clone)]
+#[diesel(table_name = kv_arweave_outbox)]
+pub struct ArweaveOutbox {
+    pub id: i32,
+    pub kv_chain_id: i32,
+    pub attempts: i32,
+    pub last_error: Option<String>,
+    pub next_attempt_at: NaiveDateTime,
+    pub created_at: NaiveDateTime,
+    /// Set once `state` is a final one (not `OUTBOX_PENDING`).
+    pub done_at: Option<NaiveDateTime>,
+    /// See `OUTBOX_*`.
+    pub state: String,
+    /// Name of archive sink.
+    pub sink: String,
+}
+
+#[derive(Insertable, Debug)]
+#[diesel(table_name = kv_arweave_outbox)]
+struct NewArweaveOutbox {
+    pub kv_chain_id: i32,
+    pub sink: String,
+}
+
+impl ArweaveOutbox {
+    /// Queue given link for uploading to Arweave, and to every other
+    /// sink in `C.archive`.  Call it in the same transaction which
+    /// finalizes the link, so no link is lost.  Returns the Arweave one.
+    pub fn enqueue(conn: &mut PgConnection, link: &KVChain) -> Result<ArweaveOutbox, Error> {
+        for mirror in mirror_names(&C.archive) {
+            Self::enqueue_sink(conn, link, mirror)?;
+        }
+        Self::enqueue_sink(conn, link, SINK_ARWEAVE)
+    }
+
+    pub fn enqueue_sink(
+        conn: &mut PgConnection,
+        link: &KVChain,
+        sink_given: &str,
+    ) -> Result<ArweaveOutbox, Error> {
+        insert_into(kv_arweave_outbox)
+            .values(&NewArweaveOutbox {
+                kv_chain_id: link.id,
+                sink: sink_given.into(),
+            })
+            .get_result(conn)
+            .map_err(|e| e.into())
+    }
+
+    /// Queue given link for uploading again (e.g. its transaction is
+    /// dropped).  Links uploaded before the outbox existed get a new entry.
+    pub fn requeue(conn: &mut PgConnection, link: &KVChain) -> Result<ArweaveOutbox, Error> {
+        match Self::find_by_kv_chain(conn, link.id)? {
+            Some(entry) => diesel::update(&entry)
+                .set((
+                    done_at.eq(None::<NaiveDateTime>),
+                    state.eq(OUTBOX_PENDING),
+                    attempts.eq(0),
+                    next_attempt_at.eq(naive_now()),
+                ))
+                .get_result(conn)
+                .map_err(|e| e.into()),
+            None => Self::enqueue_sink(conn, link, SINK_ARWEAVE),
+        }
+    }
+
+    /// Arweave entry of given link.
+    pub fn find_by_kv_chain(
+        conn: &mut PgConnection,
+        kv_chain_id_given: i32,
+    ) -> Result<Option<ArweaveOutbox>, Error> {
+        Self::find_by_kv_chain_sink(conn, kv_chain_id_given, SINK_ARWEAVE)
+    }
+
+    pub fn find_by_kv_chain_sink(
+        conn: &mut PgConnection,
+        kv_chain_id_given: i32,
+        sink_given: &str,
+    ) -> Result<Option<ArweaveOutbox>, Error> {
+        kv_arweave_outbox
+            .filter(kv_chain_id.eq(kv_chain_id_given))
+            .filter(sink.eq(sink_given))
+            .first(conn)
+            .optional()
+            .map_err(|e| e.into())
+    }
+
+    /// Claim at most `config.batch` due Arweave uploads (oldest link
+    /// first).  Claimed ones are hidden from other workers for
+    /// `config.lease` seconds.
+    pub fn claim_due(
+        conn: &mut PgConnection,
+        config: &ConfigArweaveOutbox,
+    ) -> Result<Vec<ArweaveOutbox>, Error> {
+        Self::claim_due_sink(conn, SINK_ARWEAVE, config)
+    }
+
+    /// `claim_due` of given sink.
+    pub fn claim_due_sink(
+        conn: &mut PgConnection,
+        sink_given: &str,
+        config: &ConfigArweaveOutbox,
+    ) -> Result<Vec<ArweaveOutbox>, Error> {
+        conn.transaction::<_, Error, _>(|conn| {
+            let now = naive_now();
+            let due: Vec<ArweaveOutbox> = kv_arweave_outbox
+                .filter(sink.eq(sink_given))
+                .filter(done_at.is_null())
+                .filter(next_attempt_at.le(now))
+                .order(id.asc())
+                .limit(config.batch)
+                .for_update()
+                .skip_locked()
+                .load(conn)?;
+            let due_ids: Vec<i32> = due.iter().map(|entry| entry.id).collect();
+            diesel::update(kv_arweave_outbox.filter(id.eq_any(due_ids)))
+                .set(next_attempt_at.eq(now + Duration::seconds(config.lease)))
+                .execute(conn)?;
+
+            Ok(due)
+        })
+    }
+
+    /// Count of due Arweave uploads, and creation time of the oldest one.
+    pub fn due_summary(
+        conn: &mut PgConnection,
+    ) -> Result<(i64, Option<NaiveDateTime>), Error> {
+        kv_arweave_outbox
+            .filter(sink.eq(SINK_ARWEAVE))
+            .filter(done_at.is_null())
+            .filter(next_attempt_at.le(naive_now()))
+            .select((diesel::dsl::count_star(), diesel::dsl::min(created_at)))
+            .first(conn)
+            .map_err(|e| e.into())
+    }
+
+    /// Count of dead entries of every sink.
+    pub fn count_dead(conn: &mut PgConnection) -> Result<i64, Error> {
+        kv_arweave_outbox
+            .filter(state.eq(OUTBOX_DEAD))
+            .count()
+            .get_result(conn)
+            .map_err(|e| e.into())
+    }
+
+    pub fn mark_done(&self, conn: &mut PgConnection) -> Result<(), Error> {
+        diesel::update(self)
+            .set((done_at.eq(Some(naive_now())), state.eq(OUTBOX_DONE)))
+            .execute(conn)?;
+        Ok(())
+    }
+
+    /// Give up archiving for given reason: see `OUTBOX_SKIPPED`.
+    pub fn mark_skipped(&self, conn: &mut PgConnection, reason: &str) -> Result<(), Error> {
+        diesel::update(self)
+            .set((
+                done_at.eq(Some(naive_now())),
+                state.eq(OUTBOX_SKIPPED),
+                last_error.eq(Some(reason.to_string())),
+            ))
+            .execute(conn)?;
+        Ok(())
+    }
+
+    /// Finished without being archived.  Chain of later links steps
+    /// over its link (see `KVChain::find_chained_previous`).
+    pub fn is_abandoned(&self) -> bool {
+        self.state == OUTBOX_SKIPPED || self.state == OUTBOX_DEAD
+    }
+
+    /// Record a failed attempt and schedule next one with backoff, or
+    /// give up after `config.max_attempts` (see `OUTBOX_DEAD`).
+    pub fn mark_failed(
+        &self,
+        conn: &mut PgConnection,
+        error: &str,
+        config: &ConfigArweaveOutbox,
+    ) -> Result<(), Error> {
+        let attempts_now = self.attempts + 1;
+        if attempts_now >= config.max_attempts {
+            error!(
+                "{} outbox entry {} is dead after {} attempts: {}",
+                self.sink, self.id, attempts_now, error
+            );
+            diesel::update(self)
+                .set((
+                    attempts.eq(attempts_now),
+                    last_error.eq(Some(error.to_string())),
+                    done_at.eq(Some(naive_now())),
+                    state.eq(OUTBOX_DEAD),
+                ))
+                .execute(conn)?;
+            return Ok(());
+        }
+        diesel::update(self)
+            .set((
+                attempts.eq(attempts_now),
+                last_error.eq(Some(error.to_string())),
+                next_attempt_at.eq(naive_now() + Self::backoff(attempts_now, config)),
+            ))
+            .execute(conn)?;
+        Ok(())
+    }
+
+    /// Try again after given seconds, without counting an attempt.
+    pub fn defer(&self, conn: &mut PgConnection, seconds: i64) -> Result<(), Error> {
+        diesel::update(self)
+            .set(next_attempt_at.eq(naive_now() + Duration::seconds(seconds)))
+            .execute(conn)?;
+        Ok(())
+    }
+
+    /// Wait before next attempt, after `attempts_made` failures.
+    pub fn backoff(attempts_made: i32, config: &ConfigArweaveOutbox) -> Duration {
+        let exponent = (attempts_made - 1).clamp(0, 20) as u32;
+        let seconds = config
+            .backoff
+            .saturating_mul(2_i64.pow(exponent))
+            .min(config.max_backoff);
+        Duration::seconds(seconds)
+    }
+}
@@ -0,0 +1,112 @@
+#[cfg(test)]
+mod tests {
+    use diesel::{insert_into, PgConnection, RunQueryDsl};
+    use fake::{Fake, Faker};
+    use serde_json::json;
+
+    use crate::{
+        config::ConfigArweaveOutbox,
+        crypto::secp256k1::Secp256k1KeyPair,
+        error::Error,
+        model::{
+            arweave_outbox::{ArweaveOutbox, OUTBOX_DEAD, OUTBOX_PENDING},
+            establish_connection,
+            kv_chains::{KVChain, NewKVChain},
+        },
+        schema::kv_chains::dsl::*,
+        util::naive_now,
+    };
+
+    fn create_link(conn: &mut PgConnection) -> Result<KVChain, Error> {
+        insert_into(kv_chains)
+            .values(&NewKVChain {
+                uuid: ::uuid::Uuid::new_v4(),
+                persona: Secp256k1KeyPair::generate().public_key.serialize().to_vec(),
+                platform: Faker.fake(),
+                identity: Faker.fake(),
+                patch: json!({ "test": "abc" }),
+                previous_id: None,
+                signature: vec![1],
+                signature_payload: Faker.fake(),
+                created_at: naive_now(),
+                action: "kv".into(),
+            })
+            .get_result(conn)
+            .map_err(|e| e.into())
+    }
+
+    #[test]
+    fn test_backoff() {
+        let config = ConfigArweaveOutbox {
+            backoff: 30,
+            max_backoff: 100,
+            ..Default::default()
+        };
+        assert_eq!(30, ArweaveOutbox::backoff(1, &config).num_seconds());
+        assert_eq!(60, ArweaveOutbox::backoff(2, &config).num_seconds());
+        assert_eq!(100, ArweaveOutbox::backoff(3, &config).num_seconds());
+        assert_eq!(100, ArweaveOutbox::backoff(100, &config).num_seconds());
+    }
+
+    #[test]
+    fn test_claim_fail_done() -> Result<(), Error> {
+        let mut conn = establish_connection();
+        let config = ConfigArweaveOutbox {
+            batch: 10000,
+            ..Default::default()
+        };
+        let link = create_link(&mut conn)?;
+        let entry = ArweaveOutbox::enqueue(&mut conn, &link)?;
+        assert_eq!(0, entry.attempts);
+
+        let claimed = ArweaveOutbox::claim_due(&mut conn, &config)?;
+        assert!(claimed.iter().any(|e| e.id == entry.id));
+        // Leased: not claimed again.
+        let claimed = ArweaveOutbox::claim_due(&mut conn, &config)?;
+        assert!(!claimed.iter().any(|e| e.id == entry.id));
+
+        entry.mark_failed(&mut conn, "gateway timeout", &config)?;
+        let failed = ArweaveOutbox::find_by_kv_chain(&mut conn, link.id)?.unwrap();
+        assert_eq!(1, failed.attempts);
+        assert_eq!(Some("gateway timeout".to_string()), failed.last_error);
+        assert!(failed.next_attempt_at > naive_now());
+
+        failed.mark_done(&mut conn)?;
+        let done = ArweaveOutbox::find_by_kv_chain(&mut conn, link.id)?.unwrap();
+        assert!(done.done_at.is_some());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_dead_after_max_attempts() -> Result<(), Error> {
+        let mut conn = establish_connection();
+        let config = ConfigArweaveOutbox {
+            max_attempts: 2,
+            ..Default::default()
+        };
+        let link = create_link(&mut conn)?;
+        let entry = ArweaveOutbox::enqueue(&mut conn, &link)?;
+        let dead_before = ArweaveOutbox::count_dead(&mut conn)?;
+
+        entry.mark_failed(&mut conn, "gateway timeout", &config)?;
+        let failed = ArweaveOutbox::find_by_kv_chain(&mut conn, link.id)?.unwrap();
+        assert_eq!(OUTBOX_PENDING, failed.state);
+        assert!(failed.done_at.is_none());
+
+        failed.mark_failed(&mut conn, "gateway timeout", &config)?;
+        let dead = ArweaveOutbox::find_by_kv_chain(&mut conn, link.id)?.unwrap();
+        assert_eq!(OUTBOX_DEAD, dead.state);
+        assert_eq!(2, dead.attempts);
+        assert!(dead.done_at.is_some());
+        assert!(dead.is_abandoned());
+        assert!(ArweaveOutbox::count_dead(&mut conn)? > dead_before);
+
+        let requeued = ArweaveOutbox::requeue(&mut conn, &link)?;
+        assert_eq!(OUTBOX_PENDING, requeued.state);
+        assert_eq!(0, requeued.attempts);
+        assert!(requeued.done_at.is_none());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,69 @@
+mod tests;
+
+use chrono::{NaiveDate, NaiveDateTime};
+use diesel::{insert_into, prelude::*, upsert::excluded, PgConnection};
+
+use crate::{
+    error::Error,
+    schema::{kv_arweave_spends, kv_arweave_spends::dsl::*},
+    util::naive_now,
+};
+
+/// Fees paid for Arweave transactions in a day (UTC).
+#[derive(Identifiable, Queryable, Debug, Clone, PartialEq)]
+#[diesel(table_name = kv_arweave_spends, primary_key(day))]
+pub struct ArweaveSpend {
+    pub day: NaiveDate,
+    /// Sum of fees.
+    pub winston: i64,
+    /// Count of posted transactions.
+    pub transactions: i32,
+    pub updated_at: NaiveDateTime,
+}
+
+#[derive(Insertable, Debug)]
+#[diesel(table_name = kv_arweave_spends)]
+struct NewArweaveSpend {
+    pub day: NaiveDate,
+    pub winston: i64,
+    pub transactions: i32,
+    pub updated_at: NaiveDateTime,
+}
+
+impl ArweaveSpend {
+    /// Add fee of a posted transaction to today's spend.
+    pub fn record(conn: &mut PgConnection, fee: i64) -> Result<ArweaveSpend, Error> {
+        let now = naive_now();
+        insert_into(kv_arweave_spends)
+            .values(&NewArweaveSpend {
+                day: now.date(),
+                winston: fee,
+                transactions: 1,
+                updated_at: now,
+            })
+            .on_conflict(day)
+            .do_update()
+            .set((
+                winston.eq(winston + excluded(winston)),
+                transactions.eq(transactions + 1),
+                updated_at.eq(now),
+            ))
+            .get_result(conn)
+            .map_err(|e| e.into())
+    }
+
+    /// Spend of given day.  Zero if nothing is posted.
+    pub fn of_day(conn: &mut PgConnection, day_given: NaiveDate) -> Result<ArweaveSpend, Error> {
+        let found = kv_arweave_spends.find(day_given).first(conn).optional()?;
+        Ok(found.unwrap_or(ArweaveSpend {
+            day: day_given,
+            winston: 0,
+            transactions: 0,
+            updated_at: naive_now(),
+        }))
+    }
+
+    pub fn today(conn: &mut PgConnection) -> Result<ArweaveSpend, Error> {
+        Self::of_day(conn, naive_now().date())
+    }
+}
@@ -0,0 +1,30 @@
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use crate::model::{arweave_spend::ArweaveSpend, establish_connection};
+
+    #[test]
+    fn test_record() {
+        let mut conn = establish_connection();
+        let before = ArweaveSpend::today(&mut conn).unwrap();
+
+        ArweaveSpend::record(&mut conn, 100).unwrap();
+        let after = ArweaveSpend::record(&mut conn, 50).unwrap();
+        assert_eq!(before.day, after.day);
+        assert!(after.winston >= before.winston + 150);
+        assert!(after.transactions >= before.transactions + 2);
+        assert_eq!(after, ArweaveSpend::today(&mut conn).unwrap());
+    }
+
+    #[test]
+    fn test_of_day_without_spend() {
+        let mut conn = establish_connection();
+        let day = NaiveDate::from_ymd_opt(2001, 1, 1).unwrap();
+
+        let spend = ArweaveSpend::of_day(&mut conn, day).unwrap();
+        assert_eq!(day, spend.day);
+        assert_eq!(0, spend.winston);
+        assert_eq!(0, spend.transactions);
+    }
+}
@@ -1,8 +1,10 @@
 mod tests;
 
 use crate::{
+    crypto::envelope,
     error::Error,
     schema::kv::{self, dsl::*},
+    util::naive_now,
 };
 use ::uuid::Uuid;
 use diesel::{prelude::*, PgConnection};
@@ -21,6 +23,13 @@ pub struct KV {
     pub created_at: chrono::NaiveDateTime,
     pub updated_at: chrono::NaiveDateTime,
     pub arweave_id: Option<String>,
+    /// Set when the proof of this platform-identity pair is revoked.
+    pub revoked_at: Option<chrono::NaiveDateTime>,
+    /// See `kv_chains::ARWEAVE_*`.
+    pub arweave_status: Option<String>,
+    pub arweave_block_height: Option<i64>,
+    /// `created_at` of last applied proof event.  Older ones are ignored.
+    pub proof_event_at: Option<chrono::NaiveDateTime>,
 }
 
 #[derive(Insertable, Debug)]
@@ -33,9 +42,11 @@ pub struct NewKV {
 
 impl KV {
     /// Apply a patch JSON onto current record.
+    /// Encrypted envelopes in patched content must be well-formed.
     pub fn patch(&self, conn: &mut PgConnection, patch: &serde_json::Value) -> Result<(), Error> {
         let mut patched_content = self.content.clone();
         json_patch::merge(&mut patched_content, patch);
+        envelope::validate_content(&patched_content)?;
 
         diesel::update(self)
             .set(content.eq(patched_content))
@@ -44,10 +55,15 @@ impl KV {
         Ok(())
     }
 
-    /// Update arweave_id field into newest.
+    /// Update arweave_id field into newest.  Its status starts over.
     pub fn update_arweave(&self, conn: &mut PgConnection, new_arweave: Option<String>) -> Result<(), Error> {
+        let status = crate::model::kv_chains::initial_arweave_status(&new_arweave);
         diesel::update(self)
-            .set(arweave_id.eq(new_arweave))
+            .set((
+                arweave_id.eq(new_arweave),
+                arweave_status.eq(status),
+                arweave_block_height.eq(None::<i64>),
+            ))
             .execute(conn)
             .map_err(|e| Error::from(e))?;
         Ok(())
@@ -59,6 +75,33 @@ impl KV {
     }
 }
 
+/// Mark (or unmark, if `revoked` is false) KVs of given
+/// persona-platform-identity as proof revoked, by an event happened at
+/// `event_at`.  KVs which have applied an event at or after it are left
+/// as-is.  Returns affected rows.
+pub fn mark_revoked(
+    conn: &mut PgConnection,
+    persona_given: &PublicKey,
+    platform_given: &str,
+    identity_given: &str,
+    revoked: bool,
+    event_at: chrono::NaiveDateTime,
+) -> Result<usize, Error> {
+    let persona_vec = persona_given.serialize().to_vec();
+    diesel::update(
+        kv.filter(persona.eq(&persona_vec))
+            .filter(platform.eq(platform_given))
+            .filter(identity.eq(identity_given))
+            .filter(proof_event_at.is_null().or(proof_event_at.lt(event_at))),
+    )
+    .set((
+        revoked_at.eq(if revoked { Some(naive_now()) } else { None }),
+        proof_event_at.eq(Some(event_at)),
+    ))
+    .execute(conn)
+    .map_err(|e| Error::from(e))
+}
+
 /// Find all KVs belong to given persona.
 pub fn find_all_by_persona(
     conn: &mut PgConnection,
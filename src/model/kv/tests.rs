@@ -68,6 +68,22 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_patch_invalid_envelope() -> Result<(), Error> {
+        let (mut c, username, pubkey) = connect_database_and_generate_key()?;
+
+        let (kv_created, _) = find_or_create(&mut c, "twitter", &username, &pubkey)?;
+        let result = kv_created.patch(
+            &mut c,
+            &json!({"tip": {"type": "kv_encrypted", "plaintext": "0x0123"}}),
+        );
+        assert!(matches!(result, Err(Error::EnvelopeError(_))));
+
+        let (kv_found, _) = find_or_create(&mut c, "twitter", &username, &pubkey)?;
+        assert_eq!(kv_found.content, json!({}));
+        Ok(())
+    }
+
     #[test]
     fn test_find_all_by_persona() -> Result<(), Error> {
         let mut c = establish_connection();
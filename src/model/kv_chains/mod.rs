@@ -2,7 +2,12 @@ mod tests;
 
 use ::uuid::Uuid;
 use chrono::NaiveDateTime;
-use diesel::{insert_into, prelude::*, PgConnection};
+use diesel::{
+    insert_into,
+    prelude::*,
+    result::{DatabaseErrorKind, Error as DieselError},
+    PgConnection,
+};
 use libsecp256k1::PublicKey;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
@@ -10,7 +15,7 @@ use serde_json::json;
 use crate::{
     crypto::{secp256k1::Secp256k1KeyPair, util::hex_public_key},
     error::Error,
-    model::{establish_connection, kv::KV},
+    model::{archive::SINK_ARWEAVE, establish_connection, multisig::SignerSignature},
     schema::{kv_chains, kv_chains::dsl::*},
     util::{naive_now, vec_to_base64},
 };
@@ -29,7 +34,24 @@ pub struct KVChain {
     pub created_at: NaiveDateTime,
     pub updated_at: NaiveDateTime,
     pub signature_payload: String,
-    pub arweave_id: Option<String>,
+    pub action: String,
+    /// See `ARWEAVE_*`.  `None` if not uploaded.
+    pub arweave_status: Option<String>,
+    /// Set once confirmed.
+    pub arweave_block_height: Option<i64>,
+    pub arweave_submitted_at: Option<NaiveDateTime>,
+    /// Last time `arweave_status` was polled from gateway.
+    pub arweave_checked_at: Option<NaiveDateTime>,
+    /// Transaction of the ANS-104 bundle this link is uploaded in.
+    /// `arweave` archive id is then id of the data item in it.
+    pub arweave_bundle_id: Option<String>,
+    /// Content id of this link in each archive sink (sink name => id).
+    /// See `model::archive`.  `arweave` one is the Arweave transaction
+    /// (or data item) id, see `KVChain::arweave_id()`.
+    pub archive_ids: serde_json::Value,
+    /// Signatures of m-of-n signers (see `model::multisig`), empty for
+    /// single-key avatars.  `signature` is then the one of first signer.
+    pub signer_signatures: serde_json::Value,
 }
 
 #[derive(Insertable, Clone, Debug)]
@@ -44,7 +66,42 @@ pub struct NewKVChain {
     pub signature: Vec<u8>,
     pub signature_payload: String,
     pub created_at: NaiveDateTime,
-    pub arweave_id: Option<String>, 
+    pub action: String,
+}
+
+/// Link which patches KV content of a platform-identity pair.
+pub const ACTION_KV: &str = "kv";
+/// Link which patches namespace visibility settings of a persona.
+/// See `model::visibility`.
+pub const ACTION_VISIBILITY: &str = "visibility";
+/// Link which patches m-of-n signer settings of a persona.
+/// See `model::multisig`.
+pub const ACTION_MULTISIG: &str = "multisig";
+
+/// Arweave transaction is posted, but not mined yet.
+pub const ARWEAVE_PENDING: &str = "pending";
+/// Arweave transaction is mined (see `arweave_block_height`).
+pub const ARWEAVE_CONFIRMED: &str = "confirmed";
+/// Arweave transaction is never mined. Link is queued for re-upload.
+pub const ARWEAVE_DROPPED: &str = "dropped";
+
+/// Status of a newly posted Arweave transaction.  Empty id means
+/// Arweave is not configured when uploading, so nothing to track.
+pub fn initial_arweave_status(new_arweave: &Option<String>) -> Option<String> {
+    match new_arweave.as_deref() {
+        None | Some("") => None,
+        Some(_) => Some(ARWEAVE_PENDING.into()),
+    }
+}
+
+/// Check `action` given by client. Defaults to `ACTION_KV`.
+pub fn parse_action(action_given: Option<String>) -> Result<String, Error> {
+    match action_given.as_deref() {
+        None | Some(ACTION_KV) => Ok(ACTION_KV.into()),
+        Some(ACTION_VISIBILITY) => Ok(ACTION_VISIBILITY.into()),
+        Some(ACTION_MULTISIG) => Ok(ACTION_MULTISIG.into()),
+        Some(other) => Err(Error::ParamError(format!("unknown action: {}", other))),
+    }
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -57,6 +114,9 @@ pub struct SignPayload {
     pub patch: serde_json::Value,
     pub created_at: i64,
     pub previous: Option<String>,
+    /// Omitted for `ACTION_KV` to keep sign payload of KV patches unchanged.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub action: Option<String>,
 }
 
 impl NewKVChain {
@@ -82,7 +142,7 @@ impl NewKVChain {
             signature: vec![],
             signature_payload: "".into(),
             created_at: naive_now(),
-            arweave_id: None,
+            action: ACTION_KV.into(),
         })
     }
 
@@ -113,6 +173,11 @@ impl NewKVChain {
             patch: self.patch.clone(),
             previous: previous_sig,
             created_at: self.created_at.timestamp(),
+            action: if self.action == ACTION_KV {
+                None
+            } else {
+                Some(self.action.clone())
+            },
         })
     }
 
@@ -146,7 +211,12 @@ impl NewKVChain {
         insert_into(kv_chains)
             .values(self)
             .get_result(conn)
-            .map_err(|e| e.into())
+            .map_err(|e| match e {
+                DieselError::DatabaseError(DatabaseErrorKind::UniqueViolation, _) => {
+                    Error::PayloadAlreadyUsed(self.uuid.to_string())
+                }
+                _ => e.into(),
+            })
     }
 
     /// Find last chain arweave id.
@@ -165,7 +235,7 @@ impl NewKVChain {
             .optional()?;
 
         if let Some(kv_chain) = found {
-            Ok(kv_chain.arweave_id)
+            Ok(kv_chain.arweave_id())
         } else {
             Ok(None)
         }
@@ -188,20 +258,66 @@ impl KVChain {
         Ok(found)
     }
 
-    /// Perform patch on KV record.
-    pub fn perform_patch(&self, conn: &mut PgConnection) -> Result<KV, Error> {
-        use crate::model::kv;
+    /// Perform patch on KV record, or on visibility / multisig
+    /// settings of persona, according to `self.action`.
+    pub fn perform_patch(&self, conn: &mut PgConnection) -> Result<(), Error> {
+        use crate::model::{kv, multisig, visibility};
 
         let Secp256k1KeyPair {
             public_key,
             secret_key: _,
         } = Secp256k1KeyPair::from_pubkey_vec(&self.persona)?;
 
-        let (kv_record, _is_new) =
-            kv::find_or_create(conn, &self.platform, &self.identity, &public_key)?;
-        kv_record.patch(conn, &self.patch)?;
-        
-        Ok(kv_record)
+        match self.action.as_str() {
+            ACTION_KV => {
+                let (kv_record, _is_new) =
+                    kv::find_or_create(conn, &self.platform, &self.identity, &public_key)?;
+                kv_record.patch(conn, &self.patch)
+            }
+            ACTION_VISIBILITY => {
+                let visibility_record = visibility::find_or_create(conn, &public_key)?;
+                visibility_record.patch(conn, &self.patch)
+            }
+            ACTION_MULTISIG => {
+                let multisig_record = multisig::find_or_create(conn, &public_key)?;
+                multisig_record.patch(conn, &self.patch)
+            }
+            _ => Err(Error::ParamError(format!("unknown action: {}", self.action))),
+        }
+    }
+
+    /// Arweave id of previous link, if any.  See `find_chained_previous`.
+    pub fn find_previous_arweave(&self, conn: &mut PgConnection) -> Result<Option<String>, Error> {
+        Ok(self
+            .find_chained_previous(conn)?
+            .and_then(|previous| previous.arweave_id()))
+    }
+
+    /// Previous link in Arweave chain (`previous_arweave_id`) of this
+    /// one: previous link, stepping over ones which are not archived
+    /// (see `ArweaveOutbox::is_abandoned`).
+    pub fn find_chained_previous(&self, conn: &mut PgConnection) -> Result<Option<KVChain>, Error> {
+        use crate::model::arweave_outbox::ArweaveOutbox;
+
+        let mut previous_id_next = self.previous_id;
+        while let Some(previous_id_current) = previous_id_next {
+            let previous: Option<KVChain> = kv_chains
+                .find(previous_id_current)
+                .first(conn)
+                .optional()?;
+            let previous = match previous {
+                Some(previous) => previous,
+                None => return Ok(None),
+            };
+            let abandoned = ArweaveOutbox::find_by_kv_chain(conn, previous.id)?
+                .is_some_and(|entry| entry.is_abandoned());
+            if !abandoned {
+                return Ok(Some(previous));
+            }
+            previous_id_next = previous.previous_id;
+        }
+
+        Ok(None)
     }
 
     /// Insert arweave id into kv and kv_chains.
@@ -210,20 +326,184 @@ impl KVChain {
         use crate::model::kv;
         
         // insert arweave id into table kv
-        let Secp256k1KeyPair {
-            public_key,
-            secret_key: _,
-        } = Secp256k1KeyPair::from_pubkey_vec(&self.persona)?;
-
-        let (kv_record, _is_new) =
-            kv::find_or_create(conn, &self.platform, &self.identity, &public_key)?;
-        kv_record.update_arweave(conn, new_arweave.clone())?;
+        // (settings links of persona have no KV record, and a re-uploaded
+        // link must not overwrite the one of a newer link)
+        let newer_links: i64 = kv_chains
+            .filter(persona.eq(&self.persona))
+            .filter(platform.eq(&self.platform))
+            .filter(identity.eq(&self.identity))
+            .filter(action.eq(ACTION_KV))
+            .filter(id.gt(self.id))
+            .count()
+            .get_result(conn)?;
+        if self.action == ACTION_KV && newer_links == 0 {
+            let Secp256k1KeyPair {
+                public_key,
+                secret_key: _,
+            } = Secp256k1KeyPair::from_pubkey_vec(&self.persona)?;
+
+            let (kv_record, _is_new) =
+                kv::find_or_create(conn, &self.platform, &self.identity, &public_key)?;
+            kv_record.update_arweave(conn, new_arweave.clone())?;
+        }
         
         // insert arweave id into table kv_chains
+        let status = initial_arweave_status(&new_arweave);
+        let submitted_at = status.as_ref().map(|_| naive_now());
         diesel::update(self)
-            .set(arweave_id.eq(new_arweave))
+            .set((
+                arweave_status.eq(status),
+                arweave_block_height.eq(None::<i64>),
+                arweave_submitted_at.eq(submitted_at),
+                arweave_checked_at.eq(None::<NaiveDateTime>),
+                arweave_bundle_id.eq(None::<String>),
+            ))
             .execute(conn)
             .map_err(|e| Error::from(e))?;
+        match new_arweave.filter(|content_id| !content_id.is_empty()) {
+            Some(content_id) => self.insert_archive_id(conn, SINK_ARWEAVE, &content_id)?,
+            None => self.remove_archive_id(conn, SINK_ARWEAVE)?,
+        }
+
+        Ok(())
+    }
+
+    /// Record content id of this link in given archive sink.
+    pub fn insert_archive_id(
+        &self,
+        conn: &mut PgConnection,
+        sink: &str,
+        content_id: &str,
+    ) -> Result<(), Error> {
+        use diesel::sql_types::{Integer, Text};
+
+        // Merged in SQL: other sinks may write concurrently.
+        diesel::sql_query(
+            "UPDATE kv_chains SET archive_ids = archive_ids || jsonb_build_object($1::text, $2::text) WHERE id = $3",
+        )
+        .bind::<Text, _>(sink)
+        .bind::<Text, _>(content_id)
+        .bind::<Integer, _>(self.id)
+        .execute(conn)?;
+        Ok(())
+    }
+
+    /// Forget content id of this link in given archive sink.
+    pub fn remove_archive_id(&self, conn: &mut PgConnection, sink: &str) -> Result<(), Error> {
+        use diesel::sql_types::{Integer, Text};
+
+        diesel::sql_query("UPDATE kv_chains SET archive_ids = archive_ids - $1::text WHERE id = $2")
+            .bind::<Text, _>(sink)
+            .bind::<Integer, _>(self.id)
+            .execute(conn)?;
+        Ok(())
+    }
+
+    /// Record signatures of m-of-n signers of this link.
+    pub fn insert_signer_signatures(
+        &self,
+        conn: &mut PgConnection,
+        signatures: &[SignerSignature],
+    ) -> Result<KVChain, Error> {
+        diesel::update(self)
+            .set(signer_signatures.eq(serde_json::to_value(signatures)?))
+            .get_result(conn)
+            .map_err(|e| e.into())
+    }
+
+    /// Parsed `signer_signatures`.
+    pub fn signer_signatures(&self) -> Result<Vec<SignerSignature>, Error> {
+        Ok(serde_json::from_value(self.signer_signatures.clone())?)
+    }
+
+    /// Content id of this link in given archive sink.
+    pub fn archive_id(&self, sink: &str) -> Option<&str> {
+        self.archive_ids.get(sink).and_then(|content_id| content_id.as_str())
+    }
+
+    /// Arweave transaction (or data item, if bundled) id of this link.
+    /// `None` if not uploaded yet.
+    pub fn arweave_id(&self) -> Option<String> {
+        self.archive_id(SINK_ARWEAVE).map(String::from)
+    }
+
+    /// Record the bundle transaction carrying data item `arweave_id()`.
+    pub fn insert_arweave_bundle_id(
+        &self,
+        conn: &mut PgConnection,
+        bundle_id: Option<String>,
+    ) -> Result<(), Error> {
+        diesel::update(self)
+            .set(arweave_bundle_id.eq(bundle_id))
+            .execute(conn)?;
+        Ok(())
+    }
+
+    /// Transaction to poll status of: the bundle one, if bundled.
+    pub fn arweave_transaction_id(&self) -> Option<&str> {
+        self.arweave_bundle_id
+            .as_deref()
+            .or(self.archive_id(SINK_ARWEAVE))
+            .filter(|transaction_id| !transaction_id.is_empty())
+    }
+
+    /// Pending uploads not checked in last `interval` seconds, oldest first.
+    pub fn find_unconfirmed(
+        conn: &mut PgConnection,
+        interval: i64,
+        limit: i64,
+    ) -> Result<Vec<KVChain>, Error> {
+        let checked_before = naive_now() - chrono::Duration::seconds(interval);
+        kv_chains
+            .filter(arweave_status.eq(ARWEAVE_PENDING))
+            .filter(
+                arweave_checked_at
+                    .is_null()
+                    .or(arweave_checked_at.lt(checked_before)),
+            )
+            .order(id.asc())
+            .limit(limit)
+            .get_results(conn)
+            .map_err(|e| e.into())
+    }
+
+    /// Links of the same persona after this one which are submitted to
+    /// Arweave.  Their documents chain up to the transaction of this link.
+    pub fn find_submitted_after(&self, conn: &mut PgConnection) -> Result<Vec<KVChain>, Error> {
+        kv_chains
+            .filter(persona.eq(&self.persona))
+            .filter(id.gt(self.id))
+            .filter(arweave_status.is_not_null())
+            .order(id.asc())
+            .get_results(conn)
+            .map_err(|e| e.into())
+    }
+
+    /// Record status polled from Arweave gateway, on this link and on
+    /// the KV record still pointing at its transaction.
+    pub fn update_arweave_status(
+        &self,
+        conn: &mut PgConnection,
+        status: &str,
+        block_height: Option<i64>,
+    ) -> Result<(), Error> {
+        use crate::schema::kv;
+
+        diesel::update(self)
+            .set((
+                arweave_status.eq(Some(status)),
+                arweave_block_height.eq(block_height),
+                arweave_checked_at.eq(Some(naive_now())),
+            ))
+            .execute(conn)?;
+        if let Some(current_arweave_id) = self.archive_id(SINK_ARWEAVE) {
+            diesel::update(kv::table.filter(kv::arweave_id.eq(current_arweave_id)))
+                .set((
+                    kv::arweave_status.eq(Some(status)),
+                    kv::arweave_block_height.eq(block_height),
+                ))
+                .execute(conn)?;
+        }
 
         Ok(())
     }
@@ -250,7 +530,7 @@ pub fn find_kv_chain_by_id(
     Ok((found.unwrap(), false))
 }
 
-/// Find all KVChains belongs to given platform-identity pair.
+/// Find all KVChains belongs to given platform-identity pair, oldest first.
 pub fn find_all_by_identity(
     conn: &mut PgConnection,
     platform_given: &str,
@@ -260,7 +540,23 @@ pub fn find_all_by_identity(
     let result: Vec<KVChain> = kv_chains
         .filter(platform.eq(platform_given))
         .filter(identity.eq(identity_given))
+        .order(id.asc())
+        .get_results(conn)?;
+
+    Ok(result)
+}
+
+/// Find all KVChains of given persona with given action, oldest first.
+pub fn find_all_by_action(
+    conn: &mut PgConnection,
+    persona_vec: &Vec<u8>,
+    action_given: &str,
+) -> Result<Vec<KVChain>, Error> {
+    let result: Vec<KVChain> = kv_chains
+        .filter(persona.eq(persona_vec))
+        .filter(action.eq(action_given))
+        .order(id.asc())
         .get_results(conn)?;
 
     Ok(result)
-}
\ No newline at end of file
+}
@@ -9,8 +9,9 @@ mod tests {
         crypto::secp256k1::Secp256k1KeyPair,
         error::Error,
         model::{
+            archive::SINK_ARWEAVE,
             establish_connection,
-            kv_chains::{KVChain, NewKVChain, find_kv_chain_by_id}, kv::find_all_by_persona,
+            kv_chains::{KVChain, NewKVChain, find_kv_chain_by_id, ARWEAVE_PENDING}, kv::find_all_by_persona,
         },
         schema::kv_chains::dsl::*,
         util::{naive_now, vec_to_base64},
@@ -29,7 +30,7 @@ mod tests {
         let persona_bytes = persona_pubkey.serialize().to_vec();
         let new_platform: String = Faker.fake();
         let new_identity: String = Faker.fake();
-        insert_into(kv_chains)
+        let link = insert_into(kv_chains)
             .values(&NewKVChain {
                 uuid: new_uuid,
                 persona: persona_bytes,
@@ -40,10 +41,13 @@ mod tests {
                 signature: vec![1],
                 signature_payload: "".into(),
                 created_at: naive_now(),
-                arweave_id: other_arweave_id,
+                action: "kv".into(),
             })
-            .get_result(conn)
-            .map_err(|e| e.into())
+            .get_result::<KVChain>(conn)?;
+        if let Some(content_id) = other_arweave_id {
+            link.insert_archive_id(conn, SINK_ARWEAVE, &content_id)?;
+        }
+        Ok(link)
     }
 
     #[test]
@@ -83,7 +87,7 @@ mod tests {
             signature: vec![2],
             signature_payload: "".into(),
             created_at: naive_now(),
-            arweave_id: None,
+            action: "kv".into(),
         };
         let new_link = new_kvchain.finalize(&mut conn)?;
         assert_eq!(new_link.previous_id.unwrap(), link.id);
@@ -156,18 +160,21 @@ mod tests {
         let insert_arweave_id = Some(String::from("xahZGuFbiayYiIuEG4dSpMS3XWcNH02vAXYGt4t2WFA"));
 
         let link = create_link_and_insert(&mut conn, &public_key, None)?;
-        assert_eq!(link.arweave_id, None);
+        assert_eq!(link.arweave_id(), None);
         
         // check it whether insert arweave id into kv_chains table
         let _ = link.insert_arweave_id(&mut conn, insert_arweave_id.clone());
         let (find_link, is_found) = find_kv_chain_by_id(&mut conn, link.id)?;
         assert!(is_found);
-        assert_eq!(find_link.arweave_id, insert_arweave_id);
+        assert_eq!(find_link.arweave_id(), insert_arweave_id);
+        assert_eq!(find_link.arweave_status, Some(ARWEAVE_PENDING.to_string()));
+        assert!(find_link.arweave_submitted_at.is_some());
 
         // check it whether insert arweave id into kv table
         let kvs = find_all_by_persona(&mut conn, &public_key)?;
         assert_eq!(kvs.len(), 1);
         assert_eq!(kvs[0].arweave_id, insert_arweave_id);
+        assert_eq!(kvs[0].arweave_status, Some(ARWEAVE_PENDING.to_string()));
 
         Ok(())
     }
@@ -193,7 +200,7 @@ mod tests {
             signature: vec![2],
             signature_payload: "".into(),
             created_at: naive_now(),
-            arweave_id: Some("second".into()),
+            action: "kv".into(),
         };
 
         let found_arweave_id = second_link.find_last_chain_arweave(&mut conn)?;
@@ -0,0 +1,120 @@
+mod tests;
+
+use ::uuid::Uuid;
+use chrono::{Duration, NaiveDateTime};
+use diesel::{insert_into, prelude::*, PgConnection};
+use libsecp256k1::PublicKey;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::C,
+    error::Error,
+    schema::{kv_payloads, kv_payloads::dsl::*},
+    util::naive_now,
+};
+
+/// A sign payload issued by `POST /v1/kv/payload`.
+/// `POST /v1/kv` only accepts a `uuid` recorded here, which is not
+/// expired and not used yet.
+#[derive(Identifiable, Queryable, Serialize, Deserialize, Debug)]
+#[diesel(table_name = kv_payloads)]
+pub struct KVPayload {
+    pub id: i32,
+    pub uuid: Uuid,
+    pub persona: Vec<u8>,
+    pub created_at: NaiveDateTime,
+    pub expired_at: NaiveDateTime,
+    pub used_at: Option<NaiveDateTime>,
+}
+
+#[derive(Insertable, Debug)]
+#[diesel(table_name = kv_payloads)]
+pub struct NewKVPayload {
+    pub uuid: Uuid,
+    pub persona: Vec<u8>,
+    pub created_at: NaiveDateTime,
+    pub expired_at: NaiveDateTime,
+}
+
+impl KVPayload {
+    /// Record a newly issued sign payload. It expires after
+    /// `C.payload.ttl` seconds.
+    pub fn issue(
+        conn: &mut PgConnection,
+        persona_given: &PublicKey,
+        uuid_given: Uuid,
+        created_at_given: NaiveDateTime,
+    ) -> Result<KVPayload, Error> {
+        insert_into(kv_payloads)
+            .values(&NewKVPayload {
+                uuid: uuid_given,
+                persona: persona_given.serialize().to_vec(),
+                created_at: created_at_given,
+                expired_at: created_at_given + Duration::seconds(C.payload.ttl),
+            })
+            .get_result(conn)
+            .map_err(|e| e.into())
+    }
+
+    /// Fail with `PayloadAlreadyUsed` if this persona has used the
+    /// payload. Other checks are left to `consume`.
+    pub fn ensure_unused(
+        conn: &mut PgConnection,
+        persona_given: &PublicKey,
+        uuid_given: Uuid,
+    ) -> Result<(), Error> {
+        let used: Option<Option<NaiveDateTime>> = kv_payloads
+            .filter(uuid.eq(uuid_given))
+            .filter(persona.eq(persona_given.serialize().to_vec()))
+            .select(used_at)
+            .first(conn)
+            .optional()?;
+        match used {
+            Some(Some(_)) => Err(Error::PayloadAlreadyUsed(uuid_given.to_string())),
+            _ => Ok(()),
+        }
+    }
+
+    /// Mark an issued sign payload as used.
+    /// Fails if it was never issued to this persona, has expired, was
+    /// used before, or `created_at_given` (UNIX timestamp) differs from
+    /// the issued one.
+    pub fn consume(
+        conn: &mut PgConnection,
+        persona_given: &PublicKey,
+        uuid_given: Uuid,
+        created_at_given: i64,
+    ) -> Result<KVPayload, Error> {
+        let persona_vec = persona_given.serialize().to_vec();
+        let found: KVPayload = kv_payloads
+            .filter(uuid.eq(uuid_given))
+            .filter(persona.eq(&persona_vec))
+            .first(conn)
+            .optional()?
+            .ok_or_else(|| Error::PayloadNotIssued(uuid_given.to_string()))?;
+
+        if found.used_at.is_some() {
+            return Err(Error::PayloadAlreadyUsed(uuid_given.to_string()));
+        }
+        if found.created_at.timestamp() != created_at_given {
+            return Err(Error::PayloadTimestampMismatch(uuid_given.to_string()));
+        }
+        if found.expired_at < naive_now() {
+            return Err(Error::PayloadExpired(uuid_given.to_string()));
+        }
+
+        // `used_at IS NULL` guards against concurrent uploads of the same payload.
+        diesel::update(kv_payloads.filter(id.eq(found.id)).filter(used_at.is_null()))
+            .set(used_at.eq(Some(naive_now())))
+            .get_result(conn)
+            .optional()?
+            .ok_or_else(|| Error::PayloadAlreadyUsed(uuid_given.to_string()))
+    }
+}
+
+/// Delete all expired payloads. Returns count of deleted records.
+pub fn purge_expired(conn: &mut PgConnection) -> Result<usize, Error> {
+    diesel::delete(kv_payloads.filter(expired_at.lt(naive_now())))
+        .execute(conn)
+        .map_err(|e| e.into())
+}
@@ -0,0 +1,122 @@
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+    use diesel::{insert_into, PgConnection, RunQueryDsl};
+
+    use crate::{
+        crypto::secp256k1::Secp256k1KeyPair,
+        error::Error,
+        model::{
+            establish_connection,
+            kv_payload::{purge_expired, KVPayload, NewKVPayload},
+        },
+        schema::kv_payloads::dsl::*,
+        util::naive_now,
+    };
+
+    // NOTE: `kv_payloads` is not cleared here, since payloads issued by
+    // controller tests running in parallel live in the same table.
+    fn before_each(_connection: &mut PgConnection) -> Result<(), Error> {
+        let _ = env_logger::try_init();
+        Ok(())
+    }
+
+    #[test]
+    fn test_issue_and_consume() -> Result<(), Error> {
+        let mut conn = establish_connection();
+        before_each(&mut conn)?;
+        let keypair = Secp256k1KeyPair::generate();
+        let now = naive_now();
+        let issued = KVPayload::issue(&mut conn, &keypair.public_key, ::uuid::Uuid::new_v4(), now)?;
+        assert!(issued.used_at.is_none());
+        assert!(issued.expired_at > now);
+
+        let consumed =
+            KVPayload::consume(&mut conn, &keypair.public_key, issued.uuid, now.timestamp())?;
+        assert_eq!(consumed.id, issued.id);
+        assert!(consumed.used_at.is_some());
+        Ok(())
+    }
+
+    #[test]
+    fn test_consume_not_issued() -> Result<(), Error> {
+        let mut conn = establish_connection();
+        before_each(&mut conn)?;
+        let keypair = Secp256k1KeyPair::generate();
+        let other = Secp256k1KeyPair::generate();
+        let now = naive_now();
+        let issued = KVPayload::issue(&mut conn, &keypair.public_key, ::uuid::Uuid::new_v4(), now)?;
+
+        let result = KVPayload::consume(
+            &mut conn,
+            &keypair.public_key,
+            ::uuid::Uuid::new_v4(),
+            now.timestamp(),
+        );
+        assert!(matches!(result, Err(Error::PayloadNotIssued(_))));
+
+        // Issued to another persona
+        let result = KVPayload::consume(&mut conn, &other.public_key, issued.uuid, now.timestamp());
+        assert!(matches!(result, Err(Error::PayloadNotIssued(_))));
+        Ok(())
+    }
+
+    #[test]
+    fn test_consume_twice() -> Result<(), Error> {
+        let mut conn = establish_connection();
+        before_each(&mut conn)?;
+        let keypair = Secp256k1KeyPair::generate();
+        let now = naive_now();
+        let issued = KVPayload::issue(&mut conn, &keypair.public_key, ::uuid::Uuid::new_v4(), now)?;
+
+        KVPayload::ensure_unused(&mut conn, &keypair.public_key, issued.uuid)?;
+        KVPayload::consume(&mut conn, &keypair.public_key, issued.uuid, now.timestamp())?;
+        let result =
+            KVPayload::consume(&mut conn, &keypair.public_key, issued.uuid, now.timestamp());
+        assert!(matches!(result, Err(Error::PayloadAlreadyUsed(_))));
+        let result = KVPayload::ensure_unused(&mut conn, &keypair.public_key, issued.uuid);
+        assert!(matches!(result, Err(Error::PayloadAlreadyUsed(_))));
+        Ok(())
+    }
+
+    #[test]
+    fn test_consume_timestamp_mismatch() -> Result<(), Error> {
+        let mut conn = establish_connection();
+        before_each(&mut conn)?;
+        let keypair = Secp256k1KeyPair::generate();
+        let now = naive_now();
+        let issued = KVPayload::issue(&mut conn, &keypair.public_key, ::uuid::Uuid::new_v4(), now)?;
+
+        let result =
+            KVPayload::consume(&mut conn, &keypair.public_key, issued.uuid, now.timestamp() + 1);
+        assert!(matches!(result, Err(Error::PayloadTimestampMismatch(_))));
+        Ok(())
+    }
+
+    #[test]
+    fn test_consume_expired_and_purge() -> Result<(), Error> {
+        let mut conn = establish_connection();
+        before_each(&mut conn)?;
+        let keypair = Secp256k1KeyPair::generate();
+        let issued_at = naive_now() - Duration::hours(2);
+        let issued: KVPayload = insert_into(kv_payloads)
+            .values(&NewKVPayload {
+                uuid: ::uuid::Uuid::new_v4(),
+                persona: keypair.public_key.serialize().to_vec(),
+                created_at: issued_at,
+                expired_at: issued_at + Duration::hours(1),
+            })
+            .get_result(&mut conn)?;
+
+        let result = KVPayload::consume(
+            &mut conn,
+            &keypair.public_key,
+            issued.uuid,
+            issued_at.timestamp(),
+        );
+        assert!(matches!(result, Err(Error::PayloadExpired(_))));
+
+        assert!(purge_expired(&mut conn)? >= 1);
+        Ok(())
+    }
+}
@@ -3,7 +3,14 @@ use diesel::PgConnection;
 
 pub mod kv;
 pub mod kv_chains;
+pub mod kv_payload;
+pub mod multisig;
+pub mod receipt;
+pub mod visibility;
 pub mod arweave;
+pub mod arweave_outbox;
+pub mod arweave_spend;
+pub mod archive;
 
 pub fn establish_connection() -> PgConnection {
     let database_url = crate::config::C.database_url();
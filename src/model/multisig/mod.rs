@@ -0,0 +1,352 @@
+mod tests;
+
+use ::uuid::Uuid;
+use chrono::NaiveDateTime;
+use diesel::{insert_into, prelude::*, PgConnection};
+use http::StatusCode;
+use libsecp256k1::PublicKey;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    crypto::secp256k1::Secp256k1KeyPair,
+    error::Error,
+    model::kv_chains::{KVChain, NewKVChain},
+    schema::{kv_multisigs, kv_proposal_signatures, kv_proposals},
+    util::naive_now,
+};
+
+/// An avatar whose chain links are only applied once `threshold`
+/// distinct signers have signed the same `SignPayload`.
+/// `settings` is patched by `ACTION_MULTISIG` chain links, in the form
+/// of `MultisigSetting`. Patching it into `{}` turns m-of-n off.
+#[derive(Identifiable, Queryable, Serialize, Deserialize, Debug)]
+#[diesel(table_name = kv_multisigs)]
+pub struct Multisig {
+    pub id: i32,
+    pub persona: Vec<u8>,
+    pub settings: Value,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+/// Signature of one m-of-n signer over `signature_payload` of a link.
+/// See `KVChain::signer_signatures`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SignerSignature {
+    /// Compressed hexstring started with `0x`.
+    pub signer: String,
+    /// Base64 in JSON.
+    #[serde(with = "crate::model::arweave::base64_bytes")]
+    pub signature: Vec<u8>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MultisigSetting {
+    pub threshold: usize,
+    /// Signer public keys (hexstring).
+    pub signers: Vec<String>,
+}
+
+impl MultisigSetting {
+    fn parse(value: &Value) -> Result<Self, Error> {
+        let setting: Self = serde_json::from_value(value.clone())
+            .map_err(|e| Error::ParamError(format!("invalid multisig settings: {}", e)))?;
+        let signer_keys = setting.signer_keys()?;
+        if signer_keys
+            .iter()
+            .enumerate()
+            .any(|(i, key)| signer_keys[i + 1..].contains(key))
+        {
+            return Err(Error::ParamError("duplicated multisig signer".into()));
+        }
+        if setting.threshold == 0 || setting.threshold > signer_keys.len() {
+            return Err(Error::ParamError(format!(
+                "multisig threshold should be in 1..={}",
+                signer_keys.len()
+            )));
+        }
+        Ok(setting)
+    }
+
+    pub fn signer_keys(&self) -> Result<Vec<PublicKey>, Error> {
+        self.signers
+            .iter()
+            .map(|signer_hex| Secp256k1KeyPair::from_pubkey_hex(signer_hex).map(|pair| pair.public_key))
+            .collect()
+    }
+
+    /// Check that `threshold` distinct signers signed given payload.
+    pub fn verify_signatures(
+        &self,
+        signatures: &[SignerSignature],
+        sign_payload: &str,
+    ) -> Result<(), Error> {
+        let mut signers: Vec<PublicKey> = vec![];
+        for signer_signature in signatures.iter() {
+            let signer = self.verify_signer(&signer_signature.signature, sign_payload)?;
+            if Secp256k1KeyPair::from_pubkey_hex(&signer_signature.signer)?.public_key != signer {
+                return Err(Error::SignatureValidationError(
+                    "Signer public key mismatch".into(),
+                ));
+            }
+            if !signers.contains(&signer) {
+                signers.push(signer);
+            }
+        }
+        if signers.len() < self.threshold {
+            return Err(Error::SignatureValidationError(format!(
+                "{} of {} multisig signatures",
+                signers.len(),
+                self.threshold
+            )));
+        }
+        Ok(())
+    }
+
+    /// Recover signer of given signature, which should be one of `signers`.
+    pub fn verify_signer(&self, sig: &Vec<u8>, sign_payload: &str) -> Result<PublicKey, Error> {
+        let recovered = Secp256k1KeyPair::recover_from_personal_signature(sig, sign_payload)?;
+        if !self.signer_keys()?.contains(&recovered) {
+            return Err(Error::SignatureValidationError(
+                "Signer is not in multisig set".into(),
+            ));
+        }
+        Ok(recovered)
+    }
+}
+
+impl Multisig {
+    pub fn setting(&self) -> Result<MultisigSetting, Error> {
+        MultisigSetting::parse(&self.settings)
+    }
+
+    /// Apply a patch JSON onto current settings.
+    pub fn patch(&self, conn: &mut PgConnection, patch: &Value) -> Result<(), Error> {
+        use crate::schema::kv_multisigs::dsl::*;
+
+        let mut patched_settings = self.settings.clone();
+        json_patch::merge(&mut patched_settings, patch);
+        if patched_settings == serde_json::json!({}) {
+            diesel::delete(self).execute(conn)?;
+            return Ok(());
+        }
+        MultisigSetting::parse(&patched_settings)?;
+
+        diesel::update(self)
+            .set(settings.eq(patched_settings))
+            .execute(conn)?;
+        Ok(())
+    }
+}
+
+/// Find m-of-n settings of given persona (serialized public key).
+/// `None` means a plain single-key avatar.
+pub fn find_by_persona(
+    conn: &mut PgConnection,
+    persona_vec: &Vec<u8>,
+) -> Result<Option<Multisig>, Error> {
+    use crate::schema::kv_multisigs::dsl::*;
+
+    kv_multisigs
+        .filter(persona.eq(persona_vec))
+        .first(conn)
+        .optional()
+        .map_err(|e| e.into())
+}
+
+/// Find m-of-n settings of given persona, create an empty one if not exist.
+pub fn find_or_create(conn: &mut PgConnection, persona_given: &PublicKey) -> Result<Multisig, Error> {
+    use crate::schema::kv_multisigs::dsl::*;
+
+    let persona_vec = persona_given.serialize().to_vec();
+    if let Some(found) = find_by_persona(conn, &persona_vec)? {
+        return Ok(found);
+    }
+
+    insert_into(kv_multisigs)
+        .values(persona.eq(&persona_vec))
+        .get_result(conn)
+        .map_err(|e| e.into())
+}
+
+/// A chain link of m-of-n avatar waiting for enough signatures.
+#[derive(Identifiable, Queryable, Serialize, Deserialize, Debug)]
+#[diesel(table_name = kv_proposals)]
+pub struct Proposal {
+    pub id: i32,
+    pub uuid: Uuid,
+    pub persona: Vec<u8>,
+    pub platform: String,
+    pub identity: String,
+    pub patch: Value,
+    pub previous_id: Option<i32>,
+    pub signature_payload: String,
+    pub action: String,
+    pub created_at: NaiveDateTime,
+    pub finalized_at: Option<NaiveDateTime>,
+    pub kv_chain_id: Option<i32>,
+}
+
+#[derive(Insertable, Debug)]
+#[diesel(table_name = kv_proposals)]
+pub struct NewProposal {
+    pub uuid: Uuid,
+    pub persona: Vec<u8>,
+    pub platform: String,
+    pub identity: String,
+    pub patch: Value,
+    pub previous_id: Option<i32>,
+    pub signature_payload: String,
+    pub action: String,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Identifiable, Queryable, Associations, Serialize, Deserialize, Debug)]
+#[diesel(table_name = kv_proposal_signatures, belongs_to(Proposal))]
+pub struct ProposalSignature {
+    pub id: i32,
+    pub proposal_id: i32,
+    pub signer: Vec<u8>,
+    pub signature: Vec<u8>,
+    pub created_at: NaiveDateTime,
+}
+
+impl Proposal {
+    /// Save a new proposal. `new_kv.signature_payload` should be prepared.
+    pub fn create(conn: &mut PgConnection, new_kv: &NewKVChain) -> Result<Proposal, Error> {
+        insert_into(kv_proposals::table)
+            .values(&NewProposal {
+                uuid: new_kv.uuid,
+                persona: new_kv.persona.clone(),
+                platform: new_kv.platform.clone(),
+                identity: new_kv.identity.clone(),
+                patch: new_kv.patch.clone(),
+                previous_id: new_kv.previous_id,
+                signature_payload: new_kv.signature_payload.clone(),
+                action: new_kv.action.clone(),
+                created_at: new_kv.created_at,
+            })
+            .get_result(conn)
+            .map_err(|e| e.into())
+    }
+
+    pub fn find_by_uuid(conn: &mut PgConnection, uuid_given: Uuid) -> Result<Option<Proposal>, Error> {
+        use crate::schema::kv_proposals::dsl::*;
+
+        kv_proposals
+            .filter(uuid.eq(uuid_given))
+            .first(conn)
+            .optional()
+            .map_err(|e| e.into())
+    }
+
+    /// Signatures collected so far.
+    pub fn signatures(&self, conn: &mut PgConnection) -> Result<Vec<ProposalSignature>, Error> {
+        use crate::schema::kv_proposal_signatures::dsl::*;
+
+        ProposalSignature::belonging_to(self)
+            .order(signer.asc())
+            .load(conn)
+            .map_err(|e| e.into())
+    }
+
+    /// Verify a signature over `self.signature_payload` and record it.
+    /// Signing twice by the same signer is a no-op.
+    pub fn add_signature(
+        &self,
+        conn: &mut PgConnection,
+        multisig: &Multisig,
+        sig: &Vec<u8>,
+    ) -> Result<PublicKey, Error> {
+        use crate::schema::kv_proposal_signatures::dsl::*;
+
+        if self.finalized_at.is_some() {
+            return Err(Error::General(
+                format!("Proposal already finalized: {}", self.uuid),
+                StatusCode::CONFLICT,
+            ));
+        }
+        let signer_key = multisig
+            .setting()?
+            .verify_signer(sig, &self.signature_payload)?;
+
+        insert_into(kv_proposal_signatures)
+            .values((
+                proposal_id.eq(self.id),
+                signer.eq(signer_key.serialize_compressed().to_vec()),
+                signature.eq(sig),
+            ))
+            .on_conflict((proposal_id, signer))
+            .do_nothing()
+            .execute(conn)?;
+
+        Ok(signer_key)
+    }
+
+    /// Apply this proposal as a new chain link if `threshold` distinct
+    /// signers of current multisig set have signed.
+    /// Those signatures (ordered by compressed signer key) are stored in
+    /// `signer_signatures` of the link, and the first one in `signature`.
+    pub fn try_finalize(
+        &self,
+        conn: &mut PgConnection,
+        multisig: &Multisig,
+    ) -> Result<Option<KVChain>, Error> {
+        use crate::schema::kv_proposals::dsl::*;
+
+        let setting = multisig.setting()?;
+        let signer_keys: Vec<Vec<u8>> = setting
+            .signer_keys()?
+            .iter()
+            .map(|key| key.serialize_compressed().to_vec())
+            .collect();
+        let collected: Vec<ProposalSignature> = self
+            .signatures(conn)?
+            .into_iter()
+            .filter(|sig| signer_keys.contains(&sig.signer))
+            .collect();
+        if collected.len() < setting.threshold {
+            return Ok(None);
+        }
+
+        let Secp256k1KeyPair {
+            public_key: persona_key,
+            secret_key: _,
+        } = Secp256k1KeyPair::from_pubkey_vec(&self.persona)?;
+        let mut new_kv = NewKVChain::for_persona(conn, &persona_key)?;
+        if new_kv.previous_id != self.previous_id {
+            return Err(Error::General(
+                format!("Proposal is stale, chain has moved on: {}", self.uuid),
+                StatusCode::CONFLICT,
+            ));
+        }
+        new_kv.uuid = self.uuid;
+        new_kv.platform = self.platform.clone();
+        new_kv.identity = self.identity.clone();
+        new_kv.patch = self.patch.clone();
+        new_kv.action = self.action.clone();
+        new_kv.created_at = self.created_at;
+        new_kv.signature_payload = self.signature_payload.clone();
+        let signer_signatures: Vec<SignerSignature> = collected
+            .iter()
+            .take(setting.threshold)
+            .map(|sig| SignerSignature {
+                signer: format!("0x{}", hex::encode(&sig.signer)),
+                signature: sig.signature.clone(),
+            })
+            .collect();
+        new_kv.signature = signer_signatures[0].signature.clone();
+
+        let link = new_kv
+            .finalize(conn)?
+            .insert_signer_signatures(conn, &signer_signatures)?;
+        link.perform_patch(conn)?;
+        diesel::update(self)
+            .set((finalized_at.eq(Some(naive_now())), kv_chain_id.eq(Some(link.id))))
+            .execute(conn)?;
+
+        Ok(Some(link))
+    }
+}
@@ -0,0 +1,110 @@
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::{
+        crypto::{secp256k1::Secp256k1KeyPair, util::compress_public_key},
+        error::Error,
+        model::{
+            establish_connection,
+            multisig::{find_by_persona, find_or_create, MultisigSetting, SignerSignature},
+        },
+    };
+
+    #[test]
+    fn test_patch_settings() -> Result<(), Error> {
+        let _ = env_logger::try_init();
+        let mut conn = establish_connection();
+        let owner = Secp256k1KeyPair::generate();
+        let signer_1 = compress_public_key(&Secp256k1KeyPair::generate().public_key);
+        let signer_2 = compress_public_key(&Secp256k1KeyPair::generate().public_key);
+        let persona_vec = owner.public_key.serialize().to_vec();
+
+        let multisig = find_or_create(&mut conn, &owner.public_key)?;
+        multisig.patch(&mut conn, &json!({"threshold": 2, "signers": [signer_1, signer_2]}))?;
+        let found = find_by_persona(&mut conn, &persona_vec)?.unwrap();
+        assert_eq!(2, found.setting()?.threshold);
+        assert_eq!(2, found.setting()?.signer_keys()?.len());
+
+        // Turn it off
+        found.patch(&mut conn, &json!({"threshold": null, "signers": null}))?;
+        assert!(find_by_persona(&mut conn, &persona_vec)?.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_patch_invalid_settings() -> Result<(), Error> {
+        let mut conn = establish_connection();
+        let owner = Secp256k1KeyPair::generate();
+        let signer = compress_public_key(&Secp256k1KeyPair::generate().public_key);
+        let multisig = find_or_create(&mut conn, &owner.public_key)?;
+
+        assert!(multisig
+            .patch(&mut conn, &json!({"threshold": 2, "signers": [signer.clone()]}))
+            .is_err());
+        assert!(multisig
+            .patch(&mut conn, &json!({"threshold": 0, "signers": [signer.clone()]}))
+            .is_err());
+        assert!(multisig
+            .patch(&mut conn, &json!({"threshold": 1, "signers": [signer.clone(), signer]}))
+            .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_verify_signer() -> Result<(), Error> {
+        let mut conn = establish_connection();
+        let owner = Secp256k1KeyPair::generate();
+        let signer = Secp256k1KeyPair::generate();
+        let multisig = find_or_create(&mut conn, &owner.public_key)?;
+        multisig.patch(
+            &mut conn,
+            &json!({"threshold": 1, "signers": [compress_public_key(&signer.public_key)]}),
+        )?;
+        let setting = find_by_persona(&mut conn, &owner.public_key.serialize().to_vec())?
+            .unwrap()
+            .setting()?;
+
+        let payload = "payload".to_string();
+        assert_eq!(
+            signer.public_key,
+            setting.verify_signer(&signer.personal_sign(&payload)?, &payload)?
+        );
+        assert!(setting
+            .verify_signer(&owner.personal_sign(&payload)?, &payload)
+            .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_verify_signatures() -> Result<(), Error> {
+        let signers: Vec<Secp256k1KeyPair> = (0..2).map(|_| Secp256k1KeyPair::generate()).collect();
+        let setting = MultisigSetting {
+            threshold: 2,
+            signers: signers
+                .iter()
+                .map(|signer| compress_public_key(&signer.public_key))
+                .collect(),
+        };
+        let payload = "payload".to_string();
+        let signed_by = |signer: &Secp256k1KeyPair| -> Result<SignerSignature, Error> {
+            Ok(SignerSignature {
+                signer: format!("0x{}", compress_public_key(&signer.public_key)),
+                signature: signer.personal_sign(&payload)?,
+            })
+        };
+
+        let both = vec![signed_by(&signers[0])?, signed_by(&signers[1])?];
+        assert!(setting.verify_signatures(&both, &payload).is_ok());
+
+        // Same signer twice
+        let twice = vec![signed_by(&signers[0])?, signed_by(&signers[0])?];
+        assert!(setting.verify_signatures(&twice, &payload).is_err());
+
+        // Signature of another signer than claimed
+        let mut swapped = both.clone();
+        swapped[0].signer = both[1].signer.clone();
+        assert!(setting.verify_signatures(&swapped, &payload).is_err());
+        Ok(())
+    }
+}
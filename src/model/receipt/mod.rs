@@ -0,0 +1,181 @@
+mod tests;
+
+use ::uuid::Uuid;
+use chrono::NaiveDateTime;
+use diesel::{insert_into, prelude::*, PgConnection};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::{ConfigSigner, C},
+    crypto::{
+        secp256k1::Secp256k1KeyPair,
+        util::{compress_public_key, hash_keccak256},
+    },
+    error::Error,
+    model::kv_chains::KVChain,
+    schema::{kv_receipts, kv_receipts::dsl::*},
+    util::{naive_now, vec_to_base64},
+};
+
+lazy_static! {
+    /// Keypair of `C.signer`, parsed once.  `Ok(None)` if signer is not
+    /// configured.
+    static ref SIGNER: Result<Option<Secp256k1KeyPair>, String> =
+        parse_signer(C.signer.as_ref()).map_err(|err| err.to_string());
+}
+
+/// Keypair of `C.signer`.  Call it at startup, so a malformed key is
+/// reported before any patch is accepted.
+pub fn signer() -> Result<Option<&'static Secp256k1KeyPair>, Error> {
+    match SIGNER.as_ref() {
+        Ok(keypair) => Ok(keypair.as_ref()),
+        Err(err) => Err(Error::ConfigError(config::ConfigError::Message(err.clone()))),
+    }
+}
+
+fn parse_signer(signer_config: Option<&ConfigSigner>) -> Result<Option<Secp256k1KeyPair>, Error> {
+    match signer_config {
+        Some(signer_config) => Secp256k1KeyPair::from_secret_key_hex(&signer_config.secret_key)
+            .map(Some)
+            .map_err(|err| {
+                Error::ConfigError(config::ConfigError::Message(format!(
+                    "signer.secret_key: {}",
+                    err
+                )))
+            }),
+        None => Ok(None),
+    }
+}
+
+/// Server-signed proof that a KVChain link has been accepted.
+#[derive(Identifiable, Queryable, Serialize, Deserialize, Debug)]
+#[diesel(table_name = kv_receipts)]
+pub struct Receipt {
+    pub id: i32,
+    pub uuid: Uuid,
+    pub kv_chain_id: i32,
+    pub payload: String,
+    pub signature: Vec<u8>,
+    pub signer: Vec<u8>,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Insertable, Debug)]
+#[diesel(table_name = kv_receipts)]
+pub struct NewReceipt {
+    pub uuid: Uuid,
+    pub kv_chain_id: i32,
+    pub payload: String,
+    pub signature: Vec<u8>,
+    pub signer: Vec<u8>,
+    pub created_at: NaiveDateTime,
+}
+
+/// Body which server signs to (`eth_personalSign` of its JSON string).
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ReceiptPayload {
+    pub version: String,
+    /// UUID of accepted link.
+    pub uuid: Uuid,
+    /// Avatar public key of this chain (compressed hexstring, without `0x`).
+    pub avatar: String,
+    /// 1-based position of this link in avatar's chain.
+    pub position: i64,
+    /// `0x` + hex of `keccak256(signature_payload)` of accepted link.
+    pub content_hash: String,
+    /// UNIX timestamp of acceptance.
+    pub created_at: i64,
+}
+
+impl Receipt {
+    /// Sign a receipt of given link using `C.signer`, then save it.
+    /// `None` if signer is not configured.
+    pub fn issue(conn: &mut PgConnection, link: &KVChain) -> Result<Option<Receipt>, Error> {
+        match signer()? {
+            Some(keypair) => Self::sign_and_save(conn, link, keypair).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Sign a receipt of given link using given keypair, then save it.
+    pub fn sign_and_save(
+        conn: &mut PgConnection,
+        link: &KVChain,
+        keypair: &Secp256k1KeyPair,
+    ) -> Result<Receipt, Error> {
+        let position: i64 = {
+            use crate::schema::kv_chains::dsl as kv_chains_dsl;
+            kv_chains_dsl::kv_chains
+                .filter(kv_chains_dsl::persona.eq(&link.persona))
+                .filter(kv_chains_dsl::id.le(link.id))
+                .count()
+                .get_result(conn)?
+        };
+        let Secp256k1KeyPair {
+            public_key: avatar,
+            secret_key: _,
+        } = Secp256k1KeyPair::from_pubkey_vec(&link.persona)?;
+        let now = naive_now();
+
+        let receipt_payload = ReceiptPayload {
+            version: "1".into(),
+            uuid: link.uuid,
+            avatar: compress_public_key(&avatar),
+            position,
+            content_hash: format!(
+                "0x{}",
+                hex::encode(hash_keccak256(&link.signature_payload))
+            ),
+            created_at: now.timestamp(),
+        };
+        let payload_string = serde_json::to_string(&receipt_payload)?;
+        let sig = keypair.personal_sign(&payload_string)?;
+
+        insert_into(kv_receipts)
+            .values(&NewReceipt {
+                uuid: link.uuid,
+                kv_chain_id: link.id,
+                payload: payload_string,
+                signature: sig,
+                signer: keypair.public_key.serialize_compressed().to_vec(),
+                created_at: now,
+            })
+            .get_result(conn)
+            .map_err(|e| e.into())
+    }
+
+    /// Find receipt by UUID of its link.
+    pub fn find_by_uuid(conn: &mut PgConnection, uuid_given: Uuid) -> Result<Option<Receipt>, Error> {
+        kv_receipts
+            .filter(uuid.eq(uuid_given))
+            .first(conn)
+            .optional()
+            .map_err(|e| e.into())
+    }
+
+    /// Parse signed payload of this receipt.
+    pub fn parsed_payload(&self) -> Result<ReceiptPayload, Error> {
+        serde_json::from_str(&self.payload).map_err(|e| e.into())
+    }
+
+    /// Verify if this receipt is signed by `self.signer`.
+    pub fn validate(&self) -> Result<(), Error> {
+        let recovered = Secp256k1KeyPair::recover_from_personal_signature(&self.signature, &self.payload)?;
+        if recovered.serialize_compressed().to_vec() != self.signer {
+            return Err(Error::SignatureValidationError(
+                "Receipt signer mismatch".into(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// `"0xHEXSTRING"` of server signer public key. Compressed form.
+    pub fn signer_hex(&self) -> String {
+        format!("0x{}", hex::encode(&self.signer))
+    }
+
+    /// Base64 of signature.
+    pub fn signature_base64(&self) -> String {
+        vec_to_base64(&self.signature)
+    }
+}
@@ -0,0 +1,107 @@
+#[cfg(test)]
+mod tests {
+    use diesel::{insert_into, PgConnection, RunQueryDsl};
+    use fake::{Fake, Faker};
+    use libsecp256k1::PublicKey;
+    use serde_json::json;
+
+    use crate::{
+        config::ConfigSigner,
+        crypto::{secp256k1::Secp256k1KeyPair, util::hash_keccak256},
+        error::{Error, ErrorCode},
+        model::{
+            establish_connection,
+            kv_chains::{KVChain, NewKVChain},
+            receipt::{parse_signer, Receipt},
+        },
+        schema::kv_chains::dsl::*,
+        util::naive_now,
+    };
+
+    fn create_link_and_insert(
+        conn: &mut PgConnection,
+        persona_pubkey: &PublicKey,
+        previous: Option<i32>,
+    ) -> Result<KVChain, Error> {
+        insert_into(kv_chains)
+            .values(&NewKVChain {
+                uuid: ::uuid::Uuid::new_v4(),
+                persona: persona_pubkey.serialize().to_vec(),
+                platform: Faker.fake(),
+                identity: Faker.fake(),
+                patch: json!({ "test": "abc" }),
+                previous_id: previous,
+                signature: vec![1],
+                signature_payload: Faker.fake(),
+                created_at: naive_now(),
+                action: "kv".into(),
+            })
+            .get_result(conn)
+            .map_err(|e| e.into())
+    }
+
+    #[test]
+    fn test_sign_and_find() -> Result<(), Error> {
+        let _ = env_logger::try_init();
+        let mut conn = establish_connection();
+        let server = Secp256k1KeyPair::generate();
+        let avatar = Secp256k1KeyPair::generate();
+        let first = create_link_and_insert(&mut conn, &avatar.public_key, None)?;
+        let second = create_link_and_insert(&mut conn, &avatar.public_key, Some(first.id))?;
+
+        let receipt = Receipt::sign_and_save(&mut conn, &second, &server)?;
+        let payload = receipt.parsed_payload()?;
+        assert_eq!(payload.uuid, second.uuid);
+        assert_eq!(payload.position, 2);
+        assert_eq!(
+            payload.content_hash,
+            format!("0x{}", hex::encode(hash_keccak256(&second.signature_payload)))
+        );
+
+        let found = Receipt::find_by_uuid(&mut conn, second.uuid)?.unwrap();
+        assert_eq!(found.id, receipt.id);
+        assert!(Receipt::find_by_uuid(&mut conn, ::uuid::Uuid::new_v4())?.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_verify_offline() -> Result<(), Error> {
+        let _ = env_logger::try_init();
+        let mut conn = establish_connection();
+        let server = Secp256k1KeyPair::generate();
+        let avatar = Secp256k1KeyPair::generate();
+        let link = create_link_and_insert(&mut conn, &avatar.public_key, None)?;
+
+        let mut receipt = Receipt::sign_and_save(&mut conn, &link, &server)?;
+        assert!(receipt.validate().is_ok());
+        let recovered =
+            Secp256k1KeyPair::recover_from_personal_signature(&receipt.signature, &receipt.payload)?;
+        assert_eq!(recovered, server.public_key);
+
+        receipt.payload = receipt.payload.replace(r#""position":1"#, r#""position":2"#);
+        assert!(receipt.validate().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_signer() {
+        assert!(parse_signer(None).unwrap().is_none());
+
+        let keypair = parse_signer(Some(&ConfigSigner {
+            secret_key: "0000000000000000000000000000000000000000000000000000000000000001".into(),
+        }))
+        .unwrap()
+        .unwrap();
+        assert_eq!(
+            "0x7e5f4552091a69125d5dfcb7b8c2659029395bdf",
+            crate::crypto::util::ethereum_address(&keypair.public_key)
+        );
+
+        let err = parse_signer(Some(&ConfigSigner {
+            secret_key: "not a key".into(),
+        }))
+        .unwrap_err();
+        assert_eq!(ErrorCode::ConfigError, err.code());
+        assert!(err.to_string().contains("signer.secret_key"));
+    }
+}
@@ -0,0 +1,347 @@
+mod tests;
+
+use std::collections::HashMap;
+
+use chrono::{Duration, NaiveDateTime};
+use diesel::{prelude::*, PgConnection};
+use libsecp256k1::PublicKey;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    config::C,
+    crypto::{secp256k1::Secp256k1KeyPair, util::compress_public_key},
+    error::Error,
+    schema::kv_visibilities::{self, dsl::*},
+    util::{base64_to_vec, naive_now, timestamp, timestamp_to_naive},
+};
+
+/// Namespace (top-level key of `kv.content`) visibility settings of a
+/// persona. `settings` is patched by `ACTION_VISIBILITY` chain links,
+/// in the form of `{"namespace": NamespaceSetting}`.
+#[derive(Identifiable, Queryable, Serialize, Deserialize, Debug)]
+#[diesel(table_name = kv_visibilities)]
+pub struct Visibility {
+    pub id: i32,
+    pub persona: Vec<u8>,
+    pub settings: Value,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NamespaceVisibility {
+    Public,
+    Private,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct NamespaceSetting {
+    pub visibility: NamespaceVisibility,
+    /// Avatars (public key hexstring) which can read this namespace
+    /// besides the owner.
+    #[serde(default)]
+    pub allow: Vec<String>,
+}
+
+impl NamespaceSetting {
+    /// Determine if given reader can read this namespace.
+    /// Owner check should be done by caller.
+    pub fn can_read(&self, reader: Option<&PublicKey>) -> bool {
+        match self.visibility {
+            NamespaceVisibility::Public => true,
+            NamespaceVisibility::Private => reader.is_some_and(|reader_pk| {
+                self.allow.iter().any(|allowed| {
+                    Secp256k1KeyPair::from_pubkey_hex(allowed)
+                        .map(|pair| pair.public_key == *reader_pk)
+                        .unwrap_or(false)
+                })
+            }),
+        }
+    }
+}
+
+impl Visibility {
+    /// Apply a patch JSON onto current settings.
+    pub fn patch(&self, conn: &mut PgConnection, patch: &Value) -> Result<(), Error> {
+        let mut patched_settings = self.settings.clone();
+        json_patch::merge(&mut patched_settings, patch);
+        parse_settings(&patched_settings)?;
+
+        diesel::update(self)
+            .set(settings.eq(patched_settings))
+            .execute(conn)?;
+        Ok(())
+    }
+
+    /// Parsed settings of each namespace.
+    pub fn namespaces(&self) -> Result<HashMap<String, NamespaceSetting>, Error> {
+        parse_settings(&self.settings)
+    }
+
+    /// Remove namespaces of `content` which given reader cannot read.
+    /// If stored settings cannot be parsed, every namespace listed in
+    /// them is removed.
+    pub fn redact(&self, content: &Value, reader: Option<&PublicKey>) -> Value {
+        if reader.is_some_and(|reader_pk| reader_pk.serialize().to_vec() == self.persona) {
+            return content.clone();
+        }
+        let namespaces = self.namespaces();
+        if let Err(err) = &namespaces {
+            warn!("Hiding all listed namespaces: {}", err);
+        }
+        let can_read = |namespace: &str| match &namespaces {
+            Ok(namespaces) => namespaces
+                .get(namespace)
+                .is_none_or(|setting| setting.can_read(reader)),
+            Err(_) => self.settings.get(namespace).is_none(),
+        };
+        match content {
+            Value::Object(map) => Value::Object(
+                map.iter()
+                    .filter(|(namespace, _)| can_read(namespace.as_str()))
+                    .map(|(k, v)| (k.clone(), v.clone()))
+                    .collect(),
+            ),
+            _ => content.clone(),
+        }
+    }
+}
+
+fn parse_settings(value: &Value) -> Result<HashMap<String, NamespaceSetting>, Error> {
+    serde_json::from_value(value.clone())
+        .map_err(|e| Error::ParamError(format!("invalid visibility settings: {}", e)))
+}
+
+/// Find visibility settings of given persona (serialized public key).
+pub fn find_by_persona(
+    conn: &mut PgConnection,
+    persona_vec: &Vec<u8>,
+) -> Result<Option<Visibility>, Error> {
+    kv_visibilities
+        .filter(persona.eq(persona_vec))
+        .first(conn)
+        .optional()
+        .map_err(|e| e.into())
+}
+
+/// Find visibility settings of given persona, create an empty one if
+/// not exist.
+pub fn find_or_create(
+    conn: &mut PgConnection,
+    persona_given: &PublicKey,
+) -> Result<Visibility, Error> {
+    let persona_vec = persona_given.serialize().to_vec();
+    if let Some(found) = find_by_persona(conn, &persona_vec)? {
+        return Ok(found);
+    }
+
+    diesel::insert_into(kv_visibilities::table)
+        .values(persona.eq(&persona_vec))
+        .get_result(conn)
+        .map_err(|e| e.into())
+}
+
+/// Remove namespaces which given reader cannot read from KV content
+/// owned by `persona_vec`.  A reader verified for another avatar reads
+/// as anonymous.
+pub fn redact_content(
+    conn: &mut PgConnection,
+    persona_vec: &Vec<u8>,
+    content: &Value,
+    reader: Option<&Reader>,
+) -> Result<Value, Error> {
+    let reader_pk = reader
+        .filter(|reader| reader.avatar.serialize().to_vec() == *persona_vec)
+        .map(|reader| &reader.public_key);
+    Ok(match find_by_persona(conn, persona_vec)? {
+        Some(visibility) => visibility.redact(content, reader_pk),
+        None => content.clone(),
+    })
+}
+
+/// Body a reader signs to (`eth_personalSign` of its JSON string) to
+/// read private namespaces of `avatar`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ReadRequestPayload {
+    pub action: String,
+    /// Reader public key (compressed hexstring, without `0x`).
+    pub reader: String,
+    /// Avatar to read (compressed hexstring, without `0x`).
+    pub avatar: String,
+    /// Chosen by reader.  Each one is accepted only once.
+    pub nonce: String,
+    pub created_at: i64,
+}
+
+/// Signed read request carried by `GET` queries.
+#[derive(Clone, Debug)]
+pub struct ReadRequest {
+    pub reader: String,
+    pub avatar: String,
+    pub nonce: String,
+    pub signature: String,
+    pub created_at: i64,
+}
+
+/// Reader verified by a `ReadRequest`, for given avatar only.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Reader {
+    pub public_key: PublicKey,
+    pub avatar: PublicKey,
+}
+
+impl Reader {
+    /// Owner reading its own avatar.
+    pub fn owner(avatar: &PublicKey) -> Self {
+        Self {
+            public_key: *avatar,
+            avatar: *avatar,
+        }
+    }
+}
+
+/// Max length of `reader_nonce`.
+const MAX_NONCE_LENGTH: usize = 64;
+
+impl ReadRequest {
+    /// Parse `reader`, `reader_avatar` (defaults to `avatar`),
+    /// `reader_nonce`, `reader_signature` and `reader_created_at` from
+    /// query params. `None` if `reader` is not given.
+    pub fn from_params(params: &HashMap<String, String>) -> Result<Option<Self>, Error> {
+        let reader = match params.get("reader") {
+            Some(reader) => reader.clone(),
+            None => return Ok(None),
+        };
+        let avatar = params
+            .get("reader_avatar")
+            .or(params.get("avatar"))
+            .ok_or_else(|| Error::ParamMissing("reader_avatar".into()))?
+            .clone();
+        let nonce = params
+            .get("reader_nonce")
+            .ok_or_else(|| Error::ParamMissing("reader_nonce".into()))?
+            .clone();
+        if nonce.is_empty() || nonce.len() > MAX_NONCE_LENGTH {
+            return Err(Error::ParamError(format!(
+                "reader_nonce: must be 1 to {} characters",
+                MAX_NONCE_LENGTH
+            )));
+        }
+        let signature = params
+            .get("reader_signature")
+            .ok_or_else(|| Error::ParamMissing("reader_signature".into()))?
+            .clone();
+        let created_at_given = params
+            .get("reader_created_at")
+            .ok_or_else(|| Error::ParamMissing("reader_created_at".into()))?
+            .parse::<i64>()
+            .map_err(|e| Error::ParamError(format!("reader_created_at: {}", e)))?;
+
+        Ok(Some(Self {
+            reader,
+            avatar,
+            nonce,
+            signature,
+            created_at: created_at_given,
+        }))
+    }
+
+    /// Generate sign payload of a read request.
+    pub fn payload(
+        reader_pk: &PublicKey,
+        avatar_pk: &PublicKey,
+        nonce_given: &str,
+        created_at_given: i64,
+    ) -> ReadRequestPayload {
+        ReadRequestPayload {
+            action: "read".into(),
+            reader: compress_public_key(reader_pk),
+            avatar: compress_public_key(avatar_pk),
+            nonce: nonce_given.into(),
+            created_at: created_at_given,
+        }
+    }
+
+    /// Validate freshness and signature of this request, then mark its
+    /// nonce as used.
+    pub fn verify(&self, conn: &mut PgConnection) -> Result<Reader, Error> {
+        let Secp256k1KeyPair {
+            public_key: reader_pk,
+            secret_key: _,
+        } = Secp256k1KeyPair::from_pubkey_hex(&self.reader)?;
+        let Secp256k1KeyPair {
+            public_key: avatar_pk,
+            secret_key: _,
+        } = Secp256k1KeyPair::from_pubkey_hex(&self.avatar)?;
+        if (timestamp() - self.created_at).abs() > C.payload.read_window {
+            return Err(Error::SignatureValidationError(
+                "Read request expired".into(),
+            ));
+        }
+
+        let payload_string = serde_json::to_string(&Self::payload(
+            &reader_pk,
+            &avatar_pk,
+            &self.nonce,
+            self.created_at,
+        ))?;
+        let recovered = Secp256k1KeyPair::recover_from_personal_signature(
+            &base64_to_vec(&self.signature)?,
+            &payload_string,
+        )?;
+        if recovered != reader_pk {
+            return Err(Error::SignatureValidationError(
+                "Reader public key mismatch".into(),
+            ));
+        }
+
+        consume_nonce(conn, &reader_pk, &self.nonce, self.created_at)?;
+        Ok(Reader {
+            public_key: reader_pk,
+            avatar: avatar_pk,
+        })
+    }
+}
+
+/// Record a used read request nonce of given reader.  Kept until its
+/// request could no longer pass the `read_window` check.
+fn consume_nonce(
+    conn: &mut PgConnection,
+    reader_pk: &PublicKey,
+    nonce_given: &str,
+    created_at_given: i64,
+) -> Result<(), Error> {
+    use crate::schema::kv_read_nonces::dsl as read_nonces;
+
+    diesel::delete(read_nonces::kv_read_nonces.filter(read_nonces::expired_at.lt(naive_now())))
+        .execute(conn)?;
+    diesel::insert_into(read_nonces::kv_read_nonces)
+        .values((
+            read_nonces::reader.eq(reader_pk.serialize().to_vec()),
+            read_nonces::nonce.eq(nonce_given),
+            read_nonces::expired_at.eq(
+                timestamp_to_naive(created_at_given) + Duration::seconds(C.payload.read_window),
+            ),
+        ))
+        .on_conflict_do_nothing()
+        .execute(conn)
+        .map_err(Error::from)
+        .and_then(|inserted| match inserted {
+            0 => Err(Error::PayloadAlreadyUsed(format!("reader_nonce {}", nonce_given))),
+            _ => Ok(()),
+        })
+}
+
+/// Verified reader from query params, if any.
+pub fn reader_from_params(
+    conn: &mut PgConnection,
+    params: &HashMap<String, String>,
+) -> Result<Option<Reader>, Error> {
+    ReadRequest::from_params(params)?
+        .map(|read_request| read_request.verify(conn))
+        .transpose()
+}
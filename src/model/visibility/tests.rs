@@ -0,0 +1,167 @@
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use libsecp256k1::PublicKey;
+    use serde_json::json;
+
+    use crate::{
+        crypto::{secp256k1::Secp256k1KeyPair, util::compress_public_key},
+        error::Error,
+        model::{
+            establish_connection,
+            visibility::{
+                find_or_create, reader_from_params, redact_content, ReadRequest, Reader,
+                Visibility,
+            },
+        },
+        util::{naive_now, timestamp, vec_to_base64},
+    };
+
+    fn signed_params(
+        keypair: &Secp256k1KeyPair,
+        avatar: &PublicKey,
+        created_at: i64,
+    ) -> HashMap<String, String> {
+        let nonce = uuid::Uuid::new_v4().to_string();
+        let payload = ReadRequest::payload(&keypair.public_key, avatar, &nonce, created_at);
+        let signature = keypair
+            .personal_sign(&serde_json::to_string(&payload).unwrap())
+            .unwrap();
+        HashMap::from([
+            ("reader".to_string(), compress_public_key(&keypair.public_key)),
+            ("reader_avatar".to_string(), compress_public_key(avatar)),
+            ("reader_nonce".to_string(), nonce),
+            ("reader_signature".to_string(), vec_to_base64(&signature)),
+            ("reader_created_at".to_string(), created_at.to_string()),
+        ])
+    }
+
+    fn reader(keypair: &Secp256k1KeyPair, avatar: &Secp256k1KeyPair) -> Reader {
+        Reader {
+            public_key: keypair.public_key,
+            avatar: avatar.public_key,
+        }
+    }
+
+    #[test]
+    fn test_patch_and_redact() -> Result<(), Error> {
+        let _ = env_logger::try_init();
+        let mut conn = establish_connection();
+        let owner = Secp256k1KeyPair::generate();
+        let friend = Secp256k1KeyPair::generate();
+        let stranger = Secp256k1KeyPair::generate();
+        let persona_vec = owner.public_key.serialize().to_vec();
+        let content = json!({"public": 1, "private": 2, "friends": 3});
+
+        // No settings: everything is public.
+        assert_eq!(content, redact_content(&mut conn, &persona_vec, &content, None)?);
+
+        let visibility = find_or_create(&mut conn, &owner.public_key)?;
+        visibility.patch(
+            &mut conn,
+            &json!({
+                "private": {"visibility": "private"},
+                "friends": {
+                    "visibility": "private",
+                    "allow": [format!("0x{}", compress_public_key(&friend.public_key))],
+                },
+            }),
+        )?;
+
+        assert_eq!(
+            json!({"public": 1}),
+            redact_content(&mut conn, &persona_vec, &content, None)?
+        );
+        assert_eq!(
+            json!({"public": 1}),
+            redact_content(&mut conn, &persona_vec, &content, Some(&reader(&stranger, &owner)))?
+        );
+        assert_eq!(
+            json!({"public": 1, "friends": 3}),
+            redact_content(&mut conn, &persona_vec, &content, Some(&reader(&friend, &owner)))?
+        );
+        assert_eq!(
+            content,
+            redact_content(&mut conn, &persona_vec, &content, Some(&Reader::owner(&owner.public_key)))?
+        );
+
+        // Verified for another avatar only.
+        let other = Secp256k1KeyPair::generate();
+        assert_eq!(
+            json!({"public": 1}),
+            redact_content(&mut conn, &persona_vec, &content, Some(&reader(&friend, &other)))?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_patch_invalid_settings() -> Result<(), Error> {
+        let mut conn = establish_connection();
+        let owner = Secp256k1KeyPair::generate();
+        let visibility = find_or_create(&mut conn, &owner.public_key)?;
+        let result = visibility.patch(&mut conn, &json!({"private": {"visibility": "secret"}}));
+        assert!(result.is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_redact_unparsable_settings() {
+        let owner = Secp256k1KeyPair::generate();
+        let visibility = Visibility {
+            id: 0,
+            persona: owner.public_key.serialize().to_vec(),
+            settings: json!({"private": {"visibility": "secret"}}),
+            created_at: naive_now(),
+            updated_at: naive_now(),
+        };
+        let content = json!({"public": 1, "private": 2});
+
+        assert_eq!(json!({"public": 1}), visibility.redact(&content, None));
+        assert_eq!(content, visibility.redact(&content, Some(&owner.public_key)));
+    }
+
+    #[test]
+    fn test_reader_from_params() -> Result<(), Error> {
+        let mut conn = establish_connection();
+        let reader = Secp256k1KeyPair::generate();
+        let avatar = Secp256k1KeyPair::generate();
+        assert!(reader_from_params(&mut conn, &HashMap::new())?.is_none());
+
+        let params = signed_params(&reader, &avatar.public_key, timestamp());
+        assert_eq!(
+            Some(Reader {
+                public_key: reader.public_key,
+                avatar: avatar.public_key,
+            }),
+            reader_from_params(&mut conn, &params)?
+        );
+
+        // Replayed
+        assert!(matches!(
+            reader_from_params(&mut conn, &params),
+            Err(Error::PayloadAlreadyUsed(_))
+        ));
+
+        // Stale
+        let params = signed_params(&reader, &avatar.public_key, timestamp() - 3600);
+        assert!(reader_from_params(&mut conn, &params).is_err());
+
+        // Signed by someone else
+        let mut params = signed_params(&reader, &avatar.public_key, timestamp());
+        params.insert(
+            "reader".into(),
+            compress_public_key(&Secp256k1KeyPair::generate().public_key),
+        );
+        assert!(reader_from_params(&mut conn, &params).is_err());
+
+        // Signed for another avatar
+        let mut params = signed_params(&reader, &avatar.public_key, timestamp());
+        params.insert(
+            "reader_avatar".into(),
+            compress_public_key(&Secp256k1KeyPair::generate().public_key),
+        );
+        assert!(reader_from_params(&mut conn, &params).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,400 @@
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use http::StatusCode;
+use libsecp256k1::PublicKey;
+
+use super::{
+    cache, client::PROOF_CLIENT, ethereum::EthereumAuthority, query_until, Proof, ProofPersona,
+    ProofQueryResponse, ProofQueryResponsePagination,
+};
+use crate::{
+    config::{ConfigPlatform, ConfigProofAuthority, ConfigProofService, ConfigStaticProof, C},
+    crypto::secp256k1::Secp256k1KeyPair,
+    error::{Error, ErrorCode},
+};
+
+lazy_static! {
+    /// Authority used by controllers, chosen by `proof_service.authority`.
+    pub static ref AUTHORITY: Box<dyn ProofAuthority> = from_config(&C.proof_service);
+}
+
+/// Decides if a persona can set KV of a platform-identity pair.
+#[async_trait]
+pub trait ProofAuthority: Send + Sync {
+    /// `Ok(())` if `persona_pubkey` can set KV of given
+    /// platform-identity pair.
+    async fn can_set_kv(
+        &self,
+        persona_pubkey: &PublicKey,
+        platform: &str,
+        identity: &str,
+    ) -> Result<(), Error>;
+}
+
+/// Build the authority registry described in config.
+pub fn from_config(config: &ConfigProofService) -> Box<dyn ProofAuthority> {
+    let mut registry =
+        RegistryAuthority::new(build(config, &config.authority, &ConfigPlatform::default()));
+    for (platform, platform_config) in config.platforms.iter() {
+        let authority = match platform_config.authority.as_ref() {
+            Some(kind) => Some(build(config, kind, platform_config)),
+            None if platform == "nextid" => Some(Box::new(SelfAuthority) as Box<dyn ProofAuthority>),
+            None => None,
+        };
+        registry = registry.with_platform(platform, PlatformRule::new(authority, platform_config));
+    }
+
+    Box::new(registry)
+}
+
+fn build(
+    config: &ConfigProofService,
+    kind: &ConfigProofAuthority,
+    platform_config: &ConfigPlatform,
+) -> Box<dyn ProofAuthority> {
+    match kind {
+        ConfigProofAuthority::ProofService => Box::new(ProofServiceAuthority::new(&config.url)),
+        ConfigProofAuthority::Static => Box::new(StaticAuthority::new(config.allow.clone())),
+        ConfigProofAuthority::SelfProof => Box::new(SelfAuthority),
+        ConfigProofAuthority::Ethereum => Box::new(EthereumAuthority::new(
+            &platform_config.rpc_url,
+            platform_config.client.clone(),
+        )),
+    }
+}
+
+/// Rules of a single platform in `RegistryAuthority`.
+pub struct PlatformRule {
+    /// `None` to use default authority of registry.
+    pub authority: Option<Box<dyn ProofAuthority>>,
+    /// If not empty, only these identities are accepted.
+    pub allow: Vec<String>,
+    /// Identities always rejected. `*` rejects the whole platform.
+    pub deny: Vec<String>,
+}
+
+impl PlatformRule {
+    pub fn new(authority: Option<Box<dyn ProofAuthority>>, config: &ConfigPlatform) -> Self {
+        Self {
+            authority,
+            allow: config.allow.clone(),
+            deny: config.deny.clone(),
+        }
+    }
+
+    /// Rule which only routes to given authority.
+    pub fn route(authority: Box<dyn ProofAuthority>) -> Self {
+        Self {
+            authority: Some(authority),
+            allow: vec![],
+            deny: vec![],
+        }
+    }
+
+    fn check_lists(&self, platform: &str, identity: &str) -> Result<(), Error> {
+        let denied = self.deny.iter().any(|d| d == "*" || d == identity);
+        let not_allowed = !self.allow.is_empty() && !self.allow.iter().any(|a| a == identity);
+        if denied || not_allowed {
+            return Err(Error::Coded(
+                ErrorCode::IdentityNotAccepted,
+                format!("Identity {} is not accepted on platform {}.", identity, platform),
+                StatusCode::FORBIDDEN,
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Routes each platform to its own authority, checking allow / deny
+/// lists first.  `nextid` is routed to `SelfAuthority` unless
+/// configured otherwise.
+pub struct RegistryAuthority {
+    pub default: Box<dyn ProofAuthority>,
+    pub platforms: HashMap<String, PlatformRule>,
+}
+
+impl RegistryAuthority {
+    pub fn new(default: Box<dyn ProofAuthority>) -> Self {
+        let mut platforms = HashMap::new();
+        platforms.insert("nextid".to_string(), PlatformRule::route(Box::new(SelfAuthority)));
+        Self { default, platforms }
+    }
+
+    pub fn with_platform(mut self, platform: &str, rule: PlatformRule) -> Self {
+        self.platforms.insert(platform.into(), rule);
+        self
+    }
+}
+
+#[async_trait]
+impl ProofAuthority for RegistryAuthority {
+    async fn can_set_kv(
+        &self,
+        persona_pubkey: &PublicKey,
+        platform: &str,
+        identity: &str,
+    ) -> Result<(), Error> {
+        let authority = match self.platforms.get(platform) {
+            Some(rule) => {
+                rule.check_lists(platform, identity)?;
+                rule.authority.as_ref().unwrap_or(&self.default)
+            }
+            None => &self.default,
+        };
+
+        authority.can_set_kv(persona_pubkey, platform, identity).await
+    }
+}
+
+/// Identity must be the persona public key itself (platform `nextid`).
+pub struct SelfAuthority;
+
+#[async_trait]
+impl ProofAuthority for SelfAuthority {
+    async fn can_set_kv(
+        &self,
+        persona_pubkey: &PublicKey,
+        platform: &str,
+        identity: &str,
+    ) -> Result<(), Error> {
+        let Secp256k1KeyPair {
+            public_key: identity_pubkey,
+            secret_key: _,
+        } = Secp256k1KeyPair::from_pubkey_hex(identity)?;
+        if identity_pubkey == *persona_pubkey {
+            Ok(())
+        } else {
+            Err(Error::Coded(
+                ErrorCode::IdentityPersonaMismatch,
+                format!("Identity and persona not match when 'platform' is '{}' .", platform),
+                StatusCode::BAD_REQUEST,
+            ))
+        }
+    }
+}
+
+/// Asks ProofService, with lookups cached in `cache::PROOF_CACHE`.
+pub struct ProofServiceAuthority {
+    pub url: String,
+}
+
+impl ProofServiceAuthority {
+    pub fn new(url: &str) -> Self {
+        Self { url: url.into() }
+    }
+}
+
+#[async_trait]
+impl ProofAuthority for ProofServiceAuthority {
+    async fn can_set_kv(
+        &self,
+        persona_pubkey: &PublicKey,
+        platform: &str,
+        identity: &str,
+    ) -> Result<(), Error> {
+        let persona_compressed_hex = persona_hex(persona_pubkey);
+        let found = |response: &ProofQueryResponse| {
+            check_proofs(response, &persona_compressed_hex, platform, identity).is_ok()
+        };
+        let query_response = cache::PROOF_CACHE
+            .get_or_fetch(&persona_compressed_hex, || {
+                query_until(&PROOF_CLIENT, &self.url, &persona_compressed_hex, found)
+            })
+            .await?;
+        if found(&query_response) || query_response.is_complete() {
+            return check_proofs(&query_response, &persona_compressed_hex, platform, identity);
+        }
+
+        // Cached by a lookup which stopped before the page of this proof.
+        let query_response =
+            query_until(&PROOF_CLIENT, &self.url, &persona_compressed_hex, found).await?;
+        check_proofs(&query_response, &persona_compressed_hex, platform, identity)
+    }
+}
+
+/// Accepts pairs in a fixed allow-list only. For local development.
+pub struct StaticAuthority {
+    pub allow: Vec<ConfigStaticProof>,
+}
+
+impl StaticAuthority {
+    pub fn new(allow: Vec<ConfigStaticProof>) -> Self {
+        Self { allow }
+    }
+}
+
+#[async_trait]
+impl ProofAuthority for StaticAuthority {
+    async fn can_set_kv(
+        &self,
+        persona_pubkey: &PublicKey,
+        platform: &str,
+        identity: &str,
+    ) -> Result<(), Error> {
+        let persona_compressed_hex = persona_hex(persona_pubkey);
+        let matches = |pattern: &str, value: &str| pattern == "*" || pattern == value;
+        let allowed = self.allow.iter().any(|entry| {
+            matches(&entry.persona, &persona_compressed_hex)
+                && matches(&entry.platform, platform)
+                && matches(&entry.identity, identity)
+        });
+        if allowed {
+            Ok(())
+        } else {
+            Err(proof_not_found())
+        }
+    }
+}
+
+/// In-memory ProofService for tests.  Behaves like
+/// `ProofServiceAuthority` against the proofs registered here.
+#[derive(Default)]
+pub struct MockAuthority {
+    /// Accept every pair.
+    pub allow_all: bool,
+    /// Persona compressed hex => proofs.
+    pub proofs: HashMap<String, Vec<Proof>>,
+}
+
+impl MockAuthority {
+    /// Authority which accepts every pair.
+    pub fn allow_all() -> Self {
+        Self {
+            allow_all: true,
+            ..Default::default()
+        }
+    }
+
+    /// Register a valid proof of `platform` / `identity` under persona.
+    pub fn with_proof(self, persona_pubkey: &PublicKey, platform: &str, identity: &str) -> Self {
+        self.push_proof(persona_pubkey, platform, identity, true, "")
+    }
+
+    /// Register a proof which ProofService marked invalid.
+    pub fn with_invalid_proof(
+        self,
+        persona_pubkey: &PublicKey,
+        platform: &str,
+        identity: &str,
+        invalid_reason: &str,
+    ) -> Self {
+        self.push_proof(persona_pubkey, platform, identity, false, invalid_reason)
+    }
+
+    fn push_proof(
+        mut self,
+        persona_pubkey: &PublicKey,
+        platform: &str,
+        identity: &str,
+        is_valid: bool,
+        invalid_reason: &str,
+    ) -> Self {
+        self.proofs
+            .entry(persona_hex(persona_pubkey))
+            .or_default()
+            .push(Proof {
+                platform: platform.into(),
+                identity: identity.into(),
+                created_at: "0".into(),
+                last_checked_at: "0".into(),
+                is_valid,
+                invalid_reason: invalid_reason.into(),
+            });
+        self
+    }
+
+    fn response(&self, persona_compressed_hex: &str) -> ProofQueryResponse {
+        let ids: Vec<ProofPersona> = self
+            .proofs
+            .get(persona_compressed_hex)
+            .map(|proofs| {
+                vec![ProofPersona {
+                    persona: persona_compressed_hex.into(),
+                    proofs: proofs.clone(),
+                }]
+            })
+            .unwrap_or_default();
+
+        ProofQueryResponse {
+            pagination: ProofQueryResponsePagination {
+                total: ids.len() as u32,
+                per: 20,
+                current: 1,
+                next: 0,
+            },
+            ids,
+        }
+    }
+}
+
+#[async_trait]
+impl ProofAuthority for MockAuthority {
+    async fn can_set_kv(
+        &self,
+        persona_pubkey: &PublicKey,
+        platform: &str,
+        identity: &str,
+    ) -> Result<(), Error> {
+        if self.allow_all {
+            return Ok(());
+        }
+        let persona_compressed_hex = persona_hex(persona_pubkey);
+        check_proofs(
+            &self.response(&persona_compressed_hex),
+            &persona_compressed_hex,
+            platform,
+            identity,
+        )
+    }
+}
+
+fn persona_hex(persona_pubkey: &PublicKey) -> String {
+    format!("0x{}", hex::encode(persona_pubkey.serialize_compressed()))
+}
+
+fn proof_not_found() -> Error {
+    Error::Coded(
+        ErrorCode::ProofNotFound,
+        "Proof not found under this persona.".into(),
+        StatusCode::BAD_REQUEST,
+    )
+}
+
+/// Find platform-identity proof of persona in ProofService response.
+fn check_proofs(
+    query_response: &ProofQueryResponse,
+    persona_compressed_hex: &str,
+    platform: &str,
+    identity: &str,
+) -> Result<(), Error> {
+    let persona_found = query_response
+        .ids
+        .iter()
+        .find(|id| id.persona == persona_compressed_hex)
+        .ok_or_else(|| {
+            Error::Coded(
+                ErrorCode::PersonaNotFound,
+                format!(
+                    "Persona not found on ProofService: {}",
+                    persona_compressed_hex
+                ),
+                StatusCode::BAD_REQUEST,
+            )
+        })?;
+
+    let proofs_found: Vec<&Proof> = persona_found
+        .proofs
+        .iter()
+        .filter(|proof| proof.platform == platform && proof.identity == identity)
+        .collect();
+    if proofs_found.is_empty() {
+        return Err(proof_not_found());
+    }
+    // Same proof may be listed more than once across pages.
+    if proofs_found.iter().any(|proof| proof.is_valid) {
+        return Ok(());
+    }
+
+    Err(Error::ProofInvalid(proofs_found[0].invalid_reason.clone()))
+}
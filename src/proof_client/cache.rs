@@ -0,0 +1,333 @@
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use http::StatusCode;
+use tokio::sync::OnceCell;
+
+use crate::{
+    config::{ConfigProofCache, C},
+    error::{Error, ErrorCode},
+    metrics::{PROOF_CACHE_HITS, PROOF_CACHE_MISSES},
+    proof_client::ProofQueryResponse,
+};
+
+lazy_static! {
+    /// Shared cache of `proof_client::query` results.
+    pub static ref PROOF_CACHE: ProofCache = ProofCache::new(C.proof_service.cache.clone());
+}
+
+/// Error of a shared fetch, re-created for every waiter since
+/// `Error` is not `Clone`.
+type SharedResult = Result<Arc<ProofQueryResponse>, (ErrorCode, String, StatusCode)>;
+
+struct CacheEntry {
+    value: Arc<ProofQueryResponse>,
+    /// Insertion order, for evicting the oldest entry.
+    sequence: u64,
+    expires_at: Instant,
+}
+
+/// TTL cache of ProofService lookups, keyed by compressed persona.
+/// Personas not found on ProofService are cached as negative entries
+/// with a shorter TTL. Concurrent lookups of the same persona share a
+/// single request.
+pub struct ProofCache {
+    config: ConfigProofCache,
+    entries: Mutex<HashMap<String, CacheEntry>>,
+    sequence: AtomicU64,
+    /// Bumped by `invalidate` and `clear` (with `entries` locked), so a
+    /// fetch started before is not cached.
+    generation: AtomicU64,
+    inflight: Mutex<HashMap<String, Arc<OnceCell<SharedResult>>>>,
+}
+
+impl ProofCache {
+    pub fn new(config: ConfigProofCache) -> Self {
+        Self {
+            config,
+            entries: Mutex::new(HashMap::new()),
+            sequence: AtomicU64::new(0),
+            generation: AtomicU64::new(0),
+            inflight: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Returns cached response of given persona, or calls `fetch` to
+    /// get one. Errors of `fetch` are not cached.
+    pub async fn get_or_fetch<F, Fut>(
+        &self,
+        persona: &str,
+        fetch: F,
+    ) -> Result<Arc<ProofQueryResponse>, Error>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<ProofQueryResponse, Error>>,
+    {
+        if let Some(cached) = self.get(persona) {
+            PROOF_CACHE_HITS.inc();
+            return Ok(cached);
+        }
+        PROOF_CACHE_MISSES.inc();
+
+        let cell = self
+            .inflight
+            .lock()
+            .unwrap()
+            .entry(persona.to_string())
+            .or_insert_with(|| Arc::new(OnceCell::new()))
+            .clone();
+        let result = cell
+            .get_or_init(|| async {
+                let generation = self.generation.load(Ordering::SeqCst);
+                let fetched = fetch()
+                    .await
+                    .map(Arc::new)
+                    .map_err(|e| (e.code(), e.to_string(), e.http_status()));
+                if let Ok(response) = &fetched {
+                    self.insert(persona, response.clone(), generation);
+                }
+                fetched
+            })
+            .await
+            .clone();
+        {
+            let mut inflight = self.inflight.lock().unwrap();
+            if inflight
+                .get(persona)
+                .is_some_and(|current| Arc::ptr_eq(current, &cell))
+            {
+                inflight.remove(persona);
+            }
+        }
+
+        result.map_err(|(code, message, status)| Error::Coded(code, message, status))
+    }
+
+    /// Cached, unexpired response of given persona.
+    pub fn get(&self, persona: &str) -> Option<Arc<ProofQueryResponse>> {
+        let entries = self.entries.lock().unwrap();
+        entries
+            .get(persona)
+            .filter(|entry| entry.expires_at > Instant::now())
+            .map(|entry| entry.value.clone())
+    }
+
+    /// Cache `value`, unless invalidated since `generation`.
+    fn insert(&self, persona: &str, value: Arc<ProofQueryResponse>, generation: u64) {
+        if self.config.size == 0 {
+            return;
+        }
+        let ttl = if value.ids.is_empty() {
+            self.config.negative_ttl
+        } else {
+            self.config.ttl
+        };
+        let now = Instant::now();
+        let mut entries = self.entries.lock().unwrap();
+        if self.generation.load(Ordering::SeqCst) != generation {
+            return;
+        }
+        if entries.len() >= self.config.size && !entries.contains_key(persona) {
+            entries.retain(|_, entry| entry.expires_at > now);
+        }
+        if entries.len() >= self.config.size && !entries.contains_key(persona) {
+            let oldest = entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.sequence)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                entries.remove(&oldest);
+            }
+        }
+        entries.insert(
+            persona.to_string(),
+            CacheEntry {
+                value,
+                sequence: self.sequence.fetch_add(1, Ordering::Relaxed),
+                expires_at: now + Duration::from_secs(ttl),
+            },
+        );
+    }
+
+    /// Drop cached response of given persona.  Lookups in flight are
+    /// not cached, and later ones don't wait for them.
+    pub fn invalidate(&self, persona: &str) {
+        {
+            let mut entries = self.entries.lock().unwrap();
+            self.generation.fetch_add(1, Ordering::SeqCst);
+            entries.remove(persona);
+        }
+        self.inflight.lock().unwrap().remove(persona);
+    }
+
+    /// Drop all cached responses.
+    pub fn clear(&self) {
+        {
+            let mut entries = self.entries.lock().unwrap();
+            self.generation.fetch_add(1, Ordering::SeqCst);
+            entries.clear();
+        }
+        self.inflight.lock().unwrap().clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.lock().unwrap().is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicUsize;
+
+    use super::*;
+    use crate::proof_client::{ProofPersona, ProofQueryResponsePagination};
+
+    fn config() -> ConfigProofCache {
+        ConfigProofCache {
+            size: 2,
+            ttl: 60,
+            negative_ttl: 60,
+        }
+    }
+
+    fn response(persona: &str) -> ProofQueryResponse {
+        ProofQueryResponse {
+            pagination: ProofQueryResponsePagination {
+                total: 1,
+                per: 20,
+                current: 1,
+                next: 0,
+            },
+            ids: vec![ProofPersona {
+                persona: persona.into(),
+                proofs: vec![],
+            }],
+        }
+    }
+
+    #[tokio::test]
+    async fn test_hit_and_invalidate() {
+        let cache = ProofCache::new(config());
+        let calls = AtomicUsize::new(0);
+        for _ in 0..3 {
+            let result = cache
+                .get_or_fetch("0x02aa", || async {
+                    calls.fetch_add(1, Ordering::SeqCst);
+                    Ok(response("0x02aa"))
+                })
+                .await
+                .unwrap();
+            assert_eq!("0x02aa", result.ids[0].persona);
+        }
+        assert_eq!(1, calls.load(Ordering::SeqCst));
+
+        cache.invalidate("0x02aa");
+        assert!(cache.get("0x02aa").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_invalidate_during_fetch() {
+        let cache = Arc::new(ProofCache::new(config()));
+        let calls = Arc::new(AtomicUsize::new(0));
+        let stale = {
+            let cache = cache.clone();
+            let calls = calls.clone();
+            tokio::spawn(async move {
+                cache
+                    .get_or_fetch("0x02aa", || async move {
+                        calls.fetch_add(1, Ordering::SeqCst);
+                        tokio::time::sleep(Duration::from_millis(50)).await;
+                        Ok(response("0x02aa"))
+                    })
+                    .await
+                    .unwrap()
+            })
+        };
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        cache.invalidate("0x02aa");
+
+        // Does not join the fetch started before invalidation.
+        cache
+            .get_or_fetch("0x02aa", || async {
+                calls.fetch_add(1, Ordering::SeqCst);
+                Ok(response("0x02aa"))
+            })
+            .await
+            .unwrap();
+        assert_eq!(2, calls.load(Ordering::SeqCst));
+        cache.invalidate("0x02aa");
+
+        // Stale response is not cached once it arrives.
+        stale.await.unwrap();
+        assert!(cache.get("0x02aa").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_error_not_cached() {
+        let cache = ProofCache::new(config());
+        let result = cache
+            .get_or_fetch("0x02aa", || async {
+                Err(Error::Coded(
+                    ErrorCode::ProofServiceError,
+                    "down".into(),
+                    StatusCode::BAD_GATEWAY,
+                ))
+            })
+            .await;
+        let err = result.unwrap_err();
+        assert_eq!(StatusCode::BAD_GATEWAY, err.http_status());
+        assert_eq!(ErrorCode::ProofServiceError, err.code());
+        assert!(cache.get("0x02aa").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_evict_oldest() {
+        let cache = ProofCache::new(config());
+        for persona in ["0x01", "0x02", "0x03"] {
+            cache
+                .get_or_fetch(persona, || async { Ok(response(persona)) })
+                .await
+                .unwrap();
+        }
+        assert_eq!(2, cache.len());
+        assert!(cache.get("0x01").is_none());
+        assert!(cache.get("0x03").is_some());
+    }
+
+    #[tokio::test]
+    async fn test_single_flight() {
+        let cache = Arc::new(ProofCache::new(config()));
+        let calls = Arc::new(AtomicUsize::new(0));
+        let tasks: Vec<_> = (0..10)
+            .map(|_| {
+                let cache = cache.clone();
+                let calls = calls.clone();
+                tokio::spawn(async move {
+                    cache
+                        .get_or_fetch("0x02bb", || async move {
+                            calls.fetch_add(1, Ordering::SeqCst);
+                            tokio::time::sleep(Duration::from_millis(50)).await;
+                            Ok(response("0x02bb"))
+                        })
+                        .await
+                        .unwrap()
+                })
+            })
+            .collect();
+        for task in tasks {
+            task.await.unwrap();
+        }
+        assert_eq!(1, calls.load(Ordering::SeqCst));
+    }
+}
@@ -0,0 +1,168 @@
+use crate::{
+    config::C,
+    http_client::{HttpClient, UPSTREAM_PROOF_SERVICE},
+};
+
+lazy_static! {
+    /// Long-lived client to ProofService, shared by all requests.
+    pub static ref PROOF_CLIENT: HttpClient =
+        HttpClient::new(UPSTREAM_PROOF_SERVICE, C.proof_service.client.clone());
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::Ordering;
+
+    use http::StatusCode;
+
+    use crate::{
+        config::ConfigHttpClient,
+        crypto::{secp256k1::Secp256k1KeyPair, util::compress_public_key},
+        error::{Error, ErrorCode},
+        http_client::{
+            tests::{config, mock_server},
+            HttpClient, UPSTREAM_PROOF_SERVICE,
+        },
+        proof_client::{
+            authority::{ProofAuthority, ProofServiceAuthority},
+            query_until, query_with, MAX_PAGES,
+        },
+    };
+
+    const PERSONA: &str = "0x028c3cc9b8e2abd8d03f3a1fd89c4d3ff0dd3ccbbd8d6cdbeb4b6b1e8a8f1e3e3b";
+    const PAGE_1: &str = r#"{"pagination": {"total": 2, "per": 1, "current": 1, "next": 2},
+        "ids": [{"persona": "0x028c3cc9b8e2abd8d03f3a1fd89c4d3ff0dd3ccbbd8d6cdbeb4b6b1e8a8f1e3e3b",
+                 "proofs": [{"platform": "twitter", "identity": "alice", "is_valid": true}]}]}"#;
+    const PAGE_2: &str = r#"{"pagination": {"total": 2, "per": 1, "current": 2, "next": 0},
+        "ids": [{"persona": "0x028c3cc9b8e2abd8d03f3a1fd89c4d3ff0dd3ccbbd8d6cdbeb4b6b1e8a8f1e3e3b",
+                 "proofs": [{"platform": "github", "identity": "alice", "is_valid": true}]}]}"#;
+
+    fn client(config: ConfigHttpClient) -> HttpClient {
+        HttpClient::new(UPSTREAM_PROOF_SERVICE, config)
+    }
+
+    #[tokio::test]
+    async fn test_follow_pagination() {
+        let (base, hits) = mock_server(vec![(200, PAGE_1, 0), (200, PAGE_2, 0)]).await;
+
+        let response = query_with(&client(config()), &base, PERSONA).await.unwrap();
+        assert_eq!(2, hits.load(Ordering::SeqCst));
+        assert_eq!(1, response.ids.len());
+        assert_eq!(2, response.ids[0].proofs.len());
+    }
+
+    #[tokio::test]
+    async fn test_too_many_pages() {
+        // Every page says there is a next one.
+        let (base, hits) = mock_server(vec![(200, PAGE_1, 0)]).await;
+
+        let err = query_with(&client(config()), &base, PERSONA).await.unwrap_err();
+        assert_eq!(MAX_PAGES as usize, hits.load(Ordering::SeqCst));
+        assert_eq!(ErrorCode::ProofServiceError, err.code());
+        assert_eq!(StatusCode::BAD_GATEWAY, err.http_status());
+    }
+
+    #[tokio::test]
+    async fn test_stop_when_found() {
+        let (base, hits) = mock_server(vec![(200, PAGE_1, 0), (200, PAGE_2, 0)]).await;
+
+        let response = query_until(&client(config()), &base, PERSONA, |response| {
+            response.ids.iter().any(|id| id.proofs.iter().any(|proof| proof.platform == "twitter"))
+        })
+        .await
+        .unwrap();
+        assert_eq!(1, hits.load(Ordering::SeqCst));
+        assert!(!response.is_complete());
+    }
+
+    /// A proof on a later page than the one of a cached lookup is found.
+    #[tokio::test]
+    async fn test_authority_pages_past_cached() {
+        let keypair = Secp256k1KeyPair::generate();
+        let persona = format!("0x{}", compress_public_key(&keypair.public_key));
+        let page = |current: u32, next: u32, platform: &str| {
+            serde_json::json!({
+                "pagination": {"total": 2, "per": 1, "current": current, "next": next},
+                "ids": [{"persona": persona, "proofs": [
+                    {"platform": platform, "identity": "alice", "is_valid": true},
+                ]}],
+            })
+            .to_string()
+        };
+        let (base, hits) = mock_server(vec![
+            (200, page(1, 2, "twitter"), 0),
+            (200, page(1, 2, "twitter"), 0),
+            (200, page(2, 0, "github"), 0),
+        ])
+        .await;
+        let authority = ProofServiceAuthority::new(&base);
+
+        authority.can_set_kv(&keypair.public_key, "twitter", "alice").await.unwrap();
+        assert_eq!(1, hits.load(Ordering::SeqCst));
+        authority.can_set_kv(&keypair.public_key, "github", "alice").await.unwrap();
+        assert_eq!(3, hits.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn test_retry_server_error() {
+        let (base, hits) = mock_server(vec![
+            (503, r#"{"message": "busy"}"#, 0),
+            (200, PAGE_2, 0),
+        ])
+        .await;
+        let client = client(ConfigHttpClient {
+            retries: 2,
+            ..config()
+        });
+
+        let response = query_with(&client, &base, PERSONA).await.unwrap();
+        assert_eq!(2, hits.load(Ordering::SeqCst));
+        assert_eq!("github", response.ids[0].proofs[0].platform);
+    }
+
+    #[tokio::test]
+    async fn test_server_error_after_retries() {
+        let (base, hits) = mock_server(vec![(500, r#"{"message": "oops"}"#, 0)]).await;
+        let client = client(ConfigHttpClient {
+            retries: 1,
+            ..config()
+        });
+        let err = query_with(&client, &base, PERSONA).await.unwrap_err();
+        assert_eq!(2, hits.load(Ordering::SeqCst));
+        assert_eq!(StatusCode::BAD_GATEWAY, err.http_status());
+        assert!(err.to_string().contains("oops"));
+
+        let (base, _hits) = mock_server(vec![(503, r#"{"message": "busy"}"#, 0)]).await;
+        let err = query_with(&client, &base, PERSONA).await.unwrap_err();
+        assert!(matches!(err, Error::UpstreamUnavailable(UPSTREAM_PROOF_SERVICE, _)));
+        assert_eq!(StatusCode::SERVICE_UNAVAILABLE, err.http_status());
+    }
+
+    #[tokio::test]
+    async fn test_no_retry_client_error() {
+        let (base, hits) = mock_server(vec![(404, r#"{"message": "not found"}"#, 0)]).await;
+        let client = client(ConfigHttpClient {
+            retries: 2,
+            ..config()
+        });
+
+        let err = query_with(&client, &base, PERSONA).await.unwrap_err();
+        assert_eq!(1, hits.load(Ordering::SeqCst));
+        assert_eq!(StatusCode::NOT_FOUND, err.http_status());
+        assert!(err.to_string().contains("not found"));
+    }
+
+    #[tokio::test]
+    async fn test_timeout() {
+        let (base, _hits) = mock_server(vec![(200, PAGE_2, 500)]).await;
+        let client = client(ConfigHttpClient {
+            timeout: 50,
+            ..config()
+        });
+
+        let err = query_with(&client, &base, PERSONA).await.unwrap_err();
+        assert!(matches!(err, Error::UpstreamUnavailable(UPSTREAM_PROOF_SERVICE, _)));
+        assert_eq!(ErrorCode::ProofServiceUnavailable, err.code());
+        assert_eq!("ProofService unavailable: request timed out", err.to_string());
+    }
+}
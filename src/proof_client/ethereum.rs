@@ -0,0 +1,249 @@
+use async_trait::async_trait;
+use http::StatusCode;
+use libsecp256k1::PublicKey;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use super::authority::ProofAuthority;
+use crate::{
+    config::ConfigHttpClient,
+    crypto::util::ethereum_address,
+    error::{Error, ErrorCode},
+    http_client::{HttpClient, UPSTREAM_ETHEREUM},
+};
+
+/// Selector of `owner()`.
+const OWNER_SELECTOR: &str = "0x8da5cb5b";
+/// JSON-RPC error code of a reverted execution (EIP-1474 extension
+/// used by geth).
+const RPC_EXECUTION_REVERTED: i64 = 3;
+/// Generic server error code, which some nodes use for reverts too
+/// (along with revert `data`).
+const RPC_SERVER_ERROR: i64 = -32000;
+
+/// Checks Ethereum ownership of identity (an address) through
+/// JSON-RPC: identity must be the address of persona, or a contract
+/// whose `owner()` is that address.
+pub struct EthereumAuthority {
+    pub rpc_url: String,
+    client: HttpClient,
+}
+
+#[derive(Deserialize, Debug)]
+struct RpcResponse {
+    #[serde(default)]
+    result: Option<String>,
+    #[serde(default)]
+    error: Option<RpcError>,
+}
+
+#[derive(Deserialize, Debug)]
+struct RpcError {
+    #[serde(default)]
+    code: i64,
+    #[serde(default)]
+    message: String,
+    #[serde(default)]
+    data: Option<Value>,
+}
+
+impl RpcError {
+    /// Call was executed and reverted, as opposed to a failure of the
+    /// node itself.
+    fn is_revert(&self) -> bool {
+        let has_revert_data = self
+            .data
+            .as_ref()
+            .and_then(|data| data.as_str())
+            .is_some_and(|data| data.starts_with("0x"));
+        self.code == RPC_EXECUTION_REVERTED || (self.code == RPC_SERVER_ERROR && has_revert_data)
+    }
+}
+
+impl EthereumAuthority {
+    pub fn new(rpc_url: &str, client_config: ConfigHttpClient) -> Self {
+        Self {
+            rpc_url: rpc_url.into(),
+            client: HttpClient::new(UPSTREAM_ETHEREUM, client_config),
+        }
+    }
+
+    /// `owner()` of contract at `address`, `None` if it has no such
+    /// method (e.g. `address` is not a contract).
+    async fn owner_of(&self, address: &str) -> Result<Option<String>, Error> {
+        let request = json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "eth_call",
+            "params": [{"to": address, "data": OWNER_SELECTOR}, "latest"],
+        });
+        let (status, body) = self
+            .client
+            .post_json(&self.rpc_url, request.to_string())
+            .await?;
+        if !status.is_success() {
+            return Err(Error::Coded(
+                ErrorCode::EthereumRpcError,
+                format!("Ethereum RPC error: {}", status),
+                StatusCode::BAD_GATEWAY,
+            ));
+        }
+        let response: RpcResponse = serde_json::from_slice(&body)?;
+        if let Some(error) = response.error {
+            // Contract without `owner()`.
+            if error.is_revert() {
+                return Ok(None);
+            }
+            return Err(Error::Coded(
+                ErrorCode::EthereumRpcError,
+                format!("Ethereum RPC error: {}", error.message),
+                StatusCode::BAD_GATEWAY,
+            ));
+        }
+
+        // ABI-encoded `address`: 32 bytes, left-padded.
+        let result = response.result.unwrap_or_default();
+        let word = result.trim_start_matches("0x");
+        if word.len() != 64 {
+            return Ok(None);
+        }
+        Ok(Some(format!("0x{}", word[24..].to_lowercase())))
+    }
+}
+
+#[async_trait]
+impl ProofAuthority for EthereumAuthority {
+    async fn can_set_kv(
+        &self,
+        persona_pubkey: &PublicKey,
+        _platform: &str,
+        identity: &str,
+    ) -> Result<(), Error> {
+        let identity = identity.to_lowercase();
+        let is_address = identity.len() == 42
+            && identity.starts_with("0x")
+            && hex::decode(&identity[2..]).is_ok();
+        if !is_address {
+            return Err(Error::ParamError(format!(
+                "not an ethereum address: {}",
+                identity
+            )));
+        }
+
+        let persona_address = ethereum_address(persona_pubkey);
+        if identity == persona_address {
+            return Ok(());
+        }
+        if self.owner_of(&identity).await? == Some(persona_address) {
+            return Ok(());
+        }
+
+        Err(Error::Coded(
+            ErrorCode::EthereumAddressNotOwned,
+            format!("Persona doesn't own ethereum address {}", identity),
+            StatusCode::BAD_REQUEST,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::Ordering;
+
+    use super::*;
+    use crate::{
+        crypto::secp256k1::Secp256k1KeyPair,
+        http_client::tests::{config, mock_server},
+    };
+
+    const CONTRACT: &str = "0x495f947276749ce646f68ac8c248420045cb7b5e";
+
+    #[tokio::test]
+    async fn test_own_address() {
+        let keypair = Secp256k1KeyPair::generate();
+        let (base, hits) = mock_server(vec![(200, r#"{"result": "0x"}"#, 0)]).await;
+        let authority = EthereumAuthority::new(&base, config());
+
+        let address = ethereum_address(&keypair.public_key).to_uppercase().replace("0X", "0x");
+        assert!(authority
+            .can_set_kv(&keypair.public_key, "ethereum", &address)
+            .await
+            .is_ok());
+        // No RPC call needed.
+        assert_eq!(0, hits.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn test_contract_owner() {
+        // Secret key `1` => `0x7e5f4552091a69125d5dfcb7b8c2659029395bdf`
+        let keypair = Secp256k1KeyPair::from_secret_key_hex(
+            "0000000000000000000000000000000000000000000000000000000000000001",
+        )
+        .unwrap();
+        let (base, _hits) = mock_server(vec![(
+            200,
+            r#"{"jsonrpc": "2.0", "id": 1, "result": "0x0000000000000000000000007e5f4552091a69125d5dfcb7b8c2659029395bdf"}"#,
+            0,
+        )])
+        .await;
+        let authority = EthereumAuthority::new(&base, config());
+
+        assert!(authority
+            .can_set_kv(&keypair.public_key, "ethereum", CONTRACT)
+            .await
+            .is_ok());
+        let stranger = Secp256k1KeyPair::generate();
+        let err = authority
+            .can_set_kv(&stranger.public_key, "ethereum", CONTRACT)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("doesn't own"));
+    }
+
+    #[tokio::test]
+    async fn test_not_a_contract() {
+        let keypair = Secp256k1KeyPair::generate();
+        let (base, _hits) = mock_server(vec![(200, r#"{"result": "0x"}"#, 0)]).await;
+        let authority = EthereumAuthority::new(&base, config());
+
+        assert!(authority
+            .can_set_kv(&keypair.public_key, "ethereum", CONTRACT)
+            .await
+            .is_err());
+        assert!(authority
+            .can_set_kv(&keypair.public_key, "ethereum", "alice.eth")
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_rpc_error() {
+        let keypair = Secp256k1KeyPair::generate();
+        let (base, _hits) = mock_server(vec![
+            (200, r#"{"error": {"code": 3, "message": "execution reverted", "data": "0x"}}"#, 0),
+            (200, r#"{"error": {"code": -32000, "message": "execution reverted", "data": "0x"}}"#, 0),
+            (200, r#"{"error": {"code": -32000, "message": "header not found"}}"#, 0),
+            // Mentions "revert", but is not one.
+            (200, r#"{"error": {"code": -32603, "message": "failed to revert state"}}"#, 0),
+        ])
+        .await;
+        let authority = EthereumAuthority::new(&base, config());
+
+        // Reverted: not owned.
+        for _ in 0..2 {
+            let err = authority
+                .can_set_kv(&keypair.public_key, "ethereum", CONTRACT)
+                .await
+                .unwrap_err();
+            assert_eq!(StatusCode::BAD_REQUEST, err.http_status());
+        }
+        for message in ["header not found", "failed to revert state"] {
+            let err = authority
+                .can_set_kv(&keypair.public_key, "ethereum", CONTRACT)
+                .await
+                .unwrap_err();
+            assert_eq!(StatusCode::BAD_GATEWAY, err.http_status());
+            assert!(err.to_string().contains(message));
+        }
+    }
+}
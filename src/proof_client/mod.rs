@@ -1,40 +1,65 @@
+pub mod authority;
+pub mod cache;
+pub mod client;
+pub mod ethereum;
 mod tests;
 
-use crate::{crypto::secp256k1::Secp256k1KeyPair, error::Error};
-use http::{Response, StatusCode};
-use hyper::{body::HttpBody as _, client::HttpConnector, Body, Client};
-use hyper_tls::HttpsConnector;
-use libsecp256k1::PublicKey;
-use serde::Deserialize;
+use http::StatusCode;
+
+use crate::error::{Error, ErrorCode};
+use crate::http_client::{HttpClient, UPSTREAM_PROOF_SERVICE};
+use client::PROOF_CLIENT;
+use serde::{Deserialize, Serialize};
+
+/// Max pages to follow in a single `query`.  More than this is an error.
+pub(crate) const MAX_PAGES: u32 = 50;
 
 /// https://github.com/nextdotid/proof-server/blob/master/docs/api.apib
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Default)]
 pub struct ProofQueryResponse {
+    #[serde(default)]
     pub pagination: ProofQueryResponsePagination,
+    #[serde(default)]
     pub ids: Vec<ProofPersona>,
 }
 
+impl ProofQueryResponse {
+    /// No page is left behind (see `query_until`).
+    pub fn is_complete(&self) -> bool {
+        self.pagination.next <= self.pagination.current
+    }
+}
+
 #[derive(Deserialize, Debug)]
 pub struct ProofPersona {
     pub persona: String,
+    #[serde(default)]
     pub proofs: Vec<Proof>,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct Proof {
     pub platform: String,
     pub identity: String,
+    #[serde(default)]
     pub created_at: String,
+    #[serde(default)]
     pub last_checked_at: String,
+    /// Older ProofService doesn't give this field: such proofs are
+    /// taken as invalid.
+    #[serde(default)]
     pub is_valid: bool,
+    #[serde(default)]
     pub invalid_reason: String,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
 pub struct ProofQueryResponsePagination {
     pub total: u32,
     pub per: u32,
     pub current: u32,
+    /// `0` if this is the last page.
     pub next: u32,
 }
 
@@ -43,109 +68,138 @@ pub struct ErrorResponse {
     pub message: String,
 }
 
-pub fn make_client() -> Client<HttpsConnector<HttpConnector>> {
-    let https = HttpsConnector::new();
-    let client = Client::builder().build::<_, hyper::Body>(https);
-    client
-}
-
-async fn parse_body<T>(resp: &mut Response<Body>) -> Result<T, Error>
+fn parse_body<T>(body: &[u8]) -> Result<T, Error>
 where
     T: for<'de> Deserialize<'de>,
 {
-    let mut body_bytes: Vec<u8> = vec![];
-    while let Some(chunk) = resp.body_mut().data().await {
-        let mut chunk_bytes = chunk.unwrap().to_vec();
-        body_bytes.append(&mut chunk_bytes);
-    }
-    let body = std::str::from_utf8(&body_bytes).unwrap();
-
-    Ok(serde_json::from_str(&body)?)
+    Ok(serde_json::from_slice(body)?)
 }
 
+/// Query all proofs of a persona, following pagination.
 /// Persona should be 33-bytes hexstring (`0x[0-9a-f]{66}`)
 pub async fn query(base: &str, persona: &str) -> Result<ProofQueryResponse, Error> {
-    let client = make_client();
-    let uri = format!("{}/v1/proof?platform=nextid&identity={}", base, persona)
-        .parse()
-        .unwrap();
-    let mut resp = client.get(uri).await?;
-    if !resp.status().is_success() {
-        let body: ErrorResponse = parse_body(&mut resp).await?;
-        return Err(Error::General(
-            format!("ProofService error: {}", body.message),
-            resp.status(),
-        ));
-    }
-    let body: ProofQueryResponse = parse_body(&mut resp).await?;
-    Ok(body)
+    query_with(&PROOF_CLIENT, base, persona).await
 }
 
-/// Determine if persona-platform-identity pair can set a KV.
-pub async fn can_set_kv(
-    persona_pubkey: &PublicKey,
-    platform: &String,
-    identity: &String,
-) -> Result<(), Error> {
-    // FIXME: super stupid test stub
-    if cfg!(test) {
-        return Ok(());
-    }
-    // KV of NextID: validate if identity == persona.
-    if *platform == "nextid".to_string() {
-        let Secp256k1KeyPair {
-            public_key: identity_pubkey,
-            secret_key: _,
-        } = Secp256k1KeyPair::from_pubkey_hex(&identity)?;
-        if identity_pubkey == *persona_pubkey {
-            return Ok(());
-        } else {
-            return Err(Error::General(
-                format!("Identity and persona not match when 'platform' is 'nextid' ."),
-                StatusCode::BAD_REQUEST,
+/// `query` using given client.
+pub async fn query_with(
+    client: &HttpClient,
+    base: &str,
+    persona: &str,
+) -> Result<ProofQueryResponse, Error> {
+    query_identity_with(client, base, "nextid", persona).await
+}
+
+/// Query all personas (and their proofs) linked to given
+/// platform-identity pair, following pagination.
+pub async fn query_identity(
+    base: &str,
+    platform: &str,
+    identity: &str,
+) -> Result<ProofQueryResponse, Error> {
+    query_identity_with(&PROOF_CLIENT, base, platform, identity).await
+}
+
+/// `query_identity` using given client.
+pub async fn query_identity_with(
+    client: &HttpClient,
+    base: &str,
+    platform: &str,
+    identity: &str,
+) -> Result<ProofQueryResponse, Error> {
+    query_pages(client, base, platform, identity, |_| false).await
+}
+
+/// `query_with`, but stop paging once `done` holds for the proofs got
+/// so far.  Result may be incomplete then.
+pub async fn query_until<F>(
+    client: &HttpClient,
+    base: &str,
+    persona: &str,
+    done: F,
+) -> Result<ProofQueryResponse, Error>
+where
+    F: Fn(&ProofQueryResponse) -> bool,
+{
+    query_pages(client, base, "nextid", persona, done).await
+}
+
+async fn query_pages<F>(
+    client: &HttpClient,
+    base: &str,
+    platform: &str,
+    identity: &str,
+    done: F,
+) -> Result<ProofQueryResponse, Error>
+where
+    F: Fn(&ProofQueryResponse) -> bool,
+{
+    let mut result = query_page(client, base, platform, identity, 1).await?;
+    let mut pages = 1;
+    while !result.is_complete() && !done(&result) {
+        if pages >= MAX_PAGES {
+            return Err(Error::Coded(
+                ErrorCode::ProofServiceError,
+                format!("ProofService error: more than {} pages", MAX_PAGES),
+                StatusCode::BAD_GATEWAY,
             ));
         }
+        let page = query_page(client, base, platform, identity, result.pagination.next).await?;
+        pages += 1;
+        result.pagination = page.pagination;
+        merge_ids(&mut result.ids, page.ids);
     }
-    // Else: connect to ProofService
-    let persona_compressed_hex =
-        format!("0x{}", hex::encode(persona_pubkey.serialize_compressed()));
-    let query_response =
-        query(&crate::config::C.proof_service.url, &persona_compressed_hex).await?;
-    if query_response.ids.len() == 0 {
-        return Err(Error::General(
-            format!(
-                "Persona not found on ProofService: {}",
-                persona_compressed_hex
+
+    Ok(result)
+}
+
+/// Merge personas of a following page into `ids`.
+fn merge_ids(ids: &mut Vec<ProofPersona>, page_ids: Vec<ProofPersona>) {
+    for page_persona in page_ids {
+        match ids.iter_mut().find(|id| id.persona == page_persona.persona) {
+            Some(found) => found.proofs.extend(page_persona.proofs),
+            None => ids.push(page_persona),
+        }
+    }
+}
+
+async fn query_page(
+    client: &HttpClient,
+    base: &str,
+    platform: &str,
+    identity: &str,
+    page: u32,
+) -> Result<ProofQueryResponse, Error> {
+    let query: String = url::form_urlencoded::Serializer::new(String::new())
+        .append_pair("platform", platform)
+        .append_pair("identity", identity)
+        .append_pair("page", &page.to_string())
+        .finish();
+    let (status, body) = client
+        .get(&format!("{}/v1/proof?{}", base, query))
+        .await?;
+    if !status.is_success() {
+        let message = parse_body::<ErrorResponse>(&body)
+            .map(|body| body.message)
+            .unwrap_or_else(|_| status.to_string());
+        // 5xx is still failed after retries: it is upstream's fault,
+        // not an internal error of this server.
+        return Err(match status {
+            StatusCode::SERVICE_UNAVAILABLE | StatusCode::GATEWAY_TIMEOUT => {
+                Error::UpstreamUnavailable(UPSTREAM_PROOF_SERVICE, message)
+            }
+            _ if status.is_server_error() => Error::Coded(
+                ErrorCode::ProofServiceError,
+                format!("ProofService error: {}", message),
+                StatusCode::BAD_GATEWAY,
+            ),
+            _ => Error::Coded(
+                ErrorCode::ProofServiceError,
+                format!("ProofService error: {}", message),
+                status,
             ),
-            StatusCode::BAD_REQUEST,
-        ));
+        });
     }
 
-    // FIXME: maybe no need to iter like this.
-    let persona_found = query_response
-        .ids
-        .iter()
-        .find(|id| id.persona == persona_compressed_hex)
-        .ok_or_else(|| {
-            Error::General(
-                format!(
-                    "Persona not found on ProofService: {}",
-                    persona_compressed_hex
-                ),
-                StatusCode::BAD_REQUEST,
-            )
-        })?;
-
-    let _proof_found = persona_found
-        .proofs
-        .iter()
-        .find(|proof| proof.platform == *platform && proof.identity == *identity)
-        .ok_or_else(|| {
-            Error::General(
-                format!("Proof not found under this persona."),
-                StatusCode::BAD_REQUEST,
-            )
-        })?;
-
-    Ok(())
+    parse_body(&body)
 }
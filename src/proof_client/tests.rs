@@ -1,6 +1,21 @@
 #[cfg(test)]
 mod tests {
-    use crate::{error::Error, proof_client::query};
+    use crate::{
+        config::{ConfigPlatform, ConfigProofAuthority, ConfigProofService, ConfigStaticProof},
+        crypto::{
+            secp256k1::Secp256k1KeyPair,
+            util::{compress_public_key, ethereum_address},
+        },
+        error::Error,
+        proof_client::{
+            authority::{
+                from_config, MockAuthority, PlatformRule, ProofAuthority, RegistryAuthority,
+                StaticAuthority,
+            },
+            merge_ids, query, ProofQueryResponse,
+        },
+    };
+    use serde_json::json;
     const PROOF_SERVICE_URL: &str = "https://proof-service.nextnext.id"; // Staging
 
     #[tokio::test]
@@ -14,4 +29,249 @@ mod tests {
         assert_eq!(result.ids.len(), 0);
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_mock_success() {
+        let keypair = Secp256k1KeyPair::generate();
+        let authority = MockAuthority::default().with_proof(&keypair.public_key, "twitter", "alice");
+
+        assert!(authority
+            .can_set_kv(&keypair.public_key, "twitter", "alice")
+            .await
+            .is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_mock_persona_not_found() {
+        let keypair = Secp256k1KeyPair::generate();
+        let stranger = Secp256k1KeyPair::generate();
+        let authority = MockAuthority::default().with_proof(&stranger.public_key, "twitter", "alice");
+
+        let err = authority
+            .can_set_kv(&keypair.public_key, "twitter", "alice")
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("Persona not found"));
+    }
+
+    #[tokio::test]
+    async fn test_mock_proof_missing() {
+        let keypair = Secp256k1KeyPair::generate();
+        let authority = MockAuthority::default().with_proof(&keypair.public_key, "twitter", "alice");
+
+        let err = authority
+            .can_set_kv(&keypair.public_key, "twitter", "bob")
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("Proof not found"));
+        let err = authority
+            .can_set_kv(&keypair.public_key, "github", "alice")
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("Proof not found"));
+    }
+
+    #[tokio::test]
+    async fn test_nextid() {
+        let keypair = Secp256k1KeyPair::generate();
+        let other = Secp256k1KeyPair::generate();
+        // Even with a permissive default, nextid is a self-proof.
+        let authority = RegistryAuthority::new(Box::new(MockAuthority::allow_all()));
+
+        assert!(authority
+            .can_set_kv(&keypair.public_key, "nextid", &compress_public_key(&keypair.public_key))
+            .await
+            .is_ok());
+        let err = authority
+            .can_set_kv(&keypair.public_key, "nextid", &compress_public_key(&other.public_key))
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("not match"));
+    }
+
+    #[tokio::test]
+    async fn test_static() {
+        let keypair = Secp256k1KeyPair::generate();
+        let authority = StaticAuthority::new(vec![ConfigStaticProof {
+            persona: format!("0x{}", compress_public_key(&keypair.public_key)),
+            platform: "twitter".into(),
+            identity: "*".into(),
+        }]);
+
+        assert!(authority
+            .can_set_kv(&keypair.public_key, "twitter", "anyone")
+            .await
+            .is_ok());
+        assert!(authority
+            .can_set_kv(&keypair.public_key, "github", "anyone")
+            .await
+            .is_err());
+        assert!(authority
+            .can_set_kv(&Secp256k1KeyPair::generate().public_key, "twitter", "anyone")
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_mock_proof_invalid() {
+        let keypair = Secp256k1KeyPair::generate();
+        let authority = MockAuthority::default().with_invalid_proof(
+            &keypair.public_key,
+            "twitter",
+            "alice",
+            "Tweet deleted",
+        );
+
+        let err = authority
+            .can_set_kv(&keypair.public_key, "twitter", "alice")
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("Tweet deleted"));
+        assert_eq!(
+            Some(json!({"invalid_reason": "Tweet deleted"})),
+            err.details()
+        );
+
+        // A valid copy of the same proof wins.
+        let authority = authority.with_proof(&keypair.public_key, "twitter", "alice");
+        assert!(authority
+            .can_set_kv(&keypair.public_key, "twitter", "alice")
+            .await
+            .is_ok());
+    }
+
+    #[test]
+    fn test_parse_missing_optional_fields() {
+        let response: ProofQueryResponse = serde_json::from_str(
+            r#"{"ids": [{"persona": "0x02", "proofs": [{"platform": "twitter", "identity": "alice"}]}]}"#,
+        )
+        .unwrap();
+        assert_eq!(0, response.pagination.next);
+        let proof = &response.ids[0].proofs[0];
+        assert!(!proof.is_valid);
+        assert_eq!("", proof.invalid_reason);
+
+        let response: ProofQueryResponse = serde_json::from_str("{}").unwrap();
+        assert!(response.ids.is_empty());
+    }
+
+    #[test]
+    fn test_merge_pages() {
+        let mut page_1: ProofQueryResponse = serde_json::from_str(
+            r#"{"pagination": {"total": 2, "per": 1, "current": 1, "next": 2},
+                "ids": [{"persona": "0x02", "proofs": [{"platform": "twitter", "identity": "alice"}]}]}"#,
+        )
+        .unwrap();
+        let page_2: ProofQueryResponse = serde_json::from_str(
+            r#"{"pagination": {"total": 2, "per": 1, "current": 2, "next": 0},
+                "ids": [{"persona": "0x02", "proofs": [{"platform": "github", "identity": "alice"}]},
+                        {"persona": "0x03", "proofs": []}]}"#,
+        )
+        .unwrap();
+
+        merge_ids(&mut page_1.ids, page_2.ids);
+        assert_eq!(2, page_1.ids.len());
+        assert_eq!(2, page_1.ids[0].proofs.len());
+        assert_eq!("github", page_1.ids[0].proofs[1].platform);
+    }
+
+    #[tokio::test]
+    async fn test_registry_routes_platform() {
+        let keypair = Secp256k1KeyPair::generate();
+        let authority = RegistryAuthority::new(Box::new(MockAuthority::default()))
+            .with_platform("twitter", PlatformRule::route(Box::new(MockAuthority::allow_all())));
+
+        assert!(authority
+            .can_set_kv(&keypair.public_key, "twitter", "alice")
+            .await
+            .is_ok());
+        // Unlisted platform goes to default authority.
+        let err = authority
+            .can_set_kv(&keypair.public_key, "github", "alice")
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("Persona not found"));
+    }
+
+    #[tokio::test]
+    async fn test_registry_allow_deny() {
+        let keypair = Secp256k1KeyPair::generate();
+        let authority = RegistryAuthority::new(Box::new(MockAuthority::allow_all()))
+            .with_platform(
+                "twitter",
+                PlatformRule::new(None, &ConfigPlatform {
+                    deny: vec!["mallory".into()],
+                    ..Default::default()
+                }),
+            )
+            .with_platform(
+                "github",
+                PlatformRule::new(None, &ConfigPlatform {
+                    allow: vec!["alice".into()],
+                    ..Default::default()
+                }),
+            )
+            .with_platform(
+                "facebook",
+                PlatformRule::new(None, &ConfigPlatform {
+                    deny: vec!["*".into()],
+                    ..Default::default()
+                }),
+            );
+
+        let cases = [
+            ("twitter", "alice", true),
+            ("twitter", "mallory", false),
+            ("github", "alice", true),
+            ("github", "bob", false),
+            ("facebook", "alice", false),
+        ];
+        for (platform, identity, accepted) in cases {
+            let result = authority.can_set_kv(&keypair.public_key, platform, identity).await;
+            assert_eq!(accepted, result.is_ok(), "{} {}", platform, identity);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_from_config() {
+        let keypair = Secp256k1KeyPair::generate();
+        let mut config = ConfigProofService {
+            authority: ConfigProofAuthority::Static,
+            allow: vec![ConfigStaticProof {
+                persona: "*".into(),
+                platform: "twitter".into(),
+                identity: "*".into(),
+            }],
+            ..Default::default()
+        };
+        config.platforms.insert(
+            "nextid".into(),
+            ConfigPlatform {
+                deny: vec![],
+                ..Default::default()
+            },
+        );
+        config.platforms.insert(
+            "ethereum".into(),
+            ConfigPlatform {
+                authority: Some(ConfigProofAuthority::Ethereum),
+                rpc_url: "http://127.0.0.1:1".into(),
+                ..Default::default()
+            },
+        );
+        let authority = from_config(&config);
+
+        assert!(authority
+            .can_set_kv(&keypair.public_key, "twitter", "alice")
+            .await
+            .is_ok());
+        assert!(authority
+            .can_set_kv(&keypair.public_key, "nextid", &compress_public_key(&keypair.public_key))
+            .await
+            .is_ok());
+        assert!(authority
+            .can_set_kv(&keypair.public_key, "ethereum", &ethereum_address(&keypair.public_key))
+            .await
+            .is_ok());
+    }
 }
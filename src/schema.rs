@@ -9,6 +9,33 @@ table! {
         created_at -> Timestamptz,
         updated_at -> Timestamptz,
         arweave_id -> Nullable<Varchar>,
+        revoked_at -> Nullable<Timestamptz>,
+        arweave_status -> Nullable<Varchar>,
+        arweave_block_height -> Nullable<Int8>,
+        proof_event_at -> Nullable<Timestamptz>,
+    }
+}
+
+table! {
+    kv_arweave_outbox (id) {
+        id -> Int4,
+        kv_chain_id -> Int4,
+        attempts -> Int4,
+        last_error -> Nullable<Varchar>,
+        next_attempt_at -> Timestamptz,
+        created_at -> Timestamptz,
+        done_at -> Nullable<Timestamptz>,
+        state -> Varchar,
+        sink -> Varchar,
+    }
+}
+
+table! {
+    kv_arweave_spends (day) {
+        day -> Date,
+        winston -> Int8,
+        transactions -> Int4,
+        updated_at -> Timestamptz,
     }
 }
 
@@ -25,11 +52,106 @@ table! {
         created_at -> Timestamptz,
         updated_at -> Timestamptz,
         signature_payload -> Varchar,
-        arweave_id -> Nullable<Varchar>,
+        action -> Varchar,
+        arweave_status -> Nullable<Varchar>,
+        arweave_block_height -> Nullable<Int8>,
+        arweave_submitted_at -> Nullable<Timestamptz>,
+        arweave_checked_at -> Nullable<Timestamptz>,
+        arweave_bundle_id -> Nullable<Varchar>,
+        archive_ids -> Jsonb,
+        signer_signatures -> Jsonb,
+    }
+}
+
+table! {
+    kv_payloads (id) {
+        id -> Int4,
+        uuid -> Uuid,
+        persona -> Bytea,
+        created_at -> Timestamptz,
+        expired_at -> Timestamptz,
+        used_at -> Nullable<Timestamptz>,
+    }
+}
+
+table! {
+    kv_receipts (id) {
+        id -> Int4,
+        uuid -> Uuid,
+        kv_chain_id -> Int4,
+        payload -> Varchar,
+        signature -> Bytea,
+        signer -> Bytea,
+        created_at -> Timestamptz,
+    }
+}
+
+table! {
+    kv_visibilities (id) {
+        id -> Int4,
+        persona -> Bytea,
+        settings -> Jsonb,
+        created_at -> Timestamptz,
+        updated_at -> Timestamptz,
+    }
+}
+
+table! {
+    kv_multisigs (id) {
+        id -> Int4,
+        persona -> Bytea,
+        settings -> Jsonb,
+        created_at -> Timestamptz,
+        updated_at -> Timestamptz,
+    }
+}
+
+table! {
+    kv_proposal_signatures (id) {
+        id -> Int4,
+        proposal_id -> Int4,
+        signer -> Bytea,
+        signature -> Bytea,
+        created_at -> Timestamptz,
+    }
+}
+
+table! {
+    kv_proposals (id) {
+        id -> Int4,
+        uuid -> Uuid,
+        persona -> Bytea,
+        platform -> Varchar,
+        identity -> Varchar,
+        patch -> Jsonb,
+        previous_id -> Nullable<Int4>,
+        signature_payload -> Varchar,
+        action -> Varchar,
+        created_at -> Timestamptz,
+        finalized_at -> Nullable<Timestamptz>,
+        kv_chain_id -> Nullable<Int4>,
+    }
+}
+
+table! {
+    kv_read_nonces (id) {
+        id -> Int4,
+        reader -> Bytea,
+        nonce -> Varchar,
+        expired_at -> Timestamptz,
     }
 }
 
 allow_tables_to_appear_in_same_query!(
     kv,
+    kv_arweave_outbox,
+    kv_arweave_spends,
     kv_chains,
+    kv_multisigs,
+    kv_payloads,
+    kv_proposal_signatures,
+    kv_proposals,
+    kv_read_nonces,
+    kv_receipts,
+    kv_visibilities,
 );
@@ -0,0 +1,852 @@
+use std::{collections::HashMap, future::Future, path::Path};
+
+use diesel::{prelude::*, PgConnection};
+use log::{error, info, warn};
+
+use crate::{
+    config::{ConfigArwave, ConfigArweaveOutbox, ConfigArweaveTracker, C},
+    error::Error,
+    model::{
+        archive::{self, mirrors, ArchiveSink, ArweaveSink},
+        arweave::{
+            bundle::{DataItem, DataItemSigner, JwkSigner},
+            get_tx_status, upload_bundle, ArweaveTxStatus, KVChainArweaveDocument,
+        },
+        arweave_outbox::ArweaveOutbox,
+        establish_connection,
+        kv_chains::{KVChain, ARWEAVE_CONFIRMED, ARWEAVE_DROPPED, ARWEAVE_PENDING},
+    },
+    http_client::{HttpClient, UPSTREAM_ARWEAVE},
+    metrics::ARWEAVE_OUTBOX_DEAD,
+    schema::kv_chains,
+    util::naive_now,
+};
+
+/// Poll the outbox and pending transactions forever: upload due links
+/// to Arweave and to other archive sinks in `C.archive`, then track
+/// confirmation of Arweave transactions.
+pub async fn run(config: ConfigArwave) {
+    info!("Arweave worker started");
+    let client = HttpClient::new(UPSTREAM_ARWEAVE, config.client.clone());
+    let arweave = ArweaveSink::new(config.clone());
+    let mirrors = mirrors(&C.archive);
+    let signer = if config.bundle.enabled {
+        match JwkSigner::from_jwk_path(Path::new(&config.jwt)) {
+            Ok(signer) => Some(signer),
+            Err(err) => {
+                error!("Arweave worker: bundling is off: {}", err);
+                None
+            }
+        }
+    } else {
+        None
+    };
+    loop {
+        let mut conn = establish_connection();
+        let result = match signer.as_ref() {
+            Some(signer) => run_bundle_once(&mut conn, &config, signer).await,
+            None => run_once(&mut conn, &arweave, &config.outbox).await,
+        };
+        match result {
+            Ok(0) => {}
+            Ok(uploaded) => info!("Arweave worker: {} link(s) uploaded", uploaded),
+            Err(err) => error!("Arweave worker: {}", err),
+        }
+        for mirror in mirrors.iter() {
+            match mirror_once(&mut conn, mirror.as_ref(), &config.outbox).await {
+                Ok(0) => {}
+                Ok(stored) => info!("Archive worker: {} link(s) stored in {}", stored, mirror.name()),
+                Err(err) => error!("Archive worker ({}): {}", mirror.name(), err),
+            }
+        }
+        match ArweaveOutbox::count_dead(&mut conn) {
+            Ok(dead) => ARWEAVE_OUTBOX_DEAD.set(dead),
+            Err(err) => error!("Archive worker: {}", err),
+        }
+        match track_once(&mut conn, &client, &config.url, &config.tracker).await {
+            Ok(0) => {}
+            Ok(dropped) => warn!("Arweave worker: {} dropped link(s) queued again", dropped),
+            Err(err) => error!("Arweave tracker: {}", err),
+        }
+        tokio::time::sleep(std::time::Duration::from_secs(config.outbox.interval)).await;
+    }
+}
+
+/// Claim due uploads and process them once.  Returns uploaded count.
+pub async fn run_once(
+    conn: &mut PgConnection,
+    arweave: &dyn ArchiveSink,
+    config: &ConfigArweaveOutbox,
+) -> Result<usize, Error> {
+    let mut uploaded = 0;
+    for entry in ArweaveOutbox::claim_due(conn, config)? {
+        if process(conn, &entry, config, |document| async move { arweave.put(&document).await }).await? {
+            uploaded += 1;
+        }
+    }
+
+    Ok(uploaded)
+}
+
+/// Claim due entries of given (non-Arweave) sink and store their links
+/// in it once.  Returns stored count.
+pub async fn mirror_once(
+    conn: &mut PgConnection,
+    sink: &dyn ArchiveSink,
+    config: &ConfigArweaveOutbox,
+) -> Result<usize, Error> {
+    let mut stored = 0;
+    for entry in ArweaveOutbox::claim_due_sink(conn, sink.name(), config)? {
+        if process_mirror(conn, &entry, sink, config).await? {
+            stored += 1;
+        }
+    }
+
+    Ok(stored)
+}
+
+/// Claim due uploads and post them in one ANS-104 bundle, once
+/// `config.bundle` thresholds are reached.  Returns uploaded count.
+pub async fn run_bundle_once(
+    conn: &mut PgConnection,
+    config: &ConfigArwave,
+    signer: &dyn DataItemSigner,
+) -> Result<usize, Error> {
+    let (due, oldest) = ArweaveOutbox::due_summary(conn)?;
+    let waited_enough = oldest
+        .map(|oldest| (naive_now() - oldest).num_seconds() >= config.bundle.max_wait)
+        .unwrap_or(false);
+    if due < config.bundle.max_items && !waited_enough {
+        return Ok(0);
+    }
+
+    let claim_config = ConfigArweaveOutbox {
+        batch: config.bundle.max_items,
+        ..config.outbox.clone()
+    };
+    let entries = ArweaveOutbox::claim_due(conn, &claim_config)?;
+    process_bundle(conn, &entries, config, signer, |items| async move {
+        upload_bundle(&items).await
+    })
+    .await
+}
+
+/// Check status of pending transactions once.  Returns count of
+/// dropped ones.
+pub async fn track_once(
+    conn: &mut PgConnection,
+    client: &HttpClient,
+    gateway: &str,
+    config: &ConfigArweaveTracker,
+) -> Result<usize, Error> {
+    let mut dropped = 0;
+    for link in KVChain::find_unconfirmed(conn, config.interval, config.batch)? {
+        match track(conn, client, gateway, &link, config).await {
+            Ok(status) if status == ARWEAVE_DROPPED => dropped += 1,
+            Ok(_) => {}
+            // Gateway is unreachable: try again next time.
+            Err(err) => {
+                warn!("Arweave status of link {} unknown: {}", link.uuid, err);
+                break;
+            }
+        }
+    }
+
+    Ok(dropped)
+}
+
+/// Poll gateway for status of the transaction of given link and record
+/// it.  A transaction unknown to gateway for `config.drop_after`
+/// seconds is dropped, and its link is queued for re-upload, together
+/// with newer links whose documents point at the dropped transaction.
+/// Returns recorded status.
+pub async fn track(
+    conn: &mut PgConnection,
+    client: &HttpClient,
+    gateway: &str,
+    link: &KVChain,
+    config: &ConfigArweaveTracker,
+) -> Result<&'static str, Error> {
+    // Data items have no status of their own: poll their bundle.
+    let transaction_id = match link.arweave_transaction_id() {
+        Some(transaction_id) => transaction_id,
+        None => return Ok(ARWEAVE_PENDING),
+    };
+
+    match get_tx_status(client, gateway, transaction_id).await? {
+        ArweaveTxStatus::Confirmed { block_height } => {
+            link.update_arweave_status(conn, ARWEAVE_CONFIRMED, Some(block_height))?;
+            Ok(ARWEAVE_CONFIRMED)
+        }
+        ArweaveTxStatus::NotFound
+            if link
+                .arweave_submitted_at
+                .map(|submitted_at| (naive_now() - submitted_at).num_seconds() > config.drop_after)
+                .unwrap_or(true) =>
+        {
+            warn!("Arweave transaction {} of link {} is dropped", transaction_id, link.uuid);
+            conn.transaction::<_, Error, _>(|conn| {
+                link.update_arweave_status(conn, ARWEAVE_DROPPED, None)?;
+                ArweaveOutbox::requeue(conn, link)?;
+                for newer in link.find_submitted_after(conn)? {
+                    ArweaveOutbox::requeue(conn, &newer)?;
+                }
+                Ok(())
+            })?;
+            Ok(ARWEAVE_DROPPED)
+        }
+        ArweaveTxStatus::Pending | ArweaveTxStatus::NotFound => {
+            link.update_arweave_status(conn, ARWEAVE_PENDING, None)?;
+            Ok(ARWEAVE_PENDING)
+        }
+    }
+}
+
+/// Upload link of given outbox entry using `upload`, then fill
+/// `arweave_id` of `kv_chains` and `kv`.  Returns `false` if it is
+/// failed or deferred (previous link not uploaded yet).
+pub async fn process<F, Fut>(
+    conn: &mut PgConnection,
+    entry: &ArweaveOutbox,
+    config: &ConfigArweaveOutbox,
+    upload: F,
+) -> Result<bool, Error>
+where
+    F: FnOnce(KVChainArweaveDocument) -> Fut,
+    Fut: Future<Output = Result<String, Error>>,
+{
+    let link: KVChain = kv_chains::table.find(entry.kv_chain_id).first(conn)?;
+    if !archivable(conn, entry, &link)? {
+        return Ok(false);
+    }
+    let previous = link.find_chained_previous(conn)?;
+    if previous_pending(conn, previous.as_ref())? {
+        entry.defer(conn, config.interval as i64)?;
+        return Ok(false);
+    }
+    let previous_arweave_id = previous.and_then(|previous| previous.arweave_id());
+
+    match upload(KVChainArweaveDocument::from_link(&link, previous_arweave_id)).await {
+        Ok(transaction_id) => {
+            conn.transaction::<_, Error, _>(|conn| {
+                link.insert_arweave_id(conn, Some(transaction_id))?;
+                entry.mark_done(conn)
+            })?;
+            Ok(true)
+        }
+        Err(err) => {
+            warn!("Arweave upload of link {} failed: {}", link.uuid, err);
+            entry.mark_failed(conn, &err.to_string(), config)?;
+            Ok(false)
+        }
+    }
+}
+
+/// Upload links of given outbox entries as data items of one bundle
+/// using `upload`, then fill `arweave_id` (data item) and
+/// `arweave_bundle_id` of them.  Returns uploaded count.
+pub async fn process_bundle<F, Fut>(
+    conn: &mut PgConnection,
+    entries: &[ArweaveOutbox],
+    config: &ConfigArwave,
+    signer: &dyn DataItemSigner,
+    upload: F,
+) -> Result<usize, Error>
+where
+    F: FnOnce(Vec<DataItem>) -> Fut,
+    Fut: Future<Output = Result<String, Error>>,
+{
+    let mut entries: Vec<&ArweaveOutbox> = entries.iter().collect();
+    entries.sort_by_key(|entry| entry.kv_chain_id);
+    // Data item ids of links in this bundle, by link id.
+    let mut bundled: HashMap<i32, String> = HashMap::new();
+    let mut included: Vec<(&ArweaveOutbox, KVChain, DataItem)> = vec![];
+    let mut size = 0;
+    for entry in entries {
+        let link: KVChain = kv_chains::table.find(entry.kv_chain_id).first(conn)?;
+        if !archivable(conn, entry, &link)? {
+            continue;
+        }
+        let previous = link.find_chained_previous(conn)?;
+        let previous_arweave_id = match previous.as_ref().and_then(|previous| bundled.get(&previous.id)) {
+            Some(item_id) => Some(item_id.clone()),
+            None => {
+                if previous_pending(conn, previous.as_ref())? {
+                    entry.defer(conn, config.outbox.interval as i64)?;
+                    continue;
+                }
+                previous.and_then(|previous| previous.arweave_id())
+            }
+        };
+        let item = KVChainArweaveDocument::from_link(&link, previous_arweave_id)
+            .to_data_item(signer, &config.tags)?;
+        // Full: goes to next bundle.
+        if !included.is_empty() && size + item.bytes.len() > config.bundle.max_bytes {
+            entry.defer(conn, 0)?;
+            continue;
+        }
+        size += item.bytes.len();
+        bundled.insert(link.id, item.id.clone());
+        included.push((entry, link, item));
+    }
+    if included.is_empty() {
+        return Ok(0);
+    }
+
+    let items = included.iter().map(|(_, _, item)| item.clone()).collect();
+    match upload(items).await {
+        Ok(bundle_id) => {
+            conn.transaction::<_, Error, _>(|conn| {
+                for (entry, link, item) in included.iter() {
+                    link.insert_arweave_id(conn, Some(item.id.clone()))?;
+                    link.insert_arweave_bundle_id(conn, Some(bundle_id.clone()))?;
+                    entry.mark_done(conn)?;
+                }
+                Ok(())
+            })?;
+            Ok(included.len())
+        }
+        Err(err) => {
+            warn!("Arweave bundle of {} link(s) failed: {}", included.len(), err);
+            for (entry, _, _) in included.iter() {
+                entry.mark_failed(conn, &err.to_string(), &config.outbox)?;
+            }
+            Ok(0)
+        }
+    }
+}
+
+/// Store link of given outbox entry in `sink`, then record its content
+/// id in `kv_chains.archive_ids`.  Mirrors are not chained: a link
+/// doesn't wait for its previous one.
+pub async fn process_mirror(
+    conn: &mut PgConnection,
+    entry: &ArweaveOutbox,
+    sink: &dyn ArchiveSink,
+    config: &ConfigArweaveOutbox,
+) -> Result<bool, Error> {
+    let link: KVChain = kv_chains::table.find(entry.kv_chain_id).first(conn)?;
+    if !archivable(conn, entry, &link)? {
+        return Ok(false);
+    }
+    let previous_arweave_id = link.find_previous_arweave(conn)?;
+    let document = KVChainArweaveDocument::from_link(&link, previous_arweave_id);
+    match sink.put(&document).await {
+        Ok(content_id) => {
+            conn.transaction::<_, Error, _>(|conn| {
+                link.insert_archive_id(conn, sink.name(), &content_id)?;
+                entry.mark_done(conn)
+            })?;
+            Ok(true)
+        }
+        Err(err) => {
+            warn!("{} upload of link {} failed: {}", sink.name(), link.uuid, err);
+            entry.mark_failed(conn, &err.to_string(), config)?;
+            Ok(false)
+        }
+    }
+}
+
+/// Check `archive::ensure_public` of `link`.  A refused one is skipped
+/// for good (see `OUTBOX_SKIPPED`), so it is never archived in clear.
+fn archivable(conn: &mut PgConnection, entry: &ArweaveOutbox, link: &KVChain) -> Result<bool, Error> {
+    match archive::ensure_public(conn, link) {
+        Ok(()) => Ok(true),
+        Err(err) => {
+            warn!("Link {} is not archived: {}", link.uuid, err);
+            entry.mark_skipped(conn, &err.to_string())?;
+            Ok(false)
+        }
+    }
+}
+
+/// Keep `previous_arweave_id` chain valid: `previous` (see
+/// `KVChain::find_chained_previous`) is still waiting for upload, or
+/// for re-upload after its transaction is dropped.
+fn previous_pending(conn: &mut PgConnection, previous: Option<&KVChain>) -> Result<bool, Error> {
+    match previous {
+        Some(previous) => Ok(ArweaveOutbox::find_by_kv_chain(conn, previous.id)?
+            .is_some_and(|entry| entry.done_at.is_none())),
+        None => Ok(false),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use diesel::insert_into;
+    use fake::{Fake, Faker};
+    use http::StatusCode;
+    use serde_json::json;
+
+    use super::*;
+    use crate::{
+        crypto::secp256k1::Secp256k1KeyPair,
+        model::{
+            arweave::bundle::tests::FakeSigner, arweave_outbox::{OUTBOX_DEAD, OUTBOX_SKIPPED}, kv,
+            kv_chains::NewKVChain, visibility,
+        },
+        http_client::tests::{config as client_config, mock_server},
+    };
+
+    fn create_link(conn: &mut PgConnection, persona: &Secp256k1KeyPair, previous: Option<i32>) -> KVChain {
+        create_link_patching(conn, persona, previous, json!({ "test": "abc" }))
+    }
+
+    fn create_link_patching(
+        conn: &mut PgConnection,
+        persona: &Secp256k1KeyPair,
+        previous: Option<i32>,
+        patch: serde_json::Value,
+    ) -> KVChain {
+        insert_into(kv_chains::table)
+            .values(&NewKVChain {
+                uuid: ::uuid::Uuid::new_v4(),
+                persona: persona.public_key.serialize().to_vec(),
+                platform: "twitter".into(),
+                identity: Faker.fake(),
+                patch,
+                previous_id: previous,
+                signature: vec![1],
+                signature_payload: Faker.fake(),
+                created_at: naive_now(),
+                action: "kv".into(),
+            })
+            .get_result(conn)
+            .unwrap()
+    }
+
+    fn reload(conn: &mut PgConnection, link: &KVChain) -> KVChain {
+        kv_chains::table.find(link.id).first(conn).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_upload_in_chain_order() {
+        let mut conn = establish_connection();
+        let config = ConfigArweaveOutbox::default();
+        let persona = Secp256k1KeyPair::generate();
+        let first = create_link(&mut conn, &persona, None);
+        let second = create_link(&mut conn, &persona, Some(first.id));
+        let first_entry = ArweaveOutbox::enqueue(&mut conn, &first).unwrap();
+        let second_entry = ArweaveOutbox::enqueue(&mut conn, &second).unwrap();
+
+        // Previous link is still pending.
+        let uploaded = process(&mut conn, &second_entry, &config, |_| async {
+            Ok("never".to_string())
+        })
+        .await
+        .unwrap();
+        assert!(!uploaded);
+        assert_eq!(None, reload(&mut conn, &second).arweave_id());
+
+        let uploaded = process(&mut conn, &first_entry, &config, |document| async move {
+            assert_eq!(None, document.previous_arweave_id);
+            Ok(format!("tx-{}", document.uuid))
+        })
+        .await
+        .unwrap();
+        assert!(uploaded);
+        let first_tx = format!("tx-{}", first.uuid);
+        assert_eq!(Some(first_tx.clone()), reload(&mut conn, &first).arweave_id());
+        let (kv_record, _) = kv::find_or_create(&mut conn, "twitter", &first.identity, &persona.public_key).unwrap();
+        assert_eq!(Some(first_tx.clone()), kv_record.arweave_id);
+
+        let expected_previous = first_tx.clone();
+        let uploaded = process(&mut conn, &second_entry, &config, |document| async move {
+            assert_eq!(Some(expected_previous), document.previous_arweave_id);
+            Ok(format!("tx-{}", document.uuid))
+        })
+        .await
+        .unwrap();
+        assert!(uploaded);
+        assert!(ArweaveOutbox::find_by_kv_chain(&mut conn, second.id).unwrap().unwrap().done_at.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_failed_upload_is_retried() {
+        let mut conn = establish_connection();
+        let config = ConfigArweaveOutbox::default();
+        let persona = Secp256k1KeyPair::generate();
+        let link = create_link(&mut conn, &persona, None);
+        let entry = ArweaveOutbox::enqueue(&mut conn, &link).unwrap();
+
+        let uploaded = process(&mut conn, &entry, &config, |_| async {
+            Err(Error::General("gateway down".into(), StatusCode::BAD_GATEWAY))
+        })
+        .await
+        .unwrap();
+        assert!(!uploaded);
+        let failed = ArweaveOutbox::find_by_kv_chain(&mut conn, link.id).unwrap().unwrap();
+        assert_eq!(1, failed.attempts);
+        assert_eq!(Some("gateway down".to_string()), failed.last_error);
+        assert!(failed.done_at.is_none());
+        assert_eq!(None, reload(&mut conn, &link).arweave_id());
+
+        let uploaded = process(&mut conn, &failed, &config, |_| async { Ok("tx".to_string()) })
+            .await
+            .unwrap();
+        assert!(uploaded);
+        assert_eq!(Some("tx".to_string()), reload(&mut conn, &link).arweave_id());
+    }
+
+    #[tokio::test]
+    async fn test_dead_link_is_stepped_over() {
+        let mut conn = establish_connection();
+        let config = ConfigArweaveOutbox {
+            max_attempts: 1,
+            ..Default::default()
+        };
+        let persona = Secp256k1KeyPair::generate();
+        let first = create_link(&mut conn, &persona, None);
+        let second = create_link(&mut conn, &persona, Some(first.id));
+        let first_entry = ArweaveOutbox::enqueue(&mut conn, &first).unwrap();
+        let second_entry = ArweaveOutbox::enqueue(&mut conn, &second).unwrap();
+
+        assert!(!process(&mut conn, &first_entry, &config, |_| async {
+            Err(Error::General("gateway down".into(), StatusCode::BAD_GATEWAY))
+        })
+        .await
+        .unwrap());
+        let dead = ArweaveOutbox::find_by_kv_chain(&mut conn, first.id).unwrap().unwrap();
+        assert_eq!(OUTBOX_DEAD, dead.state);
+
+        assert!(process(&mut conn, &second_entry, &config, |document| async move {
+            assert_eq!(None, document.previous_arweave_id);
+            Ok("tx-second".to_string())
+        })
+        .await
+        .unwrap());
+        assert_eq!(Some("tx-second".to_string()), reload(&mut conn, &second).arweave_id());
+    }
+
+    #[tokio::test]
+    async fn test_private_namespace_is_not_uploaded() {
+        let mut conn = establish_connection();
+        let config = ConfigArweaveOutbox::default();
+        let persona = Secp256k1KeyPair::generate();
+        visibility::find_or_create(&mut conn, &persona.public_key)
+            .unwrap()
+            .patch(&mut conn, &json!({"test": {"visibility": "private"}}))
+            .unwrap();
+        let link = create_link(&mut conn, &persona, None);
+        let entry = ArweaveOutbox::enqueue(&mut conn, &link).unwrap();
+
+        let uploaded = process(&mut conn, &entry, &config, |_| async { Ok("tx".to_string()) })
+            .await
+            .unwrap();
+        assert!(!uploaded);
+        let skipped = ArweaveOutbox::find_by_kv_chain(&mut conn, link.id).unwrap().unwrap();
+        assert_eq!(OUTBOX_SKIPPED, skipped.state);
+        assert_eq!(0, skipped.attempts);
+        assert!(skipped.done_at.is_some());
+        assert!(skipped.last_error.unwrap().contains("private"));
+        assert_eq!(None, reload(&mut conn, &link).arweave_id());
+    }
+
+    /// Public link, then one refused by `archive::ensure_public`, then
+    /// a public one again.
+    fn create_chain_with_private(conn: &mut PgConnection) -> (KVChain, KVChain, KVChain) {
+        let persona = Secp256k1KeyPair::generate();
+        visibility::find_or_create(conn, &persona.public_key)
+            .unwrap()
+            .patch(conn, &json!({"secret": {"visibility": "private"}}))
+            .unwrap();
+        let first = create_link(conn, &persona, None);
+        let private = create_link_patching(conn, &persona, Some(first.id), json!({"secret": "abc"}));
+        let last = create_link(conn, &persona, Some(private.id));
+        (first, private, last)
+    }
+
+    #[tokio::test]
+    async fn test_refused_link_is_stepped_over() {
+        let mut conn = establish_connection();
+        let config = ConfigArweaveOutbox::default();
+        let (first, private, last) = create_chain_with_private(&mut conn);
+        let entries: Vec<ArweaveOutbox> = [&first, &private, &last]
+            .iter()
+            .map(|link| ArweaveOutbox::enqueue(&mut conn, link).unwrap())
+            .collect();
+
+        assert!(process(&mut conn, &entries[0], &config, |_| async { Ok("tx-first".to_string()) })
+            .await
+            .unwrap());
+        assert!(!process(&mut conn, &entries[1], &config, |_| async { Ok("tx-private".to_string()) })
+            .await
+            .unwrap());
+        // Not deferred by the skipped link, and chained to the one before it.
+        assert!(process(&mut conn, &entries[2], &config, |document| async move {
+            assert_eq!(Some("tx-first".to_string()), document.previous_arweave_id);
+            Ok("tx-last".to_string())
+        })
+        .await
+        .unwrap());
+        assert_eq!(None, reload(&mut conn, &private).arweave_id());
+        assert_eq!(Some("tx-last".to_string()), reload(&mut conn, &last).arweave_id());
+        assert_eq!(Some("tx-first".to_string()), reload(&mut conn, &last).find_previous_arweave(&mut conn).unwrap());
+    }
+
+    /// A link uploaded `ago` seconds ago as `transaction_id`.
+    fn create_uploaded_link(conn: &mut PgConnection, transaction_id: &str, ago: i64) -> KVChain {
+        let persona = Secp256k1KeyPair::generate();
+        let link = create_link(conn, &persona, None);
+        ArweaveOutbox::enqueue(conn, &link).unwrap().mark_done(conn).unwrap();
+        link.insert_arweave_id(conn, Some(transaction_id.into())).unwrap();
+        diesel::update(&link)
+            .set(kv_chains::arweave_submitted_at.eq(Some(naive_now() - chrono::Duration::seconds(ago))))
+            .execute(conn)
+            .unwrap();
+        reload(conn, &link)
+    }
+
+    #[tokio::test]
+    async fn test_track_confirmed() {
+        let mut conn = establish_connection();
+        let config = ConfigArweaveTracker::default();
+        let (gateway, _hits) = mock_server(vec![
+            (202, "Pending", 0),
+            (200, r#"{"block_height": 1234567, "block_indep_hash": "abc", "number_of_confirmations": 3}"#, 0),
+        ])
+        .await;
+        let client = HttpClient::new(UPSTREAM_ARWEAVE, client_config());
+        let link = create_uploaded_link(&mut conn, "tx-confirmed", 0);
+        assert_eq!(Some(ARWEAVE_PENDING.to_string()), link.arweave_status);
+
+        assert_eq!(ARWEAVE_PENDING, track(&mut conn, &client, &gateway, &link, &config).await.unwrap());
+        let checked = reload(&mut conn, &link);
+        assert!(checked.arweave_checked_at.is_some());
+
+        assert_eq!(ARWEAVE_CONFIRMED, track(&mut conn, &client, &gateway, &link, &config).await.unwrap());
+        let confirmed = reload(&mut conn, &link);
+        assert_eq!(Some(ARWEAVE_CONFIRMED.to_string()), confirmed.arweave_status);
+        assert_eq!(Some(1234567), confirmed.arweave_block_height);
+        let (kv_record, _) = kv::find_or_create(
+            &mut conn,
+            "twitter",
+            &link.identity,
+            &Secp256k1KeyPair::from_pubkey_vec(&link.persona).unwrap().public_key,
+        )
+        .unwrap();
+        assert_eq!(Some(ARWEAVE_CONFIRMED.to_string()), kv_record.arweave_status);
+        assert_eq!(Some(1234567), kv_record.arweave_block_height);
+    }
+
+    #[tokio::test]
+    async fn test_track_dropped_and_reupload() {
+        let mut conn = establish_connection();
+        let config = ConfigArweaveTracker::default();
+        let (gateway, _hits) = mock_server(vec![(404, "Not Found", 0)]).await;
+        let client = HttpClient::new(UPSTREAM_ARWEAVE, client_config());
+
+        // Not propagated yet.
+        let fresh = create_uploaded_link(&mut conn, "tx-fresh", 10);
+        assert_eq!(ARWEAVE_PENDING, track(&mut conn, &client, &gateway, &fresh, &config).await.unwrap());
+
+        let stale = create_uploaded_link(&mut conn, "tx-stale", config.drop_after + 10);
+        assert_eq!(ARWEAVE_DROPPED, track(&mut conn, &client, &gateway, &stale, &config).await.unwrap());
+        assert_eq!(Some(ARWEAVE_DROPPED.to_string()), reload(&mut conn, &stale).arweave_status);
+        let entry = ArweaveOutbox::find_by_kv_chain(&mut conn, stale.id).unwrap().unwrap();
+        assert!(entry.done_at.is_none());
+
+        let uploaded = process(&mut conn, &entry, &ConfigArweaveOutbox::default(), |_| async {
+            Ok("tx-again".to_string())
+        })
+        .await
+        .unwrap();
+        assert!(uploaded);
+        let reuploaded = reload(&mut conn, &stale);
+        assert_eq!(Some("tx-again".to_string()), reuploaded.arweave_id());
+        assert_eq!(Some(ARWEAVE_PENDING.to_string()), reuploaded.arweave_status);
+    }
+
+    #[tokio::test]
+    async fn test_dropped_previous_reuploads_newer() {
+        let mut conn = establish_connection();
+        let config = ConfigArweaveTracker::default();
+        let outbox_config = ConfigArweaveOutbox::default();
+        let (gateway, _hits) = mock_server(vec![(404, "Not Found", 0)]).await;
+        let client = HttpClient::new(UPSTREAM_ARWEAVE, client_config());
+        let stale = create_uploaded_link(&mut conn, "tx-stale", config.drop_after + 10);
+        let persona = Secp256k1KeyPair::from_pubkey_vec(&stale.persona).unwrap();
+        let newer = create_link(&mut conn, &persona, Some(stale.id));
+        let newer_entry = ArweaveOutbox::enqueue(&mut conn, &newer).unwrap();
+        assert!(process(&mut conn, &newer_entry, &outbox_config, |document| async move {
+            assert_eq!(Some("tx-stale".to_string()), document.previous_arweave_id);
+            Ok("tx-newer".to_string())
+        })
+        .await
+        .unwrap());
+
+        assert_eq!(ARWEAVE_DROPPED, track(&mut conn, &client, &gateway, &stale, &config).await.unwrap());
+        let newer_entry = ArweaveOutbox::find_by_kv_chain(&mut conn, newer.id).unwrap().unwrap();
+        assert!(newer_entry.done_at.is_none());
+
+        // Waits for its previous link to be uploaded again.
+        let uploaded = process(&mut conn, &newer_entry, &outbox_config, |_| async {
+            Ok("never".to_string())
+        })
+        .await
+        .unwrap();
+        assert!(!uploaded);
+
+        let stale_entry = ArweaveOutbox::find_by_kv_chain(&mut conn, stale.id).unwrap().unwrap();
+        assert!(process(&mut conn, &stale_entry, &outbox_config, |_| async {
+            Ok("tx-again".to_string())
+        })
+        .await
+        .unwrap());
+        assert!(process(&mut conn, &newer_entry, &outbox_config, |document| async move {
+            assert_eq!(Some("tx-again".to_string()), document.previous_arweave_id);
+            Ok("tx-newer-again".to_string())
+        })
+        .await
+        .unwrap());
+        assert_eq!(Some("tx-newer-again".to_string()), reload(&mut conn, &newer).arweave_id());
+    }
+
+    /// Sink only handing out content ids (`{name}-{uuid}`); nothing
+    /// can be read back from it.
+    struct MemorySink {
+        fail: bool,
+    }
+
+    #[async_trait::async_trait]
+    impl ArchiveSink for MemorySink {
+        fn name(&self) -> &'static str {
+            crate::model::archive::SINK_IPFS
+        }
+
+        async fn put(&self, document: &KVChainArweaveDocument) -> Result<String, Error> {
+            if self.fail {
+                return Err(Error::General("node down".into(), StatusCode::BAD_GATEWAY));
+            }
+            Ok(format!("{}-{}", self.name(), document.uuid))
+        }
+
+        async fn get(&self, content_id: &str) -> Result<KVChainArweaveDocument, Error> {
+            Err(Error::General(
+                format!("{} is write-only: {}", self.name(), content_id),
+                StatusCode::NOT_FOUND,
+            ))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_mirror() {
+        let mut conn = establish_connection();
+        let config = ConfigArweaveOutbox::default();
+        let persona = Secp256k1KeyPair::generate();
+        let first = create_link(&mut conn, &persona, None);
+        let second = create_link(&mut conn, &persona, Some(first.id));
+        ArweaveOutbox::enqueue_sink(&mut conn, &first, "ipfs").unwrap();
+        let entry = ArweaveOutbox::enqueue_sink(&mut conn, &second, "ipfs").unwrap();
+
+        // Failure is retried later.
+        assert!(!process_mirror(&mut conn, &entry, &MemorySink { fail: true }, &config).await.unwrap());
+        let failed = ArweaveOutbox::find_by_kv_chain_sink(&mut conn, second.id, "ipfs").unwrap().unwrap();
+        assert_eq!(1, failed.attempts);
+
+        // Not waiting for previous link, nor for Arweave.
+        assert!(process_mirror(&mut conn, &failed, &MemorySink { fail: false }, &config).await.unwrap());
+        let second = reload(&mut conn, &second);
+        assert_eq!(Some(format!("ipfs-{}", second.uuid).as_str()), second.archive_id("ipfs"));
+        assert_eq!(None, second.archive_id("arweave"));
+
+        second.insert_arweave_id(&mut conn, Some("tx-second".into())).unwrap();
+        let second = reload(&mut conn, &second);
+        assert_eq!(Some("tx-second"), second.archive_id("arweave"));
+        assert_eq!(Some(format!("ipfs-{}", second.uuid).as_str()), second.archive_id("ipfs"));
+        // Arweave entry is a separate one.
+        assert!(ArweaveOutbox::find_by_kv_chain(&mut conn, second.id).unwrap().is_none());
+    }
+
+    fn bundle_config() -> ConfigArwave {
+        ConfigArwave {
+            jwt: "".into(),
+            url: "".into(),
+            outbox: Default::default(),
+            tracker: Default::default(),
+            tags: Default::default(),
+            bundle: Default::default(),
+            wallet: Default::default(),
+            client: client_config(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_upload_bundle() {
+        let mut conn = establish_connection();
+        let config = bundle_config();
+        let persona = Secp256k1KeyPair::generate();
+        let first = create_link(&mut conn, &persona, None);
+        let second = create_link(&mut conn, &persona, Some(first.id));
+        // Enqueued in reverse order: still bundled in chain order.
+        let entries = vec![
+            ArweaveOutbox::enqueue(&mut conn, &second).unwrap(),
+            ArweaveOutbox::enqueue(&mut conn, &first).unwrap(),
+        ];
+
+        let uploaded = process_bundle(&mut conn, &entries, &config, &FakeSigner, |items| async move {
+            assert_eq!(2, items.len());
+            Ok("bundle-tx".to_string())
+        })
+        .await
+        .unwrap();
+        assert_eq!(2, uploaded);
+
+        let first = reload(&mut conn, &first);
+        let second = reload(&mut conn, &second);
+        assert_eq!(Some("bundle-tx".to_string()), first.arweave_bundle_id);
+        assert_eq!(Some("bundle-tx"), second.arweave_transaction_id());
+        // Chain is linked by data item ids.
+        let second_item = KVChainArweaveDocument::from_link(&second, first.arweave_id())
+            .to_data_item(&FakeSigner, &config.tags)
+            .unwrap();
+        assert_eq!(second.arweave_id(), Some(second_item.id));
+        assert!(ArweaveOutbox::find_by_kv_chain(&mut conn, second.id).unwrap().unwrap().done_at.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_bundle_steps_over_refused_link() {
+        let mut conn = establish_connection();
+        let config = bundle_config();
+        let (first, private, last) = create_chain_with_private(&mut conn);
+        let entries: Vec<ArweaveOutbox> = [&first, &private, &last]
+            .iter()
+            .map(|link| ArweaveOutbox::enqueue(&mut conn, link).unwrap())
+            .collect();
+
+        let uploaded = process_bundle(&mut conn, &entries, &config, &FakeSigner, |items| async move {
+            assert_eq!(2, items.len());
+            Ok("bundle-tx".to_string())
+        })
+        .await
+        .unwrap();
+        assert_eq!(2, uploaded);
+
+        let first = reload(&mut conn, &first);
+        let last = reload(&mut conn, &last);
+        let last_item = KVChainArweaveDocument::from_link(&last, first.arweave_id())
+            .to_data_item(&FakeSigner, &config.tags)
+            .unwrap();
+        assert_eq!(last.arweave_id(), Some(last_item.id));
+        assert_eq!(None, reload(&mut conn, &private).arweave_id());
+    }
+
+    #[tokio::test]
+    async fn test_failed_bundle_is_retried() {
+        let mut conn = establish_connection();
+        let config = bundle_config();
+        let persona = Secp256k1KeyPair::generate();
+        let link = create_link(&mut conn, &persona, None);
+        let entries = vec![ArweaveOutbox::enqueue(&mut conn, &link).unwrap()];
+
+        let uploaded = process_bundle(&mut conn, &entries, &config, &FakeSigner, |_| async {
+            Err(Error::General("gateway down".into(), StatusCode::BAD_GATEWAY))
+        })
+        .await
+        .unwrap();
+        assert_eq!(0, uploaded);
+        let failed = ArweaveOutbox::find_by_kv_chain(&mut conn, link.id).unwrap().unwrap();
+        assert_eq!(1, failed.attempts);
+        assert_eq!(None, reload(&mut conn, &link).arweave_bundle_id);
+    }
+}
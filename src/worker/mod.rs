@@ -0,0 +1,4 @@
+//! Background jobs.  Run inside standalone server, or as separate
+//! binaries under `examples/`.
+
+pub mod arweave;